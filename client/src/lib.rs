@@ -0,0 +1,438 @@
+//! Reference client for contributors to the KZG ceremony.
+//!
+//! Implements the participant side of the sequencer API: retrieving the
+//! sign-in links, waiting in the lobby, computing the contribution and
+//! submitting it in exchange for a signed receipt. Contributions can also be
+//! computed offline from a downloaded contribution file.
+#![warn(clippy::all, clippy::pedantic, clippy::cargo, clippy::nursery)]
+#![cfg_attr(test, allow(clippy::wildcard_imports))]
+#![allow(clippy::cargo_common_metadata)]
+#![allow(clippy::multiple_crate_versions)]
+#![allow(clippy::module_name_repetitions)]
+
+use kzg_ceremony_crypto::{BatchContribution, BinaryError, BinaryFormat, CeremoniesError, Entropy};
+use reqwest::{header, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fs, io, path::Path, time::Duration};
+use thiserror::Error;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{info, instrument};
+use url::Url;
+
+#[cfg(not(feature = "blst"))]
+pub type Engine = kzg_ceremony_crypto::Arkworks;
+#[cfg(feature = "blst")]
+pub type Engine = kzg_ceremony_crypto::Blst;
+
+/// Media type of the binary encoding of contributions.
+pub const BINARY_MEDIA_TYPE: &str = "application/octet-stream";
+
+/// The sequencer's default `--lobby-checkin-frequency`.
+pub const DEFAULT_CHECKIN_FREQUENCY: Duration = Duration::from_secs(30);
+
+/// How often to ask for the result of a submitted contribution.
+pub const JOB_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid url: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("sequencer responded with {0}: {1}")]
+    Sequencer(StatusCode, String),
+    #[error("unexpected response from sequencer: {0}")]
+    UnexpectedResponse(String),
+    #[error("invalid binary contribution: {0}")]
+    Binary(#[from] BinaryError),
+    #[error("invalid json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not compute contribution: {0}")]
+    Contribution(#[from] CeremoniesError),
+    #[error("contribution rejected: {}", .0.message)]
+    Rejected(Rejection),
+}
+
+/// Why the sequencer rejected a contribution.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+pub struct Rejection {
+    /// The failed check, for example `G1PairingFailed`.
+    pub kind:     String,
+    /// The sub-ceremony that failed the check, if it was a single one.
+    pub ceremony: Option<usize>,
+    pub message:  String,
+}
+
+/// Sign-in links returned by `/auth/request_link`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AuthLinks {
+    pub eth_auth_url:    String,
+    pub github_auth_url: String,
+}
+
+/// Result of a lobby check-in.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CheckIn {
+    /// It is our turn, compute this contribution.
+    Contribute(BatchContribution),
+    /// Position in the queue, starting at one for the next contributor.
+    Waiting {
+        position:       usize,
+        estimated_wait: Duration,
+    },
+}
+
+#[derive(Deserialize)]
+struct Queued {
+    position:               usize,
+    estimated_wait_seconds: u64,
+}
+
+/// The signed receipt returned by `/contribute`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ContributeReceipt {
+    pub receipt:   Value,
+    pub signature: String,
+}
+
+#[derive(Deserialize)]
+struct Job {
+    job_id: String,
+}
+
+/// Returned by `/contribute/status/{job_id}`.
+#[derive(Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum JobStatus {
+    Pending,
+    Accepted(ContributeReceipt),
+    Rejected { error: Rejection },
+}
+
+impl ContributeReceipt {
+    /// Writes the receipt as JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be written.
+    pub fn save(&self, path: &Path) -> Result<(), ClientError> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Encoding of a contribution file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileFormat {
+    Json,
+    Binary,
+}
+
+impl FileFormat {
+    /// Detects the format from the binary magic number.
+    #[must_use]
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&BatchContribution::MAGIC) {
+            Self::Binary
+        } else {
+            Self::Json
+        }
+    }
+
+    /// Decodes a contribution.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not a contribution in this format.
+    pub fn decode(self, bytes: &[u8]) -> Result<BatchContribution, ClientError> {
+        Ok(match self {
+            Self::Json => serde_json::from_slice(bytes)?,
+            Self::Binary => BatchContribution::from_binary(bytes)?,
+        })
+    }
+
+    /// Encodes a contribution.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if JSON serialization fails.
+    pub fn encode(self, contribution: &BatchContribution) -> Result<Vec<u8>, ClientError> {
+        Ok(match self {
+            Self::Json => serde_json::to_vec(contribution)?,
+            Self::Binary => contribution.to_binary(),
+        })
+    }
+}
+
+/// Client for a single sequencer.
+#[derive(Clone, Debug)]
+pub struct Client {
+    http:              reqwest::Client,
+    server:            Url,
+    checkin_frequency: Duration,
+}
+
+impl Client {
+    /// Creates a client for the sequencer at `server`. The lobby is polled
+    /// every `checkin_frequency`, which must match the sequencer's
+    /// `--lobby-checkin-frequency`.
+    #[must_use]
+    pub fn new(server: Url, checkin_frequency: Duration) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            server,
+            checkin_frequency,
+        }
+    }
+
+    /// Retrieves the links to sign in with Ethereum or GitHub. Signing in
+    /// redirects to the sequencer's callback, which returns the session id.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    pub async fn auth_links(&self) -> Result<AuthLinks, ClientError> {
+        let response = self
+            .http
+            .get(self.server.join("auth/request_link")?)
+            .send()
+            .await?;
+        Ok(check_status(response).await?.json().await?)
+    }
+
+    /// Checks in with the lobby once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the session is unknown.
+    pub async fn try_contribute(&self, session_id: &str) -> Result<CheckIn, ClientError> {
+        let response = self
+            .http
+            .post(self.server.join("lobby/try_contribute")?)
+            .bearer_auth(session_id)
+            .header(header::ACCEPT, BINARY_MEDIA_TYPE)
+            .send()
+            .await?;
+        let response = check_status(response).await?;
+        let is_binary = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            == Some(BINARY_MEDIA_TYPE);
+        if is_binary {
+            return Ok(CheckIn::Contribute(BatchContribution::from_binary(
+                &response.bytes().await?,
+            )?));
+        }
+        let body = response.json::<Value>().await?;
+        let queued = serde_json::from_value::<Queued>(body.clone())
+            .map_err(|_| ClientError::UnexpectedResponse(body.to_string()))?;
+        Ok(CheckIn::Waiting {
+            position:       queued.position,
+            estimated_wait: Duration::from_secs(queued.estimated_wait_seconds),
+        })
+    }
+
+    /// Checks in with the lobby every `checkin_frequency` until it is our
+    /// turn, and returns the contribution to compute.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails, for example because the session
+    /// expired.
+    #[instrument(level = "info", skip_all)]
+    pub async fn wait_for_turn(&self, session_id: &str) -> Result<BatchContribution, ClientError> {
+        // The sequencer removes participants that check in late, so keep a fixed
+        // schedule instead of sleeping between requests.
+        let mut ticks = interval(self.checkin_frequency);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            match self.try_contribute(session_id).await? {
+                CheckIn::Contribute(contribution) => return Ok(contribution),
+                CheckIn::Waiting {
+                    position,
+                    estimated_wait,
+                } => info!(position, ?estimated_wait, "Waiting in the lobby"),
+            }
+        }
+    }
+
+    /// Submits a computed contribution. The sequencer verifies it in the
+    /// background, so the upload does not wait for the verification, and the
+    /// receipt is polled with [`Self::wait_for_receipt`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the contribution is rejected.
+    pub async fn submit(
+        &self,
+        session_id: &str,
+        contribution: &BatchContribution,
+    ) -> Result<ContributeReceipt, ClientError> {
+        let response = self
+            .http
+            .post(self.server.join("contribute")?)
+            .bearer_auth(session_id)
+            .header(header::CONTENT_TYPE, BINARY_MEDIA_TYPE)
+            .header("Prefer", "respond-async")
+            .body(contribution.to_binary())
+            .send()
+            .await?;
+        let response = check_status(response).await?;
+        if response.status() != StatusCode::ACCEPTED {
+            // The sequencer verified it right away
+            return Ok(response.json().await?);
+        }
+        let job = response.json::<Job>().await?;
+        self.wait_for_receipt(session_id, &job.job_id).await
+    }
+
+    /// Polls the status of a submitted contribution until it is verified.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails or the contribution is rejected.
+    #[instrument(level = "info", skip(self, session_id))]
+    pub async fn wait_for_receipt(
+        &self,
+        session_id: &str,
+        job_id: &str,
+    ) -> Result<ContributeReceipt, ClientError> {
+        let url = self.server.join(&format!("contribute/status/{job_id}"))?;
+        let mut ticks = interval(JOB_POLL_INTERVAL);
+        loop {
+            ticks.tick().await;
+            let response = self
+                .http
+                .get(url.clone())
+                .bearer_auth(session_id)
+                .send()
+                .await?;
+            match check_status(response).await?.json().await? {
+                JobStatus::Pending => info!("Waiting for the contribution to be verified"),
+                JobStatus::Accepted(receipt) => return Ok(receipt),
+                JobStatus::Rejected { error } => return Err(ClientError::Rejected(error)),
+            }
+        }
+    }
+
+    /// Runs the whole flow for a signed-in participant: waits for our turn,
+    /// computes the contribution and submits it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any step fails.
+    #[instrument(level = "info", skip_all)]
+    pub async fn contribute(
+        &self,
+        session_id: &str,
+        entropy: Entropy,
+    ) -> Result<ContributeReceipt, ClientError> {
+        let mut contribution = self.wait_for_turn(session_id).await?;
+        info!("Computing contribution");
+        let contribution = tokio::task::spawn_blocking(move || {
+            contribution.add_entropy::<Engine>(&entropy)?;
+            Ok::<_, ClientError>(contribution)
+        })
+        .await
+        .expect("contribution task panicked")?;
+        info!("Submitting contribution");
+        self.submit(session_id, &contribution).await
+    }
+}
+
+/// Computes a contribution from a downloaded contribution file and writes the
+/// response file, in the same format as the input.
+///
+/// # Errors
+///
+/// Returns an error if a file can not be read or written, or the input is not
+/// a valid contribution.
+#[instrument(level = "info", skip(entropy))]
+pub fn contribute_offline(
+    input: &Path,
+    output: &Path,
+    entropy: &Entropy,
+) -> Result<(), ClientError> {
+    let bytes = fs::read(input)?;
+    let format = FileFormat::detect(&bytes);
+    let mut contribution = format.decode(&bytes)?;
+    contribution.add_entropy::<Engine>(entropy)?;
+    fs::write(output, format.encode(&contribution)?)?;
+    Ok(())
+}
+
+/// Turns an error status into [`ClientError::Sequencer`], with the error
+/// message from the body if there is one.
+async fn check_status(response: Response) -> Result<Response, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await?;
+    let message = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|value| value.get("error")?.as_str().map(str::to_owned))
+        .unwrap_or(body);
+    Err(ClientError::Sequencer(status, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kzg_ceremony_crypto::BatchTranscript;
+    use tempfile::tempdir;
+
+    #[test]
+    fn parses_rejection() {
+        let status = serde_json::from_str::<JobStatus>(
+            r#"{
+                "status": "rejected",
+                "error": {
+                    "kind": "DuplicateG1",
+                    "ceremony": 1,
+                    "message": "contribution invalid: ..."
+                }
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            status,
+            JobStatus::Rejected { error } if error == Rejection {
+                kind:     "DuplicateG1".to_owned(),
+                ceremony: Some(1),
+                message:  "contribution invalid: ...".to_owned(),
+            }
+        ));
+    }
+
+    #[test]
+    fn offline_contribution() {
+        let mut transcript = BatchTranscript::new(&[(4, 2), (8, 2)]);
+        let dir = tempdir().unwrap();
+        for (seed, format) in [(1, FileFormat::Json), (2, FileFormat::Binary)] {
+            let input = dir.path().join("contribution");
+            let output = dir.path().join("response");
+            fs::write(&input, format.encode(&transcript.contribution()).unwrap()).unwrap();
+
+            contribute_offline(&input, &output, &[seed; 32].into()).unwrap();
+
+            let bytes = fs::read(&output).unwrap();
+            assert_eq!(FileFormat::detect(&bytes), format);
+            let response = format.decode(&bytes).unwrap();
+            transcript.verify_add::<Engine>(response).unwrap();
+        }
+        assert_eq!(transcript.transcripts[1].num_contributions(), 2);
+
+        let input = dir.path().join("invalid");
+        fs::write(&input, b"{}").unwrap();
+        assert!(matches!(
+            contribute_offline(&input, &dir.path().join("out"), &[7; 32].into()),
+            Err(ClientError::Json(_))
+        ));
+    }
+}
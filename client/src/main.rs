@@ -0,0 +1,112 @@
+#![warn(clippy::all, clippy::pedantic, clippy::cargo, clippy::nursery)]
+#![allow(clippy::cargo_common_metadata)]
+#![allow(clippy::multiple_crate_versions)]
+
+use clap::{Parser, Subcommand};
+use eyre::{eyre, Result as EyreResult, WrapErr};
+use kzg_ceremony_client::{contribute_offline, Client, DEFAULT_CHECKIN_FREQUENCY};
+use kzg_ceremony_crypto::{Entropy, EntropyMixer, OsRngSource, TextSource};
+use std::{num::ParseIntError, path::PathBuf, str::FromStr, time::Duration};
+use url::Url;
+
+fn duration_from_str(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+struct Options {
+    /// Text to mix into the entropy from the operating system.
+    #[clap(long, env, hide_env_values = true, global = true)]
+    entropy_text: Option<String>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+enum Command {
+    /// Print the links to sign in with Ethereum or GitHub. Signing in returns
+    /// the session id to contribute with.
+    AuthLinks {
+        /// Sequencer url
+        #[clap(long, env, default_value = "http://127.0.0.1:3000/")]
+        server: Url,
+    },
+    /// Wait in the lobby, then compute and submit a contribution.
+    Contribute {
+        /// Sequencer url
+        #[clap(long, env, default_value = "http://127.0.0.1:3000/")]
+        server: Url,
+
+        /// Session id returned by the sign-in callback.
+        #[clap(long, env)]
+        session_id: String,
+
+        /// Seconds between lobby check-ins. Must match the sequencer's
+        /// `--lobby-checkin-frequency`.
+        #[clap(long, env, value_parser = duration_from_str, default_value = "30")]
+        lobby_checkin_frequency: Duration,
+
+        /// File to store the signed receipt in.
+        #[clap(long, env, default_value = "receipt.json")]
+        receipt: PathBuf,
+    },
+    /// Compute a contribution from a downloaded contribution file, in JSON or
+    /// binary format, and write the response file in the same format.
+    Offline {
+        /// Contribution file to read.
+        input: PathBuf,
+
+        /// Response file to write.
+        output: PathBuf,
+    },
+}
+
+fn entropy(text: Option<&str>) -> EyreResult<Entropy> {
+    let mut mixer = EntropyMixer::new();
+    mixer.add(&mut OsRngSource);
+    if let Some(text) = text {
+        mixer.add(&mut TextSource::new(text.to_owned()));
+    }
+    mixer.finalize().ok_or_else(|| eyre!("No entropy sources"))
+}
+
+#[tokio::main]
+async fn main() -> EyreResult<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    let options = Options::parse();
+    match options.command {
+        Command::AuthLinks { server } => {
+            let links = Client::new(server, DEFAULT_CHECKIN_FREQUENCY)
+                .auth_links()
+                .await?;
+            println!("Sign in with Ethereum: {}", links.eth_auth_url);
+            println!("Sign in with GitHub:   {}", links.github_auth_url);
+        }
+        Command::Contribute {
+            server,
+            session_id,
+            lobby_checkin_frequency,
+            receipt,
+        } => {
+            let client = Client::new(server, lobby_checkin_frequency);
+            let signed = client
+                .contribute(&session_id, entropy(options.entropy_text.as_deref())?)
+                .await?;
+            signed
+                .save(&receipt)
+                .wrap_err("Can not write receipt file")?;
+            eprintln!(
+                "Contribution accepted, receipt written to {}",
+                receipt.display()
+            );
+        }
+        Command::Offline { input, output } => {
+            contribute_offline(&input, &output, &entropy(options.entropy_text.as_deref())?)?;
+            eprintln!("Response written to {}", output.display());
+        }
+    }
+    Ok(())
+}
@@ -1,7 +1,7 @@
-use crate::{CeremoniesError, Contribution, Engine, G2};
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use crate::{CeremoniesError, Contribution, Engine, Entropy, G2};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, hash::Hash};
 use tracing::instrument;
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -17,26 +17,75 @@ impl BatchContribution {
     }
 
     #[instrument(level = "info", skip_all, fields(n=self.contributions.len()))]
-    pub fn add_entropy<E: Engine>(&mut self, entropy: [u8; 32]) -> Result<(), CeremoniesError> {
-        let entropies = derive_entropy(entropy, self.contributions.len());
+    pub fn add_entropy<E: Engine>(&mut self, entropy: &Entropy) -> Result<(), CeremoniesError> {
         self.contributions
             .par_iter_mut()
-            .zip(entropies)
             .enumerate()
-            .try_for_each(|(i, (contribution, entropy))| {
+            .try_for_each(|(i, contribution)| {
                 contribution
-                    .add_entropy::<E>(entropy)
+                    .add_entropy::<E>(&entropy.derive(i))
                     .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
             })
     }
 
-    // TODO: Sanity check the batch contribution. Besides checking the individual
-    // contributions, we should also check that there are no repeated values between
-    // contributions. This prevents problems where a participant contributes the
-    // same tau value for each contribution.
+    /// Sanity checks the individual contributions, and checks that there are
+    /// no repeated values between contributions. The latter prevents a
+    /// participant from contributing the same tau to each ceremony.
+    ///
+    /// Contributions without entropy are exempt from the cross checks.
+    #[instrument(level = "info", skip_all, fields(n=self.contributions.len()))]
+    pub fn sanity_check(&self) -> Result<(), CeremoniesError> {
+        self.contributions
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(i, contribution)| {
+                contribution
+                    .sanity_check()
+                    .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
+            })?;
+
+        let with_entropy = || {
+            self.contributions
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.has_entropy())
+        };
+        if let Some((i, j)) = find_shared(with_entropy().map(|(i, c)| (i, [c.pubkey]))) {
+            return Err(CeremoniesError::DuplicatePubKey(i, j));
+        }
+        if let Some((i, j)) =
+            find_shared(with_entropy().map(|(i, c)| (i, c.powers.g1[1..].iter().copied())))
+        {
+            return Err(CeremoniesError::DuplicateG1(i, j));
+        }
+        if let Some((i, j)) = find_shared(
+            with_entropy().map(|(i, c)| (i, c.powers.g2[1..].iter().copied().chain([c.pubkey]))),
+        ) {
+            return Err(CeremoniesError::DuplicateG2(i, j));
+        }
+        Ok(())
+    }
 }
 
-fn derive_entropy(entropy: [u8; 32], size: usize) -> Vec<[u8; 32]> {
-    let mut rng = StdRng::from_seed(entropy);
-    (0..size).map(|_| rng.gen()).collect()
+/// Finds the first pair of groups that have a value in common. Repeated values
+/// within a single group are ignored.
+pub fn find_shared<T, I, J>(groups: I) -> Option<(usize, usize)>
+where
+    T: Hash + Eq,
+    I: IntoIterator<Item = (usize, J)>,
+    J: IntoIterator<Item = T>,
+{
+    let mut seen = HashMap::<T, usize>::new();
+    for (i, group) in groups {
+        for value in group {
+            match seen.get(&value) {
+                Some(&j) if j != i => return Some((j, i)),
+                Some(_) => {}
+                None => {
+                    seen.insert(value, i);
+                }
+            }
+        }
+    }
+    None
 }
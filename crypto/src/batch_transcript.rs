@@ -1,4 +1,7 @@
-use crate::{BatchContribution, CeremoniesError, Engine, Transcript};
+use crate::{
+    batch_contribution::find_shared, BatchContribution, Beacon, CeremoniesError, Engine, Powers,
+    Randomness, Transcript,
+};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
@@ -7,6 +10,14 @@ use tracing::instrument;
 #[serde(deny_unknown_fields)]
 pub struct BatchTranscript {
     pub transcripts: Vec<Transcript>,
+    /// Set when the transcript is finalized. The last contribution to every
+    /// ceremony is then derived from this beacon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beacon:      Option<Beacon>,
+    /// Set when the first contribution to every ceremony was imported from
+    /// the same Phase-1 ceremony with [`Self::from_powers`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub imported:    bool,
 }
 
 impl BatchTranscript {
@@ -19,6 +30,29 @@ impl BatchTranscript {
                 .into_iter()
                 .map(|(num_g1, num_g2)| Transcript::new(*num_g1, *num_g2))
                 .collect(),
+            beacon:      None,
+            imported:    false,
+        }
+    }
+
+    /// Create a transcript continuing from the powers of a Phase-1 ceremony,
+    /// one set of powers per ceremony. The Phase-1 ceremony is recorded as
+    /// the first contribution to every ceremony, see
+    /// [`Transcript::from_powers`].
+    ///
+    /// All powers must come from the same Phase-1 ceremony: its contribution
+    /// is shared between the ceremonies, and is exempt from the check for
+    /// repeated values in [`Self::sanity_check`].
+    ///
+    /// # Panics
+    ///
+    /// Every ceremony must have at least two g1 and two g2 points.
+    #[must_use]
+    pub fn from_powers(powers: Vec<Powers>) -> Self {
+        Self {
+            transcripts: powers.into_iter().map(Transcript::from_powers).collect(),
+            beacon:      None,
+            imported:    true,
         }
     }
 
@@ -35,12 +69,38 @@ impl BatchTranscript {
     }
 
     /// Adds a batch contribution to the transcript. The contribution must be
-    /// valid.
-    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    /// valid and the transcript can not be finalized.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first check the contribution fails.
     pub fn verify_add<E: Engine>(
         &mut self,
         contribution: BatchContribution,
     ) -> Result<(), CeremoniesError> {
+        self.verify_add_with::<E>(contribution, Randomness::Fresh)
+    }
+
+    /// Like [`Self::verify_add`], but uses the given randomness for the
+    /// pairing checks.
+    ///
+    /// Use [`Randomness::fiat_shamir_batch`] to make the verification
+    /// reproducible.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first check the contribution fails. If the batched pairing
+    /// check fails, the failing individual check is reported.
+    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    pub fn verify_add_with<E: Engine>(
+        &mut self,
+        contribution: BatchContribution,
+        randomness: Randomness,
+    ) -> Result<(), CeremoniesError> {
+        if self.beacon.is_some() {
+            return Err(CeremoniesError::Finalized);
+        }
+
         // Verify contribution count
         if self.transcripts.len() != contribution.contributions.len() {
             return Err(CeremoniesError::UnexpectedNumContributions(
@@ -49,17 +109,43 @@ impl BatchTranscript {
             ));
         }
 
-        // Verify contributions in parallel
+        // Verify there are no repeated values between contributions
+        contribution.sanity_check()?;
+
+        // Verify contributions in parallel, except for the pairings
         self.transcripts
-            .par_iter_mut()
+            .par_iter()
             .zip(&contribution.contributions)
             .enumerate()
             .try_for_each(|(i, (transcript, contribution))| {
                 transcript
-                    .verify::<E>(contribution)
+                    .verify_inputs::<E>(contribution)
                     .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
             })?;
 
+        // Verify all pairings at once. If this fails, repeat the checks one by
+        // one to find out which one failed.
+        let batch = self
+            .transcripts
+            .iter()
+            .zip(&contribution.contributions)
+            .map(|(transcript, contribution)| (transcript.powers.g1[1], contribution))
+            .collect::<Vec<_>>();
+        if E::verify_batch(&batch, randomness).is_err() {
+            self.transcripts
+                .par_iter()
+                .zip(&contribution.contributions)
+                .enumerate()
+                .try_for_each(|(i, (transcript, contribution))| {
+                    transcript
+                        .verify_pairings::<E>(
+                            contribution,
+                            randomness.derive(&(i as u64).to_le_bytes()),
+                        )
+                        .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
+                })?;
+        }
+
         // Add contributions
         for (transcript, contribution) in self
             .transcripts
@@ -72,10 +158,371 @@ impl BatchTranscript {
         Ok(())
     }
 
-    // TODO: Sanity check that the batch transcript. Besides checking the individual
-    // transcripts, we should also check that there are no repeated values between
-    // transcripts.
-    //
-    // In addtionn, while not strictly necessary, it we can also assert that each
-    // transcript has the same number of contributions.
+    /// Adds the final contribution, derived from the public randomness beacon.
+    /// No contributions can be added afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremoniesError::Finalized`] if the transcript is already
+    /// finalized, or the first check the beacon contribution fails.
+    #[instrument(level = "info", skip_all, fields(log2_iterations=beacon.log2_iterations))]
+    pub fn finalize<E: Engine>(&mut self, beacon: Beacon) -> Result<(), CeremoniesError> {
+        if self.beacon.is_some() {
+            return Err(CeremoniesError::Finalized);
+        }
+        let mut contribution = self.contribution();
+        contribution.add_entropy::<E>(&beacon.entropy()?)?;
+        self.verify_add::<E>(contribution)?;
+        self.beacon = Some(beacon);
+        Ok(())
+    }
+
+    /// Recomputes the beacon and checks that the last contribution to every
+    /// ceremony has its pubkey. Together with [`Self::verify_full`], this
+    /// shows that the transcript was finalized with the beacon.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremoniesError::MissingBeacon`] if the transcript is not
+    /// finalized, [`CeremoniesError::BeaconTooManyIterations`] if the beacon
+    /// would take too long to recompute, or the first ceremony that does not
+    /// end with the beacon.
+    #[instrument(level = "info", skip_all, fields(n=self.transcripts.len()))]
+    pub fn verify_beacon<E: Engine>(&self) -> Result<(), CeremoniesError> {
+        let beacon = self.beacon.as_ref().ok_or(CeremoniesError::MissingBeacon)?;
+
+        // The pubkey does not depend on the powers, so use the smallest ones.
+        let mut expected = BatchContribution {
+            contributions: self
+                .transcripts
+                .iter()
+                .map(|_| Transcript::new(2, 2).contribution())
+                .collect(),
+        };
+        expected.add_entropy::<E>(&beacon.entropy()?)?;
+        for (i, (transcript, contribution)) in self
+            .transcripts
+            .iter()
+            .zip(&expected.contributions)
+            .enumerate()
+        {
+            if !transcript.has_entropy()
+                || transcript.witness.pubkeys.last() != Some(&contribution.pubkey)
+            {
+                return Err(CeremoniesError::BeaconMismatch(i));
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies all transcripts in full, including their witness chains.
+    ///
+    /// See [`Transcript::verify_full`].
+    #[instrument(level = "info", skip_all, fields(n=self.transcripts.len()))]
+    pub fn verify_full<E: Engine>(&self) -> Result<(), CeremoniesError> {
+        self.transcripts
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(i, transcript)| {
+                transcript
+                    .verify_full::<E>()
+                    .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
+            })
+    }
+
+    /// Sanity checks the individual transcripts, and checks that they have
+    /// the same number of contributions and no repeated values between them.
+    #[instrument(level = "info", skip_all, fields(n=self.transcripts.len()))]
+    pub fn sanity_check(&self) -> Result<(), CeremoniesError> {
+        self.transcripts
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(i, transcript)| {
+                transcript
+                    .sanity_check()
+                    .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
+            })?;
+
+        // All transcripts must have the same number of contributions
+        if let Some(first) = self.transcripts.first() {
+            let expected = first.num_contributions();
+            for (i, transcript) in self.transcripts.iter().enumerate().skip(1) {
+                if transcript.num_contributions() != expected {
+                    return Err(CeremoniesError::InconsistentNumContributions(
+                        expected,
+                        i,
+                        transcript.num_contributions(),
+                    ));
+                }
+            }
+        }
+
+        // An imported contribution must be the same in every ceremony
+        if self.imported {
+            let imported = |t: &Transcript| t.witness.pubkeys.get(1).copied();
+            let expected = self.transcripts.first().and_then(imported);
+            for (i, transcript) in self.transcripts.iter().enumerate() {
+                if expected.is_none() || imported(transcript) != expected {
+                    return Err(CeremoniesError::InconsistentImport(i));
+                }
+            }
+        }
+
+        // No values can be shared between transcripts. The first values are
+        // always the generator and are exempt, as are transcripts without entropy.
+        // An imported first contribution is shared by design, so it is exempt
+        // too, and so are the powers until there is a contribution after it.
+        let first = 1 + usize::from(self.imported);
+        let transcripts = || {
+            self.transcripts
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.num_contributions() >= first)
+        };
+        if let Some((i, j)) =
+            find_shared(transcripts().map(|(i, t)| (i, t.witness.pubkeys[first..].iter().copied())))
+        {
+            return Err(CeremoniesError::DuplicatePubKey(i, j));
+        }
+        if let Some((i, j)) = find_shared(transcripts().map(|(i, t)| {
+            let products = t.witness.products[first..].iter();
+            (i, t.powers.g1[1..].iter().chain(products).copied())
+        })) {
+            return Err(CeremoniesError::DuplicateG1(i, j));
+        }
+        if let Some((i, j)) = find_shared(transcripts().map(|(i, t)| {
+            let pubkeys = t.witness.pubkeys[first..].iter();
+            (i, t.powers.g2[1..].iter().chain(pubkeys).copied())
+        })) {
+            return Err(CeremoniesError::DuplicateG2(i, j));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "arkworks")]
+mod test {
+    use super::*;
+    use crate::{Arkworks, CeremonyError};
+
+    const SIZES: [(usize, usize); 3] = [(4, 2), (8, 3), (4, 2)];
+
+    #[test]
+    fn verify_add() {
+        let mut transcript = BatchTranscript::new(&SIZES);
+        assert_eq!(transcript.sanity_check(), Ok(()));
+        for entropy in [[1; 32], [2; 32]] {
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_entropy::<Arkworks>(&entropy.into())
+                .unwrap();
+            assert_eq!(contribution.sanity_check(), Ok(()));
+            transcript.verify_add::<Arkworks>(contribution).unwrap();
+        }
+        assert_eq!(transcript.sanity_check(), Ok(()));
+        assert_eq!(transcript.verify_full::<Arkworks>(), Ok(()));
+    }
+
+    #[test]
+    fn verify_add_fiat_shamir() {
+        let mut transcript = BatchTranscript::new(&SIZES);
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[1; 32].into())
+            .unwrap();
+        let randomness = Randomness::fiat_shamir_batch(&transcript, &contribution);
+        assert!(matches!(randomness, Randomness::Seeded(_)));
+        transcript
+            .verify_add_with::<Arkworks>(contribution, randomness)
+            .unwrap();
+        assert_eq!(transcript.transcripts[0].num_contributions(), 1);
+    }
+
+    #[test]
+    fn verify_add_reports_failed_pairing() {
+        let mut transcript = BatchTranscript::new(&SIZES);
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[1; 32].into())
+            .unwrap();
+        contribution.contributions[1].powers.g1.swap(2, 3);
+        assert_eq!(
+            transcript.verify_add::<Arkworks>(contribution),
+            Err(CeremoniesError::InvalidCeremony(
+                1,
+                CeremonyError::G1PairingFailed
+            ))
+        );
+    }
+
+    #[test]
+    fn verify_add_rejects_reused_tau() {
+        let mut transcript = BatchTranscript::new(&SIZES);
+        let mut contribution = transcript.contribution();
+        for contribution in &mut contribution.contributions {
+            contribution
+                .add_entropy::<Arkworks>(&[1; 32].into())
+                .unwrap();
+        }
+        assert_eq!(
+            transcript.verify_add::<Arkworks>(contribution),
+            Err(CeremoniesError::DuplicatePubKey(0, 1))
+        );
+        assert_eq!(transcript.transcripts[0].num_contributions(), 0);
+    }
+
+    #[test]
+    fn sanity_check_rejects_repeats() {
+        let mut transcript = BatchTranscript::new(&SIZES);
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[1; 32].into())
+            .unwrap();
+        transcript.verify_add::<Arkworks>(contribution).unwrap();
+
+        let mut repeated = transcript.clone();
+        repeated.transcripts[2] = repeated.transcripts[0].clone();
+        assert_eq!(
+            repeated.sanity_check(),
+            Err(CeremoniesError::DuplicatePubKey(0, 2))
+        );
+
+        let mut repeated = transcript.clone();
+        repeated.transcripts[2].powers.g1[3] = repeated.transcripts[1].powers.g1[2];
+        assert_eq!(
+            repeated.sanity_check(),
+            Err(CeremoniesError::DuplicateG1(1, 2))
+        );
+
+        let mut inconsistent = transcript;
+        inconsistent.transcripts[1] = Transcript::new(8, 3);
+        assert_eq!(
+            inconsistent.sanity_check(),
+            Err(CeremoniesError::InconsistentNumContributions(1, 1, 0))
+        );
+    }
+
+    /// Seeds a transcript with prefixes of the powers of a single tau.
+    fn imported() -> BatchTranscript {
+        let mut phase1 = Transcript::new(8, 3);
+        let mut contribution = phase1.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[9; 32].into())
+            .unwrap();
+        phase1.verify::<Arkworks>(&contribution).unwrap();
+        phase1.add(contribution);
+        BatchTranscript::from_powers(
+            SIZES
+                .iter()
+                .map(|&(num_g1, num_g2)| Powers {
+                    g1: phase1.powers.g1[..num_g1].to_vec(),
+                    g2: phase1.powers.g2[..num_g2].to_vec(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn from_powers() {
+        let mut transcript = imported();
+        assert!(transcript.imported);
+        assert_eq!(transcript.transcripts[1].num_contributions(), 1);
+        assert_eq!(transcript.sanity_check(), Ok(()));
+        assert_eq!(transcript.verify_full::<Arkworks>(), Ok(()));
+
+        // Without the exemption, the shared contribution is a repeat
+        let mut unmarked = transcript.clone();
+        unmarked.imported = false;
+        assert_eq!(
+            unmarked.sanity_check(),
+            Err(CeremoniesError::DuplicatePubKey(0, 1))
+        );
+
+        // The exemption only covers a contribution shared by all ceremonies
+        let mut inconsistent = transcript.clone();
+        inconsistent.transcripts[2].witness.pubkeys[1] = transcript.transcripts[1].powers.g2[2];
+        assert_eq!(
+            inconsistent.sanity_check(),
+            Err(CeremoniesError::InconsistentImport(2))
+        );
+
+        // Contributions after the imported one must not repeat values
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[1; 32].into())
+            .unwrap();
+        transcript.verify_add::<Arkworks>(contribution).unwrap();
+        assert_eq!(transcript.sanity_check(), Ok(()));
+        assert_eq!(transcript.verify_full::<Arkworks>(), Ok(()));
+        let mut repeated = transcript.clone();
+        repeated.transcripts[2].powers.g1[3] = repeated.transcripts[1].powers.g1[2];
+        assert_eq!(
+            repeated.sanity_check(),
+            Err(CeremoniesError::DuplicateG1(1, 2))
+        );
+
+        // The marker survives a JSON round trip
+        let json = serde_json::to_string(&transcript).unwrap();
+        assert!(json.contains(r#""imported":true"#));
+        assert_eq!(serde_json::from_str(&json).ok(), Some(transcript));
+        let json = serde_json::to_string(&BatchTranscript::new(&SIZES)).unwrap();
+        assert!(!json.contains("imported"));
+    }
+
+    #[test]
+    fn finalize_with_beacon() {
+        let beacon = Beacon::new(b"block hash".to_vec(), 4);
+        let mut transcript = BatchTranscript::new(&SIZES);
+        assert_eq!(
+            transcript.verify_beacon::<Arkworks>(),
+            Err(CeremoniesError::MissingBeacon)
+        );
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[1; 32].into())
+            .unwrap();
+        transcript.verify_add::<Arkworks>(contribution).unwrap();
+
+        transcript.finalize::<Arkworks>(beacon.clone()).unwrap();
+        assert_eq!(transcript.transcripts[0].num_contributions(), 2);
+        assert_eq!(transcript.beacon, Some(beacon));
+        assert_eq!(transcript.sanity_check(), Ok(()));
+        assert_eq!(transcript.verify_full::<Arkworks>(), Ok(()));
+        assert_eq!(transcript.verify_beacon::<Arkworks>(), Ok(()));
+
+        // The beacon is recorded in the JSON transcript
+        let json = serde_json::to_string(&transcript).unwrap();
+        let parsed = serde_json::from_str::<BatchTranscript>(&json).unwrap();
+        assert_eq!(parsed.verify_beacon::<Arkworks>(), Ok(()));
+
+        // No contributions after the beacon
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[2; 32].into())
+            .unwrap();
+        assert_eq!(
+            transcript.verify_add::<Arkworks>(contribution),
+            Err(CeremoniesError::Finalized)
+        );
+        assert_eq!(
+            transcript.finalize::<Arkworks>(Beacon::new(vec![], 4)),
+            Err(CeremoniesError::Finalized)
+        );
+
+        // A different beacon value does not verify
+        let mut tampered = transcript;
+        tampered.beacon = Some(Beacon::new(b"other hash".to_vec(), 4));
+        assert_eq!(
+            tampered.verify_beacon::<Arkworks>(),
+            Err(CeremoniesError::BeaconMismatch(0))
+        );
+
+        // Beacons that would take too long to recompute are rejected
+        tampered.beacon = Some(Beacon::new(b"block hash".to_vec(), 64));
+        assert_eq!(
+            tampered.verify_beacon::<Arkworks>(),
+            Err(CeremoniesError::BeaconTooManyIterations(64))
+        );
+    }
 }
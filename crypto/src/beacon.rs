@@ -0,0 +1,124 @@
+//! Public randomness beacon for the final contribution.
+//!
+//! After the last participant, the transcript is finalized with entropy
+//! derived from a value nobody could predict in advance, such as a future
+//! block hash. The value is iterated through `2^log2_iterations` rounds of
+//! SHA-256 like the Zcash ceremony did, so the last participant can not
+//! grind on the outcome before the beacon is known.
+
+use crate::{CeremoniesError, Entropy};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+/// The largest supported `log2_iterations`. Beacons come from untrusted
+/// transcripts, and more rounds would take too long to verify.
+pub const MAX_BEACON_LOG2_ITERATIONS: u32 = 48;
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Beacon {
+    /// The public beacon value.
+    #[serde(serialize_with = "value_to_hex", deserialize_with = "hex_to_value")]
+    pub value:           Vec<u8>,
+    /// Base two logarithm of the number of SHA-256 rounds.
+    #[serde(deserialize_with = "bounded_log2_iterations")]
+    pub log2_iterations: u32,
+}
+
+impl Beacon {
+    #[must_use]
+    pub const fn new(value: Vec<u8>, log2_iterations: u32) -> Self {
+        Self {
+            value,
+            log2_iterations,
+        }
+    }
+
+    /// Derives the entropy: `h = sha256(value)`, then `h = sha256(h)` repeated
+    /// `2^log2_iterations` times.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremoniesError::BeaconTooManyIterations`] if
+    /// `log2_iterations` is more than [`MAX_BEACON_LOG2_ITERATIONS`].
+    pub fn entropy(&self) -> Result<Entropy, CeremoniesError> {
+        self.hash().map(Entropy::new)
+    }
+
+    fn hash(&self) -> Result<[u8; 32], CeremoniesError> {
+        if self.log2_iterations > MAX_BEACON_LOG2_ITERATIONS {
+            return Err(CeremoniesError::BeaconTooManyIterations(
+                self.log2_iterations,
+            ));
+        }
+        let mut hash: [u8; 32] = Sha256::digest(&self.value).into();
+        for _ in 0..1_u64 << self.log2_iterations {
+            hash = Sha256::digest(hash).into();
+        }
+        Ok(hash)
+    }
+}
+
+fn value_to_hex<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(value)))
+}
+
+fn hex_to_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    let value = value
+        .strip_prefix("0x")
+        .ok_or_else(|| de::Error::custom("hex string must start with `0x`"))?;
+    hex::decode(value).map_err(|e| de::Error::custom(format!("hex decoding failed: {e}")))
+}
+
+fn bounded_log2_iterations<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let log2_iterations = u32::deserialize(deserializer)?;
+    if log2_iterations > MAX_BEACON_LOG2_ITERATIONS {
+        return Err(de::Error::custom(format!(
+            "log2Iterations can be at most {MAX_BEACON_LOG2_ITERATIONS}, got {log2_iterations}"
+        )));
+    }
+    Ok(log2_iterations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_serde() {
+        let beacon = Beacon::new(hex!("00ff").to_vec(), 10);
+        let json = serde_json::to_value(&beacon).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "value": "0x00ff", "log2Iterations": 10 })
+        );
+        assert_eq!(serde_json::from_value::<Beacon>(json).unwrap(), beacon);
+        assert!(serde_json::from_str::<Beacon>(r#"{"value":"00ff","log2Iterations":1}"#).is_err());
+        assert!(
+            serde_json::from_str::<Beacon>(r#"{"value":"0x00ff","log2Iterations":49}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_iterations() {
+        let hash = |n| Beacon::new(b"beacon".to_vec(), n).hash().unwrap();
+        let initial: [u8; 32] = Sha256::digest(b"beacon").into();
+        let once: [u8; 32] = Sha256::digest(initial).into();
+        let twice: [u8; 32] = Sha256::digest(once).into();
+        assert_eq!(hash(0), once);
+        assert_eq!(hash(1), twice);
+        assert_ne!(hash(2), twice);
+    }
+
+    #[test]
+    fn test_too_many_iterations() {
+        for log2_iterations in [MAX_BEACON_LOG2_ITERATIONS + 1, 64, u32::MAX] {
+            assert_eq!(
+                Beacon::new(vec![], log2_iterations).entropy().map(|_| ()),
+                Err(CeremoniesError::BeaconTooManyIterations(log2_iterations))
+            );
+        }
+    }
+}
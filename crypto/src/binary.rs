@@ -0,0 +1,372 @@
+//! Compact binary encoding of batch transcripts and contributions.
+//!
+//! The encoding starts with a four byte magic number identifying the type,
+//! followed by a `u16` format version. After that come the ceremonies, each
+//! as a sequence of length-prefixed lists of raw compressed points. All
+//! integers are little-endian and lengths are `u32`.
+//!
+//! Version 2 adds a marker for transcripts that start with an imported
+//! Phase-1 contribution, and the beacon of a finalized transcript at the end,
+//! as the length-prefixed value followed by the `u32` log2 of the iterations.
+//! Values that need nothing from version 2 are still written as version 1, so
+//! readers of version 1 only reject the transcripts they can not read.
+//!
+//! ```text
+//! transcript:   "KZGT" version n (g1* g2* products* pubkeys*){n} "KZGI"? ("KZGB" value* log2_iterations)?
+//!                                                                \___________ version 2 only ___________/
+//! contribution: "KZGC" version n (g1* g2* pubkey){n}
+//! ```
+//!
+//! Points are not validated when decoding, this is left to the [`Engine`].
+//!
+//! [`Engine`]: crate::Engine
+
+use crate::{
+    transcript::Witness, BatchContribution, BatchTranscript, Beacon, BinaryError, Contribution,
+    Powers, Transcript, G1, G2, MAX_BEACON_LOG2_ITERATIONS,
+};
+
+/// Marks a transcript that starts with an imported contribution.
+const IMPORTED_MAGIC: [u8; 4] = *b"KZGI";
+
+/// Marks the optional beacon at the end of a transcript.
+const BEACON_MAGIC: [u8; 4] = *b"KZGB";
+
+/// The current version of the binary format.
+pub const BINARY_VERSION: u16 = 2;
+
+/// Types with a binary encoding.
+pub trait BinaryFormat: Sized {
+    /// Magic number at the start of the encoding.
+    const MAGIC: [u8; 4];
+
+    /// The oldest format version that can encode `self`.
+    fn version(&self) -> u16 {
+        1
+    }
+
+    /// Appends the encoding of the body, without header.
+    fn encode(&self, writer: &mut BinaryWriter);
+
+    /// Decodes the body of the given format version, without header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input ends early or is invalid.
+    fn decode(reader: &mut BinaryReader, version: u16) -> Result<Self, BinaryError>;
+
+    /// Encodes to bytes, including magic number and version.
+    #[must_use]
+    fn to_binary(&self) -> Vec<u8> {
+        let mut writer = BinaryWriter(Vec::new());
+        writer.0.extend_from_slice(&Self::MAGIC);
+        writer.0.extend_from_slice(&self.version().to_le_bytes());
+        self.encode(&mut writer);
+        writer.0
+    }
+
+    /// Decodes from bytes, checking the magic number and version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is wrong or the input is not exactly one
+    /// encoded value.
+    fn from_binary(bytes: &[u8]) -> Result<Self, BinaryError> {
+        let mut reader = BinaryReader(bytes);
+        if reader.take(4)? != Self::MAGIC {
+            return Err(BinaryError::InvalidMagic);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if !(1..=BINARY_VERSION).contains(&version) {
+            return Err(BinaryError::UnsupportedVersion(version));
+        }
+        let value = Self::decode(&mut reader, version)?;
+        if !reader.0.is_empty() {
+            return Err(BinaryError::TrailingBytes(reader.0.len()));
+        }
+        Ok(value)
+    }
+}
+
+/// Output buffer for [`BinaryFormat::encode`].
+pub struct BinaryWriter(Vec<u8>);
+
+impl BinaryWriter {
+    fn len(&mut self, len: usize) {
+        let len = u32::try_from(len).expect("Length exceeds u32");
+        self.0.extend_from_slice(&len.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn g1s(&mut self, points: &[G1]) {
+        self.len(points.len());
+        for point in points {
+            self.0.extend_from_slice(&point.0);
+        }
+    }
+
+    fn g2s(&mut self, points: &[G2]) {
+        self.len(points.len());
+        for point in points {
+            self.0.extend_from_slice(&point.0);
+        }
+    }
+}
+
+/// Input buffer for [`BinaryFormat::decode`].
+pub struct BinaryReader<'a>(&'a [u8]);
+
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BinaryError> {
+        if self.0.len() < n {
+            return Err(BinaryError::UnexpectedEnd);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BinaryError> {
+        let mut result = [0; N];
+        result.copy_from_slice(self.take(N)?);
+        Ok(result)
+    }
+
+    /// Reads a length, checking that at least `len * size` bytes remain so
+    /// that corrupt lengths can not cause large allocations.
+    fn len(&mut self, size: usize) -> Result<usize, BinaryError> {
+        let len = u32::from_le_bytes(self.array()?) as usize;
+        if len.saturating_mul(size) > self.0.len() {
+            return Err(BinaryError::UnexpectedEnd);
+        }
+        Ok(len)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, BinaryError> {
+        let len = self.len(1)?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn g1s(&mut self) -> Result<Vec<G1>, BinaryError> {
+        let len = self.len(48)?;
+        (0..len).map(|_| self.array().map(G1)).collect()
+    }
+
+    fn g2s(&mut self) -> Result<Vec<G2>, BinaryError> {
+        let len = self.len(96)?;
+        (0..len).map(|_| self.array().map(G2)).collect()
+    }
+}
+
+impl BinaryFormat for BatchTranscript {
+    const MAGIC: [u8; 4] = *b"KZGT";
+
+    fn version(&self) -> u16 {
+        if self.imported || self.beacon.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn encode(&self, writer: &mut BinaryWriter) {
+        writer.len(self.transcripts.len());
+        for transcript in &self.transcripts {
+            writer.g1s(&transcript.powers.g1);
+            writer.g2s(&transcript.powers.g2);
+            writer.g1s(&transcript.witness.products);
+            writer.g2s(&transcript.witness.pubkeys);
+        }
+        if self.imported {
+            writer.0.extend_from_slice(&IMPORTED_MAGIC);
+        }
+        if let Some(beacon) = &self.beacon {
+            writer.0.extend_from_slice(&BEACON_MAGIC);
+            writer.bytes(&beacon.value);
+            writer
+                .0
+                .extend_from_slice(&beacon.log2_iterations.to_le_bytes());
+        }
+    }
+
+    fn decode(reader: &mut BinaryReader, version: u16) -> Result<Self, BinaryError> {
+        let len = reader.len(16)?;
+        let transcripts = (0..len)
+            .map(|_| {
+                Ok(Transcript {
+                    powers:  Powers {
+                        g1: reader.g1s()?,
+                        g2: reader.g2s()?,
+                    },
+                    witness: Witness {
+                        products: reader.g1s()?,
+                        pubkeys:  reader.g2s()?,
+                    },
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let imported = version >= 2 && reader.0.starts_with(&IMPORTED_MAGIC);
+        if imported {
+            reader.take(IMPORTED_MAGIC.len())?;
+        }
+        let beacon = if version >= 2 && reader.0.starts_with(&BEACON_MAGIC) {
+            reader.take(BEACON_MAGIC.len())?;
+            let value = reader.bytes()?;
+            let log2_iterations = u32::from_le_bytes(reader.array()?);
+            if log2_iterations > MAX_BEACON_LOG2_ITERATIONS {
+                return Err(BinaryError::BeaconTooManyIterations(log2_iterations));
+            }
+            Some(Beacon::new(value, log2_iterations))
+        } else {
+            None
+        };
+        Ok(Self {
+            transcripts,
+            beacon,
+            imported,
+        })
+    }
+}
+
+impl BinaryFormat for BatchContribution {
+    const MAGIC: [u8; 4] = *b"KZGC";
+
+    fn encode(&self, writer: &mut BinaryWriter) {
+        writer.len(self.contributions.len());
+        for contribution in &self.contributions {
+            writer.g1s(&contribution.powers.g1);
+            writer.g2s(&contribution.powers.g2);
+            writer.0.extend_from_slice(&contribution.pubkey.0);
+        }
+    }
+
+    fn decode(reader: &mut BinaryReader, _version: u16) -> Result<Self, BinaryError> {
+        let len = reader.len(104)?;
+        let contributions = (0..len)
+            .map(|_| {
+                Ok(Contribution {
+                    powers: Powers {
+                        g1: reader.g1s()?,
+                        g2: reader.g2s()?,
+                    },
+                    pubkey: G2(reader.array()?),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { contributions })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn transcript() -> BatchTranscript {
+        let mut transcript = BatchTranscript::new(&[(4, 2), (8, 3)]);
+        transcript.transcripts[1].witness.products.push(G1::zero());
+        transcript.transcripts[1].witness.pubkeys.push(G2::zero());
+        transcript
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let transcript = transcript();
+        let bytes = transcript.to_binary();
+        assert_eq!(&bytes[..6], b"KZGT\x01\x00");
+        assert_eq!(BatchTranscript::from_binary(&bytes), Ok(transcript.clone()));
+        assert!(bytes.len() < serde_json::to_vec(&transcript).unwrap().len() / 2);
+
+        let contribution = transcript.contribution();
+        let bytes = contribution.to_binary();
+        assert_eq!(&bytes[..6], b"KZGC\x01\x00");
+        assert_eq!(BatchContribution::from_binary(&bytes), Ok(contribution));
+
+        let bytes = transcript.to_binary();
+        let mut finalized = transcript;
+        finalized.beacon = Some(Beacon::new(vec![1, 2, 3], 42));
+        let finalized_bytes = finalized.to_binary();
+        assert_eq!(&finalized_bytes[..6], b"KZGT\x02\x00");
+        assert_eq!(&finalized_bytes[6..bytes.len()], &bytes[6..]);
+        assert_eq!(
+            BatchTranscript::from_binary(&finalized_bytes),
+            Ok(finalized.clone())
+        );
+
+        let mut imported = finalized;
+        imported.imported = true;
+        let imported_bytes = imported.to_binary();
+        assert_eq!(&imported_bytes[..6], b"KZGT\x02\x00");
+        assert_eq!(
+            BatchTranscript::from_binary(&imported_bytes),
+            Ok(imported.clone())
+        );
+        imported.beacon = None;
+        assert_eq!(
+            BatchTranscript::from_binary(&imported.to_binary()),
+            Ok(imported)
+        );
+    }
+
+    #[test]
+    fn test_invalid_beacon() {
+        let mut finalized = transcript();
+        finalized.beacon = Some(Beacon::new(vec![1, 2, 3], 42));
+        let bytes = finalized.to_binary();
+
+        // The beacon is not part of version 1
+        let mut version_1 = bytes.clone();
+        version_1[4] = 1;
+        let trailer = bytes.len() - transcript().to_binary().len();
+        assert_eq!(
+            BatchTranscript::from_binary(&version_1),
+            Err(BinaryError::TrailingBytes(trailer))
+        );
+
+        let mut too_many_iterations = bytes;
+        let len = too_many_iterations.len();
+        too_many_iterations[len - 4..].copy_from_slice(&64_u32.to_le_bytes());
+        assert_eq!(
+            BatchTranscript::from_binary(&too_many_iterations),
+            Err(BinaryError::BeaconTooManyIterations(64))
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = transcript().to_binary();
+        assert_eq!(
+            BatchContribution::from_binary(&bytes),
+            Err(BinaryError::InvalidMagic)
+        );
+        for version in [0, BINARY_VERSION + 1] {
+            let mut wrong_version = bytes.clone();
+            wrong_version[4..6].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(
+                BatchTranscript::from_binary(&wrong_version),
+                Err(BinaryError::UnsupportedVersion(version))
+            );
+        }
+        for len in [0, 5, 6, 10, bytes.len() - 1] {
+            assert_eq!(
+                BatchTranscript::from_binary(&bytes[..len]),
+                Err(BinaryError::UnexpectedEnd)
+            );
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            BatchTranscript::from_binary(&trailing),
+            Err(BinaryError::TrailingBytes(1))
+        );
+        let mut huge = bytes;
+        huge[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            BatchTranscript::from_binary(&huge),
+            Err(BinaryError::UnexpectedEnd)
+        );
+    }
+}
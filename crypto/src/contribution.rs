@@ -1,4 +1,4 @@
-use crate::{CeremonyError, Engine, Powers, G1, G2};
+use crate::{CeremonyError, Engine, Entropy, Powers, G1, G2};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tracing::instrument;
@@ -20,7 +20,7 @@ impl Contribution {
 
     /// Adds entropy to this contribution. Can be called multiple times.
     #[instrument(level = "info", skip_all, , fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
-    pub fn add_entropy<E: Engine>(&mut self, entropy: [u8; 32]) -> Result<(), CeremonyError> {
+    pub fn add_entropy<E: Engine>(&mut self, entropy: &Entropy) -> Result<(), CeremonyError> {
         // Basic checks
         self.sanity_check()?;
 
@@ -155,7 +155,7 @@ mod test {
 #[cfg(feature = "bench")]
 #[doc(hidden)]
 pub mod bench {
-    use crate::{Arkworks, Transcript};
+    use crate::{Arkworks, Entropy, Transcript};
     use criterion::{BatchSize, Criterion};
     use rand::Rng;
 
@@ -170,7 +170,9 @@ pub mod bench {
             b.iter_batched_ref(
                 || {
                     let mut contribution = transcript.contribution();
-                    contribution.add_entropy::<Arkworks>(rng.gen()).unwrap();
+                    contribution
+                        .add_entropy::<Arkworks>(&Entropy::new(rng.gen()))
+                        .unwrap();
                     contribution
                 },
                 |contribution| contribution.sanity_check().unwrap(),
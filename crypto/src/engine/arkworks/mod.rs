@@ -7,16 +7,18 @@ mod zcash_format;
 
 use self::endomorphism::{g1_mul_glv, g1_subgroup_check, g2_subgroup_check};
 use super::Engine;
-use crate::{CeremonyError, ParseError, G1, G2};
+use crate::{
+    CeremonyError, Contribution, Entropy, KzgOpening, ParseError, Randomness, Scalar, G1, G2,
+};
 use ark_bls12_381::{Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
 use ark_ec::{
     msm::VariableBaseMSM, wnaf::WnafContext, AffineCurve, PairingEngine, ProjectiveCurve,
 };
 use ark_ff::{One, PrimeField, UniformRand, Zero};
-use rand::{rngs::StdRng, SeedableRng};
 use rayon::prelude::*;
 use std::iter;
 use tracing::instrument;
+use zeroize::Zeroizing;
 
 /// Arkworks implementation of [`Engine`] with additional endomorphism
 /// optimizations.
@@ -65,7 +67,7 @@ impl Engine for Arkworks {
     }
 
     #[instrument(level = "info", skip_all, fields(n=powers.len()))]
-    fn verify_g1(powers: &[G1], tau: G2) -> Result<(), CeremonyError> {
+    fn verify_g1(powers: &[G1], tau: G2, randomness: Randomness) -> Result<(), CeremonyError> {
         // Parse ZCash format
         let powers = powers
             .into_par_iter()
@@ -74,7 +76,7 @@ impl Engine for Arkworks {
         let tau = G2Affine::try_from(tau)?;
 
         // Compute random linear combination
-        let (factors, sum) = random_factors(powers.len() - 1);
+        let (factors, sum) = random_factors(randomness, b"verify_g1", powers.len() - 1);
         let lhs_g1 = VariableBaseMSM::multi_scalar_mul(&powers[1..], &factors[..]);
         let lhs_g2 = G2Affine::prime_subgroup_generator().mul(sum);
         let rhs_g1 = VariableBaseMSM::multi_scalar_mul(&powers[..factors.len()], &factors[..]);
@@ -88,7 +90,7 @@ impl Engine for Arkworks {
     }
 
     #[instrument(level = "info", skip_all, fields(n1=g1.len(), n2=g2.len()))]
-    fn verify_g2(g1: &[G1], g2: &[G2]) -> Result<(), CeremonyError> {
+    fn verify_g2(g1: &[G1], g2: &[G2], randomness: Randomness) -> Result<(), CeremonyError> {
         assert!(g1.len() == g2.len());
 
         // Parse ZCash format
@@ -102,7 +104,7 @@ impl Engine for Arkworks {
             .collect::<Result<Vec<_>, _>>()?;
 
         // Compute random linear combination
-        let (factors, sum) = random_factors(g2.len());
+        let (factors, sum) = random_factors(randomness, b"verify_g2", g2.len());
         let lhs_g1 = VariableBaseMSM::multi_scalar_mul(&g1, &factors[..]);
         let lhs_g2 = G2Affine::prime_subgroup_generator().mul(sum);
         let rhs_g1 = G1Affine::prime_subgroup_generator().mul(sum);
@@ -115,16 +117,87 @@ impl Engine for Arkworks {
         Ok(())
     }
 
-    #[instrument(level = "info", skip_all, fields(n=powers.len()))]
-    fn add_entropy_g1(entropy: [u8; 32], powers: &mut [G1]) -> Result<(), CeremonyError> {
-        let tau = random_scalar(entropy);
-        let taus = iter::successors(Some(Fr::one()), |x| Some(*x * tau))
-            .take(powers.len())
+    #[instrument(level = "info", skip_all, fields(n=contributions.len()))]
+    fn verify_batch(
+        contributions: &[(G1, &Contribution)],
+        randomness: Randomness,
+    ) -> Result<(), CeremonyError> {
+        // Combine the checks of each contribution into
+        //   e(a, G2) · e(-s · previous, pubkey) · e(-b, g2[1]) · e(-G1, c) = 1
+        // where a and c accumulate over all contributions.
+        let terms = contributions
+            .par_iter()
+            .enumerate()
+            .map(|(i, (previous, contribution))| {
+                // Parse ZCash format
+                let g1 = contribution
+                    .powers
+                    .g1
+                    .par_iter()
+                    .map(|p| G1Affine::try_from(*p))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let g2 = contribution
+                    .powers
+                    .g2
+                    .par_iter()
+                    .map(|p| G2Affine::try_from(*p))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let previous = G1Affine::try_from(*previous)?;
+                let pubkey = G2Affine::try_from(contribution.pubkey)?;
+
+                // Random factors for the pubkey, G1 and G2 checks
+                let randomness = randomness.derive(&(i as u64).to_le_bytes());
+                let s = scalar_factors(randomness, b"verify_pubkey", 1)[0];
+                let r = scalar_factors(randomness, b"verify_g1", g1.len() - 1);
+                let t = scalar_factors(randomness, b"verify_g2", g2.len());
+
+                // Coefficients of the powers in the generator pairing
+                let mut a = vec![Fr::zero(); g1.len()];
+                for (a, r) in a[1..].iter_mut().zip(&r) {
+                    *a += r;
+                }
+                for (a, t) in a.iter_mut().zip(&t) {
+                    *a += t;
+                }
+                a[1] += s;
+
+                let a = VariableBaseMSM::multi_scalar_mul(&g1, &to_repr(&a));
+                let b = VariableBaseMSM::multi_scalar_mul(&g1[..r.len()], &to_repr(&r));
+                let c = VariableBaseMSM::multi_scalar_mul(&g2, &to_repr(&t));
+                let pairs = [(-previous.mul(s), pubkey.into()), (-b, g2[1].into())];
+                Ok((a, pairs, c))
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?;
+
+        let mut a = G1Projective::zero();
+        let mut c = G2Projective::zero();
+        let mut pairs = Vec::with_capacity(2 * terms.len() + 2);
+        for (term_a, term_pairs, term_c) in terms {
+            a += term_a;
+            c += term_c;
+            pairs.extend(term_pairs);
+        }
+        pairs.push((a, G2Affine::prime_subgroup_generator().into_projective()));
+        pairs.push((-G1Affine::prime_subgroup_generator().into_projective(), c));
+        let prepared = pairs
+            .into_iter()
+            .map(|(p, q)| (p.into_affine().into(), q.into_affine().into()))
             .collect::<Vec<_>>();
+
+        // Check pairing
+        if !Bls12_381::product_of_pairings(&prepared).is_one() {
+            return Err(CeremonyError::BatchPairingFailed);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=powers.len()))]
+    fn add_entropy_g1(entropy: &Entropy, powers: &mut [G1]) -> Result<(), CeremonyError> {
+        let taus = powers_of_tau(entropy, powers.len());
         let mut projective = powers
             .par_iter()
-            .zip(taus)
-            .map(|(p, tau)| G1Affine::try_from(*p).map(|p| g1_mul_glv(&p, tau)))
+            .zip(taus.par_iter())
+            .map(|(p, tau)| G1Affine::try_from(*p).map(|p| g1_mul_glv(&p, *tau)))
             .collect::<Result<Vec<_>, _>>()?;
         G1Projective::batch_normalization(&mut projective);
         for (p, a) in powers.iter_mut().zip(projective) {
@@ -134,18 +207,15 @@ impl Engine for Arkworks {
     }
 
     #[instrument(level = "info", skip_all, fields(n=powers.len()))]
-    fn add_entropy_g2(entropy: [u8; 32], powers: &mut [G2]) -> Result<(), CeremonyError> {
-        let tau = random_scalar(entropy);
-        let taus = iter::successors(Some(Fr::one()), |x| Some(*x * tau))
-            .take(powers.len())
-            .collect::<Vec<_>>();
+    fn add_entropy_g2(entropy: &Entropy, powers: &mut [G2]) -> Result<(), CeremonyError> {
+        let taus = powers_of_tau(entropy, powers.len());
         let mut projective = powers
             .par_iter()
-            .zip(taus)
+            .zip(taus.par_iter())
             .map(|(p, tau)| {
                 G2Affine::try_from(*p).map(|p| {
                     let wnaf = WnafContext::new(5);
-                    wnaf.mul(p.into(), &tau)
+                    wnaf.mul(p.into(), tau)
                 })
             })
             .collect::<Result<Vec<_>, _>>()?;
@@ -155,25 +225,94 @@ impl Engine for Arkworks {
         }
         Ok(())
     }
+
+    #[instrument(level = "info", skip_all, fields(n=points.len()))]
+    fn g1_lincomb(points: &[G1], scalars: &[Scalar]) -> Result<G1, CeremonyError> {
+        assert_eq!(points.len(), scalars.len());
+        let points = points
+            .par_iter()
+            .enumerate()
+            .map(|(i, p)| G1Affine::try_from(*p).map_err(|e| CeremonyError::InvalidG1Power(i, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = VariableBaseMSM::multi_scalar_mul(&points, &to_repr(scalars));
+        Ok(result.into_affine().into())
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=openings.len()))]
+    fn verify_openings(
+        tau: G2,
+        openings: &[KzgOpening],
+        randomness: Randomness,
+    ) -> Result<(), CeremonyError> {
+        // Combine the checks into
+        //   e(Σ r (C - y G1 + z π), G2) · e(-Σ r π, τ) = 1
+        let tau = G2Affine::try_from(tau)?;
+        let r = scalar_factors(randomness, b"verify_openings", openings.len());
+        let mut points = Vec::with_capacity(2 * openings.len() + 1);
+        let mut factors = Vec::with_capacity(2 * openings.len() + 1);
+        let mut sum_ry = Fr::zero();
+        for (opening, r) in openings.iter().zip(&r) {
+            points.push(G1Affine::try_from(opening.commitment)?);
+            factors.push(*r);
+            points.push(G1Affine::try_from(opening.proof)?);
+            factors.push(*r * opening.z);
+            sum_ry += *r * opening.y;
+        }
+        points.push(G1Affine::prime_subgroup_generator());
+        factors.push(-sum_ry);
+        let lhs = VariableBaseMSM::multi_scalar_mul(&points, &to_repr(&factors));
+        let proofs = points[..2 * openings.len()]
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .copied()
+            .collect::<Vec<_>>();
+        let rhs = VariableBaseMSM::multi_scalar_mul(&proofs, &to_repr(&r));
+
+        // Check pairing
+        let prepared = [
+            (
+                lhs.into_affine().into(),
+                G2Affine::prime_subgroup_generator().into(),
+            ),
+            ((-rhs).into_affine().into(), tau.into()),
+        ];
+        if !Bls12_381::product_of_pairings(&prepared).is_one() {
+            return Err(CeremonyError::OpeningPairingFailed);
+        }
+        Ok(())
+    }
+}
+
+/// Returns `[1, tau, tau^2, ...]` with `n` elements.
+fn powers_of_tau(entropy: &Entropy, n: usize) -> Zeroizing<Vec<Fr>> {
+    let tau = entropy.tau();
+    // Growing the vector would leave copies of the powers in freed memory
+    let mut powers = Zeroizing::new(Vec::with_capacity(n));
+    powers.extend(iter::successors(Some(Fr::one()), |x| Some(*x * *tau)).take(n));
+    powers
+}
+
+fn scalar_factors(randomness: Randomness, domain: &[u8], n: usize) -> Vec<Fr> {
+    randomness
+        .factors(domain, n)
+        .into_iter()
+        .map(Fr::from)
+        .collect()
 }
 
-fn random_factors(n: usize) -> (Vec<<Fr as PrimeField>::BigInt>, Fr) {
-    let mut rng = rand::thread_rng();
-    let mut sum = Fr::zero();
-    let factors = iter::from_fn(|| {
-        let r = Fr::rand(&mut rng);
-        sum += r;
-        Some(r.0)
-    })
-    .take(n)
-    .collect::<Vec<_>>();
-    (factors, sum)
+fn random_factors(
+    randomness: Randomness,
+    domain: &[u8],
+    n: usize,
+) -> (Vec<<Fr as PrimeField>::BigInt>, Fr) {
+    let factors = scalar_factors(randomness, domain, n);
+    let sum = factors.iter().sum();
+    (to_repr(&factors), sum)
 }
 
-fn random_scalar(entropy: [u8; 32]) -> Fr {
-    // TODO: Use an explicit cryptographic rng.
-    let mut rng = StdRng::from_seed(entropy);
-    Fr::rand(&mut rng)
+fn to_repr(scalars: &[Fr]) -> Vec<<Fr as PrimeField>::BigInt> {
+    scalars.iter().map(PrimeField::into_repr).collect()
 }
 
 #[cfg(test)]
@@ -0,0 +1,540 @@
+//! BLST implementation of [`Engine`].
+
+#![cfg(feature = "blst")]
+// The BLST bindings take raw pointers, which we pass as coerced references.
+#![allow(clippy::borrow_as_ptr)]
+
+use super::Engine;
+use crate::{
+    CeremonyError, Contribution, Entropy, KzgOpening, ParseError, Randomness, Scalar, G1, G2,
+};
+use ::blst::{
+    blst_fp12, blst_fp12_is_one, blst_fr, blst_fr_from_scalar, blst_fr_mul, blst_p1,
+    blst_p1_add_or_double, blst_p1_affine, blst_p1_affine_compress, blst_p1_affine_generator,
+    blst_p1_affine_in_g1, blst_p1_affine_is_inf, blst_p1_cneg, blst_p1_from_affine, blst_p1_mult,
+    blst_p1_to_affine, blst_p1_uncompress, blst_p2, blst_p2_add_or_double, blst_p2_affine,
+    blst_p2_affine_compress, blst_p2_affine_generator, blst_p2_affine_in_g2, blst_p2_affine_is_inf,
+    blst_p2_from_affine, blst_p2_mult, blst_p2_to_affine, blst_p2_uncompress, blst_scalar,
+    blst_scalar_from_fr, blst_scalar_from_lendian, p1_affines, p2_affines, MultiPoint, BLST_ERROR,
+};
+use ark_bls12_381::Fr;
+use ark_ff::{BigInteger, PrimeField, Zero};
+use hex_literal::hex;
+use rayon::prelude::*;
+use tracing::instrument;
+use zeroize::{Zeroize, Zeroizing};
+
+/// The BLS12-381 base field modulus in big-endian bytes.
+const MODULUS: [u8; 48] = hex!("1a0111ea397fe69a4b1ba7b6434bacd764774b84f38512bf6730d2a0f6b0f6241eabfffeb153ffffb9feffffffffaaab");
+
+/// Number of scalar bits in the linear combinations. The random factors have
+/// [`FACTOR_BITS`](crate::randomness::FACTOR_BITS) bits, so sums of up to four
+/// of them fit.
+const FACTOR_BITS: usize = 128;
+
+/// BLST implementation of [`Engine`].
+pub struct Blst;
+
+impl Engine for Blst {
+    #[instrument(level = "info", skip_all, fields(n=points.len()))]
+    fn validate_g1(points: &[G1]) -> Result<(), CeremonyError> {
+        points.into_par_iter().enumerate().try_for_each(|(i, p)| {
+            parse_g1(*p)
+                .map(|_| ())
+                .map_err(|e| CeremonyError::InvalidG1Power(i, e))
+        })
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=points.len()))]
+    fn validate_g2(points: &[G2]) -> Result<(), CeremonyError> {
+        points.into_par_iter().enumerate().try_for_each(|(i, p)| {
+            parse_g2(*p)
+                .map(|_| ())
+                .map_err(|e| CeremonyError::InvalidG2Power(i, e))
+        })
+    }
+
+    #[instrument(level = "info", skip_all)]
+    fn verify_pubkey(tau: G1, previous: G1, pubkey: G2) -> Result<(), CeremonyError> {
+        let tau = parse_g1(tau)?;
+        let previous = parse_g1(previous)?;
+        let pubkey = parse_g2(pubkey)?;
+        if !pairings_equal(&tau, &g2_generator(), &previous, &pubkey) {
+            return Err(CeremonyError::PubKeyPairingFailed);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=powers.len()))]
+    fn verify_g1(powers: &[G1], tau: G2, randomness: Randomness) -> Result<(), CeremonyError> {
+        // Parse ZCash format
+        let powers = powers
+            .into_par_iter()
+            .map(|p| parse_g1(*p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tau = parse_g2(tau)?;
+
+        // Compute random linear combination
+        let factors = to_bytes(&randomness.factors(b"verify_g1", powers.len() - 1));
+        let lhs_g1 = to_affine_g1(&powers[1..].mult(&factors, FACTOR_BITS));
+        let rhs_g1 = to_affine_g1(&powers[..powers.len() - 1].mult(&factors, FACTOR_BITS));
+
+        // Check pairing
+        if !pairings_equal(&lhs_g1, &g2_generator(), &rhs_g1, &tau) {
+            return Err(CeremonyError::G1PairingFailed);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all, fields(n1=g1.len(), n2=g2.len()))]
+    fn verify_g2(g1: &[G1], g2: &[G2], randomness: Randomness) -> Result<(), CeremonyError> {
+        assert!(g1.len() == g2.len());
+
+        // Parse ZCash format
+        let g1 = g1
+            .into_par_iter()
+            .map(|p| parse_g1(*p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let g2 = g2
+            .into_par_iter()
+            .map(|p| parse_g2(*p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Compute random linear combination
+        let factors = to_bytes(&randomness.factors(b"verify_g2", g2.len()));
+        let lhs_g1 = to_affine_g1(&g1.mult(&factors, FACTOR_BITS));
+        let rhs_g2 = to_affine_g2(&g2.mult(&factors, FACTOR_BITS));
+
+        // Check pairing
+        if !pairings_equal(&lhs_g1, &g2_generator(), &g1_generator(), &rhs_g2) {
+            return Err(CeremonyError::G2PairingFailed);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=contributions.len()))]
+    fn verify_batch(
+        contributions: &[(G1, &Contribution)],
+        randomness: Randomness,
+    ) -> Result<(), CeremonyError> {
+        // Combine the checks of each contribution into
+        //   e(a, G2) · e(-s · previous, pubkey) · e(-b, g2[1]) · e(-G1, c) = 1
+        // where a and c accumulate over all contributions.
+        let terms = contributions
+            .par_iter()
+            .enumerate()
+            .map(|(i, (previous, contribution))| {
+                let g1 = contribution
+                    .powers
+                    .g1
+                    .par_iter()
+                    .map(|p| parse_g1(*p))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let g2 = contribution
+                    .powers
+                    .g2
+                    .par_iter()
+                    .map(|p| parse_g2(*p))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let previous = parse_g1(*previous)?;
+                let pubkey = parse_g2(contribution.pubkey)?;
+
+                // Random factors for the pubkey, G1 and G2 checks
+                let randomness = randomness.derive(&(i as u64).to_le_bytes());
+                let s = randomness.factors(b"verify_pubkey", 1)[0];
+                let r = randomness.factors(b"verify_g1", g1.len() - 1);
+                let t = randomness.factors(b"verify_g2", g2.len());
+
+                // Coefficients of the powers in the generator pairing
+                let mut a = vec![0_u128; g1.len()];
+                for (a, r) in a[1..].iter_mut().zip(&r) {
+                    *a += r;
+                }
+                for (a, t) in a.iter_mut().zip(&t) {
+                    *a += t;
+                }
+                a[1] += s;
+
+                let a = g1.mult(&to_bytes(&a), FACTOR_BITS);
+                let mut b = g1[..r.len()].mult(&to_bytes(&r), FACTOR_BITS);
+                let c = g2.mult(&to_bytes(&t), FACTOR_BITS);
+                let mut previous_s = blst_p1::default();
+                unsafe {
+                    let mut base = blst_p1::default();
+                    blst_p1_from_affine(&mut base, &previous);
+                    blst_p1_mult(
+                        &mut previous_s,
+                        &base,
+                        s.to_le_bytes().as_ptr(),
+                        FACTOR_BITS,
+                    );
+                    blst_p1_cneg(&mut previous_s, true);
+                    blst_p1_cneg(&mut b, true);
+                }
+                let loops = miller_loop(&to_affine_g1(&previous_s), &pubkey)
+                    * miller_loop(&to_affine_g1(&b), &g2[1]);
+                Ok((a, loops, c))
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?;
+
+        let mut a = blst_p1::default();
+        let mut c = blst_p2::default();
+        let mut product = blst_fp12::default();
+        for (term_a, term_loops, term_c) in terms {
+            unsafe {
+                blst_p1_add_or_double(&mut a, &a, &term_a);
+                blst_p2_add_or_double(&mut c, &c, &term_c);
+            }
+            product *= term_loops;
+        }
+        let mut minus_one = blst_p1::default();
+        unsafe {
+            blst_p1_from_affine(&mut minus_one, &g1_generator());
+            blst_p1_cneg(&mut minus_one, true);
+        }
+        product *= miller_loop(&to_affine_g1(&a), &g2_generator());
+        product *= miller_loop(&to_affine_g1(&minus_one), &to_affine_g2(&c));
+
+        // Check pairing
+        if !unsafe { blst_fp12_is_one(&product.final_exp()) } {
+            return Err(CeremonyError::BatchPairingFailed);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=powers.len()))]
+    fn add_entropy_g1(entropy: &Entropy, powers: &mut [G1]) -> Result<(), CeremonyError> {
+        if powers.is_empty() {
+            return Ok(());
+        }
+        let taus = powers_of_scalar(&random_scalar(entropy), powers.len());
+        let projective = powers
+            .par_iter()
+            .zip(taus.par_iter())
+            .map(|(p, tau)| {
+                parse_g1(*p).map(|p| {
+                    let mut base = blst_p1::default();
+                    let mut result = blst_p1::default();
+                    unsafe {
+                        blst_p1_from_affine(&mut base, &p);
+                        blst_p1_mult(&mut result, &base, tau.b.as_ptr(), 255);
+                    }
+                    result
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let affine = p1_affines::from(&projective);
+        for (p, a) in powers.iter_mut().zip(affine.as_slice()) {
+            *p = write_g1(a);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=powers.len()))]
+    fn add_entropy_g2(entropy: &Entropy, powers: &mut [G2]) -> Result<(), CeremonyError> {
+        if powers.is_empty() {
+            return Ok(());
+        }
+        let taus = powers_of_scalar(&random_scalar(entropy), powers.len());
+        let projective = powers
+            .par_iter()
+            .zip(taus.par_iter())
+            .map(|(p, tau)| {
+                parse_g2(*p).map(|p| {
+                    let mut base = blst_p2::default();
+                    let mut result = blst_p2::default();
+                    unsafe {
+                        blst_p2_from_affine(&mut base, &p);
+                        blst_p2_mult(&mut result, &base, tau.b.as_ptr(), 255);
+                    }
+                    result
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let affine = p2_affines::from(&projective);
+        for (p, a) in powers.iter_mut().zip(affine.as_slice()) {
+            *p = write_g2(a);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=points.len()))]
+    fn g1_lincomb(points: &[G1], scalars: &[Scalar]) -> Result<G1, CeremonyError> {
+        assert_eq!(points.len(), scalars.len());
+        let points = points
+            .par_iter()
+            .enumerate()
+            .map(|(i, p)| parse_g1(*p).map_err(|e| CeremonyError::InvalidG1Power(i, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(write_g1(&to_affine_g1(&lincomb_g1(&points, scalars))))
+    }
+
+    #[instrument(level = "info", skip_all, fields(n=openings.len()))]
+    fn verify_openings(
+        tau: G2,
+        openings: &[KzgOpening],
+        randomness: Randomness,
+    ) -> Result<(), CeremonyError> {
+        // Combine the checks into
+        //   e(Σ r (C - y G1 + z π), G2) · e(-Σ r π, τ) = 1
+        let tau = parse_g2(tau)?;
+        let r = randomness
+            .factors(b"verify_openings", openings.len())
+            .into_iter()
+            .map(Fr::from)
+            .collect::<Vec<_>>();
+        let mut points = Vec::with_capacity(2 * openings.len() + 1);
+        let mut factors = Vec::with_capacity(2 * openings.len() + 1);
+        let mut proofs = Vec::with_capacity(openings.len());
+        let mut sum_ry = Fr::zero();
+        for (opening, r) in openings.iter().zip(&r) {
+            let proof = parse_g1(opening.proof)?;
+            points.push(parse_g1(opening.commitment)?);
+            factors.push(*r);
+            points.push(proof);
+            factors.push(*r * opening.z);
+            proofs.push(proof);
+            sum_ry += *r * opening.y;
+        }
+        points.push(g1_generator());
+        factors.push(-sum_ry);
+        let lhs = lincomb_g1(&points, &factors);
+        let mut rhs = lincomb_g1(&proofs, &r);
+        unsafe { blst_p1_cneg(&mut rhs, true) };
+
+        // Check pairing
+        let product = miller_loop(&to_affine_g1(&lhs), &g2_generator())
+            * miller_loop(&to_affine_g1(&rhs), &tau);
+        if !unsafe { blst_fp12_is_one(&product.final_exp()) } {
+            return Err(CeremonyError::OpeningPairingFailed);
+        }
+        Ok(())
+    }
+}
+
+/// Checks the ZCash flags and that all field elements are reduced.
+///
+/// BLST reports all of these as a single `BLST_BAD_ENCODING`, so we check
+/// them up front to produce the same [`ParseError`]s as the other engines.
+fn check_encoding<const N: usize>(bytes: &[u8; N]) -> Result<(), ParseError> {
+    let flags = bytes[0];
+    let mut bytes = *bytes;
+    bytes[0] &= 0x1f;
+    for (i, chunk) in bytes.chunks_exact(MODULUS.len()).enumerate() {
+        if chunk >= &MODULUS[..] {
+            return Err(ParseError::InvalidPrimeField(i));
+        }
+    }
+    if flags & 0x80 == 0 {
+        return Err(ParseError::NotCompressed);
+    }
+    if flags & 0x40 != 0 && (flags & 0x20 != 0 || bytes.iter().any(|b| *b != 0)) {
+        return Err(ParseError::InvalidInfinity);
+    }
+    Ok(())
+}
+
+/// Deserialize a ZCash encoded G1 point and check it is in the subgroup.
+fn parse_g1(g1: G1) -> Result<blst_p1_affine, ParseError> {
+    check_encoding(&g1.0)?;
+    let mut point = blst_p1_affine::default();
+    if unsafe { blst_p1_uncompress(&mut point, g1.0.as_ptr()) } != BLST_ERROR::BLST_SUCCESS {
+        return Err(ParseError::InvalidXCoordinate);
+    }
+    if !unsafe { blst_p1_affine_in_g1(&point) } {
+        return Err(ParseError::InvalidSubgroup);
+    }
+    Ok(point)
+}
+
+/// Deserialize a ZCash encoded G2 point and check it is in the subgroup.
+fn parse_g2(g2: G2) -> Result<blst_p2_affine, ParseError> {
+    check_encoding(&g2.0)?;
+    let mut point = blst_p2_affine::default();
+    if unsafe { blst_p2_uncompress(&mut point, g2.0.as_ptr()) } != BLST_ERROR::BLST_SUCCESS {
+        return Err(ParseError::InvalidXCoordinate);
+    }
+    if !unsafe { blst_p2_affine_in_g2(&point) } {
+        return Err(ParseError::InvalidSubgroup);
+    }
+    Ok(point)
+}
+
+fn write_g1(point: &blst_p1_affine) -> G1 {
+    let mut out = [0_u8; 48];
+    unsafe { blst_p1_affine_compress(out.as_mut_ptr(), point) };
+    G1(out)
+}
+
+fn write_g2(point: &blst_p2_affine) -> G2 {
+    let mut out = [0_u8; 96];
+    unsafe { blst_p2_affine_compress(out.as_mut_ptr(), point) };
+    G2(out)
+}
+
+fn g1_generator() -> blst_p1_affine {
+    unsafe { *blst_p1_affine_generator() }
+}
+
+fn g2_generator() -> blst_p2_affine {
+    unsafe { *blst_p2_affine_generator() }
+}
+
+fn to_affine_g1(point: &blst_p1) -> blst_p1_affine {
+    let mut out = blst_p1_affine::default();
+    unsafe { blst_p1_to_affine(&mut out, point) };
+    out
+}
+
+fn to_affine_g2(point: &blst_p2) -> blst_p2_affine {
+    let mut out = blst_p2_affine::default();
+    unsafe { blst_p2_to_affine(&mut out, point) };
+    out
+}
+
+/// Computes the Miller loop of a pair. The raw BLST Miller loop does not
+/// support points at infinity, so these are mapped to one.
+fn miller_loop(p: &blst_p1_affine, q: &blst_p2_affine) -> blst_fp12 {
+    if unsafe { blst_p1_affine_is_inf(p) || blst_p2_affine_is_inf(q) } {
+        return blst_fp12::default();
+    }
+    blst_fp12::miller_loop(q, p)
+}
+
+/// Checks `e(a1, a2) == e(b1, b2)`.
+fn pairings_equal(
+    a1: &blst_p1_affine,
+    a2: &blst_p2_affine,
+    b1: &blst_p1_affine,
+    b2: &blst_p2_affine,
+) -> bool {
+    blst_fp12::finalverify(&miller_loop(a1, a2), &miller_loop(b1, b2))
+}
+
+/// Concatenated little-endian factors, as expected by the multi-scalar
+/// multiplication.
+fn to_bytes(factors: &[u128]) -> Vec<u8> {
+    factors.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+
+/// Multi-scalar multiplication with full size scalars.
+fn lincomb_g1(points: &[blst_p1_affine], scalars: &[Fr]) -> blst_p1 {
+    if points.is_empty() {
+        return blst_p1::default();
+    }
+    let bytes = scalars
+        .iter()
+        .flat_map(|s| s.into_repr().to_bytes_le())
+        .collect::<Vec<_>>();
+    points.mult(&bytes, 255)
+}
+
+/// Derives the secret scalar from entropy.
+fn random_scalar(entropy: &Entropy) -> Zeroizing<blst_scalar> {
+    let bytes = Zeroizing::new(entropy.tau().into_repr().to_bytes_le());
+    let mut scalar = Zeroizing::new(blst_scalar::default());
+    unsafe { blst_scalar_from_lendian(&mut *scalar, bytes.as_ptr()) };
+    scalar
+}
+
+/// Returns `[1, tau, tau^2, ...]` with `n` elements.
+fn powers_of_scalar(tau: &blst_scalar, n: usize) -> Zeroizing<Vec<blst_scalar>> {
+    let mut one = blst_scalar::default();
+    one.b[0] = 1;
+    let mut tau_fr = blst_fr::default();
+    let mut power = blst_fr::default();
+    unsafe {
+        blst_fr_from_scalar(&mut tau_fr, tau);
+        blst_fr_from_scalar(&mut power, &one);
+    }
+    // Allocated up front, so no copies are left behind by reallocation
+    let mut powers = Zeroizing::new(Vec::with_capacity(n));
+    for _ in 0..n {
+        let mut scalar = blst_scalar::default();
+        unsafe { blst_scalar_from_fr(&mut scalar, &power) };
+        powers.push(scalar);
+        let power_ptr: *mut blst_fr = &mut power;
+        unsafe { blst_fr_mul(power_ptr, power_ptr, &tau_fr) };
+    }
+    tau_fr.l.zeroize();
+    power.l.zeroize();
+    powers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_generators() {
+        assert_eq!(parse_g1(G1::one()).unwrap(), g1_generator());
+        assert_eq!(parse_g2(G2::one()).unwrap(), g2_generator());
+        assert_eq!(write_g1(&g1_generator()), G1::one());
+        assert_eq!(write_g2(&g2_generator()), G2::one());
+        assert_eq!(write_g1(&parse_g1(G1::zero()).unwrap()), G1::zero());
+        assert_eq!(write_g2(&parse_g2(G2::zero()).unwrap()), G2::zero());
+    }
+
+    #[test]
+    fn test_reject_bad_encodings() {
+        let mut uncompressed = G1::one();
+        uncompressed.0[0] &= 0x7f;
+        assert_eq!(parse_g1(uncompressed), Err(ParseError::NotCompressed));
+        let mut infinity = G1::zero();
+        infinity.0[47] = 1;
+        assert_eq!(parse_g1(infinity), Err(ParseError::InvalidInfinity));
+        let mut unreduced = G1(MODULUS);
+        unreduced.0[0] |= 0x80;
+        assert_eq!(parse_g1(unreduced), Err(ParseError::InvalidPrimeField(0)));
+    }
+
+    #[test]
+    fn test_verify_entropy() {
+        let mut g1 = vec![G1::one(); 8];
+        let mut g2 = vec![G2::one(); 4];
+        let mut pubkey = [G2::zero(), G2::one()];
+        Blst::add_entropy_g1(&[1; 32].into(), &mut g1).unwrap();
+        Blst::add_entropy_g2(&[1; 32].into(), &mut g2).unwrap();
+        Blst::add_entropy_g2(&[1; 32].into(), &mut pubkey).unwrap();
+        Blst::validate_g1(&g1).unwrap();
+        Blst::validate_g2(&g2).unwrap();
+        Blst::verify_pubkey(g1[1], G1::one(), pubkey[1]).unwrap();
+        Blst::verify_g1(&g1, g2[1], Randomness::Fresh).unwrap();
+        Blst::verify_g2(&g1[..g2.len()], &g2, Randomness::Fresh).unwrap();
+        assert_eq!(
+            Blst::verify_pubkey(g1[2], G1::one(), pubkey[1]),
+            Err(CeremonyError::PubKeyPairingFailed)
+        );
+        g1.swap(2, 3);
+        assert_eq!(
+            Blst::verify_g1(&g1, g2[1], Randomness::Fresh),
+            Err(CeremonyError::G1PairingFailed)
+        );
+        assert_eq!(
+            Blst::verify_g2(&g1[..g2.len()], &g2, Randomness::Fresh),
+            Err(CeremonyError::G2PairingFailed)
+        );
+    }
+
+    #[cfg(feature = "arkworks")]
+    #[test]
+    fn test_matches_arkworks() {
+        use crate::Arkworks;
+        let mut blst = vec![G1::one(); 8];
+        let mut ark = blst.clone();
+        Blst::add_entropy_g1(&[7; 32].into(), &mut blst).unwrap();
+        Arkworks::add_entropy_g1(&[7; 32].into(), &mut ark).unwrap();
+        assert_eq!(blst, ark);
+    }
+}
+
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use super::{super::bench::bench_engine, *};
+    use criterion::Criterion;
+
+    pub fn group(criterion: &mut Criterion) {
+        bench_engine::<Blst>(criterion, "blst");
+    }
+}
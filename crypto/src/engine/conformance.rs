@@ -0,0 +1,480 @@
+//! Conformance and differential tests for [`Engine`] implementations.
+//!
+//! The checks are generic over the engine. Reference values are computed
+//! directly with `ark-bls12-381` primitives, so they do not depend on any
+//! engine implementation. To test a new engine, add it to the
+//! `conformance_tests!` and `differential_tests!` invocations at the bottom.
+
+#![allow(dead_code)] // Checks are only instantiated for enabled engines.
+
+use super::Engine;
+use crate::{
+    CeremonyError, Contribution, Entropy, KzgOpening, KzgSetup, ParseError, Powers, Randomness, G1,
+    G2,
+};
+use ark_bls12_381::{Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{BigInteger, PrimeField, Zero};
+use hex_literal::hex;
+use proptest::{
+    arbitrary::any, prop_assume, prop_oneof, proptest, strategy::Strategy,
+    test_runner::Config as ProptestConfig,
+};
+use std::iter;
+
+/// Pairing checks are expensive, so they run fewer cases.
+const PAIRING_CASES: u32 = 8;
+
+/// Number of G1 and G2 powers used in pairing checks.
+const NUM_G1: usize = 8;
+const NUM_G2: usize = 4;
+
+/// The BLS12-381 base field modulus in big-endian bytes.
+const MODULUS: [u8; 48] = hex!("1a0111ea397fe69a4b1ba7b6434bacd764774b84f38512bf6730d2a0f6b0f6241eabfffeb153ffffb9feffffffffaaab");
+
+/// Serialize a G1 point into compressed ZCash format.
+fn compress_g1(p: &G1Affine) -> G1 {
+    if p.infinity {
+        return G1::zero();
+    }
+    let mut bytes = [0_u8; 48];
+    bytes.copy_from_slice(&p.x.into_repr().to_bytes_be());
+    bytes[0] |= 0x80;
+    if p.y > -p.y {
+        bytes[0] |= 0x20;
+    }
+    G1(bytes)
+}
+
+/// Serialize a G2 point into compressed ZCash format.
+fn compress_g2(p: &G2Affine) -> G2 {
+    if p.infinity {
+        return G2::zero();
+    }
+    let mut bytes = [0_u8; 96];
+    bytes[..48].copy_from_slice(&p.x.c1.into_repr().to_bytes_be());
+    bytes[48..].copy_from_slice(&p.x.c0.into_repr().to_bytes_be());
+    bytes[0] |= 0x80;
+    if p.y > -p.y {
+        bytes[0] |= 0x20;
+    }
+    G2(bytes)
+}
+
+/// The secret scalar engines are expected to derive from entropy.
+fn reference_scalar(entropy: [u8; 32]) -> Fr {
+    *Entropy::from(entropy).tau()
+}
+
+/// Powers of `tau` in G1 and G2, and the matching pubkey.
+fn reference_powers(tau: Fr, num_g1: usize, num_g2: usize) -> (Vec<G1>, Vec<G2>, G2) {
+    let taus = iter::successors(Some(Fr::from(1_u64)), |x| Some(*x * tau))
+        .take(num_g1)
+        .collect::<Vec<_>>();
+    let g1 = taus
+        .iter()
+        .map(|t| compress_g1(&G1Affine::prime_subgroup_generator().mul(*t).into_affine()))
+        .collect();
+    let g2 = taus[..num_g2]
+        .iter()
+        .map(|t| compress_g2(&G2Affine::prime_subgroup_generator().mul(*t).into_affine()))
+        .collect();
+    let pubkey = compress_g2(&G2Affine::prime_subgroup_generator().mul(tau).into_affine());
+    (g1, g2, pubkey)
+}
+
+/// A contribution that adds `tau` to a fresh transcript.
+fn reference_contribution(tau: Fr) -> Contribution {
+    let (g1, g2, pubkey) = reference_powers(tau, NUM_G1, NUM_G2);
+    Contribution {
+        powers: Powers { g1, g2 },
+        pubkey,
+    }
+}
+
+/// Finds a curve point with small `x` that is not in the prime order
+/// subgroup.
+fn g1_off_subgroup() -> G1 {
+    (1_u64..1000)
+        .filter_map(|i| G1Affine::get_point_from_x(Fq::from(i), false))
+        .find(|p| !p.is_in_correct_subgroup_assuming_on_curve())
+        .map(|p| compress_g1(&p))
+        .expect("BUG: most curve points are not in the subgroup")
+}
+
+fn g2_off_subgroup() -> G2 {
+    (1_u64..1000)
+        .filter_map(|i| G2Affine::get_point_from_x(Fq2::new(Fq::from(i), Fq::from(1_u64)), false))
+        .find(|p| !p.is_in_correct_subgroup_assuming_on_curve())
+        .map(|p| compress_g2(&p))
+        .expect("BUG: most curve points are not in the subgroup")
+}
+
+/// Finds a small `x` that is not the coordinate of any curve point.
+fn g1_off_curve() -> G1 {
+    let x = (1_u64..1000)
+        .map(Fq::from)
+        .find(|x| G1Affine::get_point_from_x(*x, false).is_none())
+        .expect("BUG: half of all x are not on the curve");
+    let mut bytes = [0_u8; 48];
+    bytes.copy_from_slice(&x.into_repr().to_bytes_be());
+    bytes[0] |= 0x80;
+    G1(bytes)
+}
+
+fn g2_off_curve() -> G2 {
+    let x = (1_u64..1000)
+        .map(|i| Fq2::new(Fq::from(i), Fq::from(1_u64)))
+        .find(|x| G2Affine::get_point_from_x(*x, false).is_none())
+        .expect("BUG: half of all x are not on the curve");
+    let mut bytes = [0_u8; 96];
+    bytes[..48].copy_from_slice(&x.c1.into_repr().to_bytes_be());
+    bytes[48..].copy_from_slice(&x.c0.into_repr().to_bytes_be());
+    bytes[0] |= 0x80;
+    G2(bytes)
+}
+
+/// Known-bad G1 encodings and the error every engine must report.
+pub fn bad_g1() -> Vec<(G1, ParseError)> {
+    let mut uncompressed = G1::one();
+    uncompressed.0[0] &= 0x7f;
+    let mut uncompressed_zero = G1::zero();
+    uncompressed_zero.0[0] &= 0x7f;
+    let mut infinity_sign = G1::zero();
+    infinity_sign.0[0] |= 0x20;
+    let mut infinity_x = G1::zero();
+    infinity_x.0[47] = 1;
+    let mut infinity_flag = G1::one();
+    infinity_flag.0[0] |= 0x40;
+    let mut modulus = G1(MODULUS);
+    modulus.0[0] |= 0x80;
+    let mut too_large = G1([0xff; 48]);
+    too_large.0[0] = 0x9f;
+    vec![
+        (uncompressed, ParseError::NotCompressed),
+        (uncompressed_zero, ParseError::NotCompressed),
+        (infinity_sign, ParseError::InvalidInfinity),
+        (infinity_x, ParseError::InvalidInfinity),
+        (infinity_flag, ParseError::InvalidInfinity),
+        (modulus, ParseError::InvalidPrimeField(0)),
+        (too_large, ParseError::InvalidPrimeField(0)),
+        (g1_off_curve(), ParseError::InvalidXCoordinate),
+        (g1_off_subgroup(), ParseError::InvalidSubgroup),
+    ]
+}
+
+/// Known-bad G2 encodings and the error every engine must report.
+pub fn bad_g2() -> Vec<(G2, ParseError)> {
+    let mut uncompressed = G2::one();
+    uncompressed.0[0] &= 0x7f;
+    let mut infinity_sign = G2::zero();
+    infinity_sign.0[0] |= 0x20;
+    let mut infinity_x = G2::zero();
+    infinity_x.0[95] = 1;
+    let mut infinity_flag = G2::one();
+    infinity_flag.0[0] |= 0x40;
+    let mut modulus_c1 = G2::one();
+    modulus_c1.0[..48].copy_from_slice(&MODULUS);
+    modulus_c1.0[0] |= 0x80;
+    let mut modulus_c0 = G2::one();
+    modulus_c0.0[48..].copy_from_slice(&MODULUS);
+    vec![
+        (uncompressed, ParseError::NotCompressed),
+        (infinity_sign, ParseError::InvalidInfinity),
+        (infinity_x, ParseError::InvalidInfinity),
+        (infinity_flag, ParseError::InvalidInfinity),
+        (modulus_c1, ParseError::InvalidPrimeField(0)),
+        (modulus_c0, ParseError::InvalidPrimeField(1)),
+        (g2_off_curve(), ParseError::InvalidXCoordinate),
+        (g2_off_subgroup(), ParseError::InvalidSubgroup),
+    ]
+}
+
+fn arb_fr() -> impl Strategy<Value = Fr> {
+    any::<[u8; 32]>().prop_map(|bytes| Fr::from_le_bytes_mod_order(&bytes))
+}
+
+fn arb_g1() -> impl Strategy<Value = G1> {
+    arb_fr().prop_map(|s| compress_g1(&G1Affine::prime_subgroup_generator().mul(s).into_affine()))
+}
+
+fn arb_g2() -> impl Strategy<Value = G2> {
+    arb_fr().prop_map(|s| compress_g2(&G2Affine::prime_subgroup_generator().mul(s).into_affine()))
+}
+
+fn arb_randomness() -> impl Strategy<Value = Randomness> {
+    any::<Option<[u8; 32]>>().prop_map(|seed| seed.map_or(Randomness::Fresh, Randomness::Seeded))
+}
+
+/// Mostly plausible encodings: compressed with a reduced `x`, so about half
+/// are on the curve and exercise the subgroup check.
+fn arb_g1_encoding() -> impl Strategy<Value = G1> {
+    prop_oneof![
+        any::<[u8; 32]>().prop_map(|bytes| {
+            let mut result = [0_u8; 48];
+            result[16..].copy_from_slice(&bytes);
+            result[0] = 0x80 | (bytes[0] & 0x60);
+            G1(result)
+        }),
+        any::<[[u8; 16]; 3]>().prop_map(|bytes| G1(bytes.concat().try_into().unwrap())),
+        arb_g1(),
+    ]
+}
+
+fn arb_g2_encoding() -> impl Strategy<Value = G2> {
+    prop_oneof![
+        any::<[[u8; 32]; 2]>().prop_map(|bytes| {
+            let mut result = [0_u8; 96];
+            result[16..48].copy_from_slice(&bytes[0]);
+            result[64..].copy_from_slice(&bytes[1]);
+            result[0] = 0x80 | (bytes[0][0] & 0x60);
+            G2(result)
+        }),
+        any::<[[u8; 32]; 3]>().prop_map(|bytes| G2(bytes.concat().try_into().unwrap())),
+        arb_g2(),
+    ]
+}
+
+/// Checks that valid points are accepted and known-bad points are rejected
+/// with the expected error.
+pub fn check_validate<E: Engine>() {
+    assert_eq!(E::validate_g1(&[G1::zero(), G1::one()]), Ok(()));
+    assert_eq!(E::validate_g2(&[G2::zero(), G2::one()]), Ok(()));
+    proptest!(|(p in arb_g1(), q in arb_g2())| {
+        assert_eq!(E::validate_g1(&[p]), Ok(()));
+        assert_eq!(E::validate_g2(&[q]), Ok(()));
+    });
+    for (point, error) in bad_g1() {
+        assert_eq!(
+            E::validate_g1(&[G1::one(), point]),
+            Err(CeremonyError::InvalidG1Power(1, error)),
+            "bad G1 point {point:?}"
+        );
+        assert_eq!(
+            E::verify_pubkey(point, G1::one(), G2::one()),
+            Err(CeremonyError::ParserError(error))
+        );
+        assert_eq!(
+            E::add_entropy_g1(&[0; 32].into(), &mut [G1::one(), point]),
+            Err(CeremonyError::ParserError(error))
+        );
+    }
+    for (point, error) in bad_g2() {
+        assert_eq!(
+            E::validate_g2(&[G2::one(), point]),
+            Err(CeremonyError::InvalidG2Power(1, error)),
+            "bad G2 point {point:?}"
+        );
+        assert_eq!(
+            E::verify_pubkey(G1::one(), G1::one(), point),
+            Err(CeremonyError::ParserError(error))
+        );
+        assert_eq!(
+            E::add_entropy_g2(&[0; 32].into(), &mut [G2::one(), point]),
+            Err(CeremonyError::ParserError(error))
+        );
+    }
+}
+
+/// Checks that correct powers pass the pairing checks and tampered powers
+/// fail them.
+pub fn check_pairings<E: Engine>() {
+    proptest!(ProptestConfig::with_cases(PAIRING_CASES), |(tau in arb_fr(), other in arb_fr(), randomness in arb_randomness())| {
+        prop_assume!(tau != other && !tau.is_zero() && !other.is_zero());
+        let (g1, g2, pubkey) = reference_powers(tau, NUM_G1, NUM_G2);
+        let (other_g1, other_g2, other_pubkey) = reference_powers(other, NUM_G1, NUM_G2);
+
+        // Accept
+        assert_eq!(E::verify_pubkey(g1[1], G1::one(), pubkey), Ok(()));
+        assert_eq!(E::verify_g1(&g1, g2[1], randomness), Ok(()));
+        assert_eq!(E::verify_g2(&g1[..NUM_G2], &g2, randomness), Ok(()));
+
+        // Reject
+        assert_eq!(E::verify_pubkey(g1[1], G1::one(), other_pubkey), Err(CeremonyError::PubKeyPairingFailed));
+        assert_eq!(E::verify_g1(&g1, other_g2[1], randomness), Err(CeremonyError::G1PairingFailed));
+        let mut tampered = g1.clone();
+        tampered[NUM_G1 - 1] = other_g1[NUM_G1 - 1];
+        assert_eq!(E::verify_g1(&tampered, g2[1], randomness), Err(CeremonyError::G1PairingFailed));
+        let mut tampered = g2.clone();
+        tampered[NUM_G2 - 1] = other_g2[NUM_G2 - 1];
+        assert_eq!(E::verify_g2(&g1[..NUM_G2], &tampered, randomness), Err(CeremonyError::G2PairingFailed));
+
+        // Batched
+        let contribution = reference_contribution(tau);
+        let other_contribution = reference_contribution(other);
+        assert_eq!(E::verify_batch(&[(G1::one(), &contribution), (G1::one(), &other_contribution)], randomness), Ok(()));
+        assert_eq!(E::verify_batch(&[(G1::one(), &contribution), (g1[1], &other_contribution)], randomness), Err(CeremonyError::BatchPairingFailed));
+        let mut tampered = other_contribution.clone();
+        tampered.pubkey = pubkey;
+        assert_eq!(E::verify_batch(&[(G1::one(), &contribution), (G1::one(), &tampered)], randomness), Err(CeremonyError::BatchPairingFailed));
+        let mut tampered = other_contribution.clone();
+        tampered.powers.g1[NUM_G1 - 1] = g1[NUM_G1 - 1];
+        assert_eq!(E::verify_batch(&[(G1::one(), &contribution), (G1::one(), &tampered)], randomness), Err(CeremonyError::BatchPairingFailed));
+        let mut tampered = other_contribution;
+        tampered.powers.g2[NUM_G2 - 1] = g2[NUM_G2 - 1];
+        assert_eq!(E::verify_batch(&[(G1::one(), &contribution), (G1::one(), &tampered)], randomness), Err(CeremonyError::BatchPairingFailed));
+    });
+}
+
+/// Checks that adding entropy multiplies by powers of the expected scalar.
+pub fn check_add_entropy<E: Engine>() {
+    proptest!(ProptestConfig::with_cases(PAIRING_CASES), |(entropy in any::<[u8; 32]>(), tau in arb_fr())| {
+        let (mut g1, mut g2, _) = reference_powers(tau, NUM_G1, NUM_G2);
+        let (expected_g1, expected_g2, _) = reference_powers(tau * reference_scalar(entropy), NUM_G1, NUM_G2);
+        E::add_entropy_g1(&entropy.into(), &mut g1).unwrap();
+        E::add_entropy_g2(&entropy.into(), &mut g2).unwrap();
+        assert_eq!(g1, expected_g1);
+        assert_eq!(g2, expected_g2);
+    });
+}
+
+/// Checks linear combinations against a reference, and that KZG openings
+/// computed from them are accepted and wrong openings rejected.
+pub fn check_kzg<E: Engine>() {
+    proptest!(ProptestConfig::with_cases(PAIRING_CASES), |(tau in arb_fr(), polynomial in proptest::collection::vec(arb_fr(), 0..=NUM_G1), z in arb_fr(), randomness in arb_randomness())| {
+        let (g1, g2, _) = reference_powers(tau, NUM_G1, NUM_G2);
+        let value = polynomial.iter().rev().fold(Fr::zero(), |acc, c| acc * tau + c);
+        let expected = compress_g1(&G1Affine::prime_subgroup_generator().mul(value).into_affine());
+        assert_eq!(E::g1_lincomb(&g1[..polynomial.len()], &polynomial), Ok(expected));
+
+        let setup = KzgSetup { g1, tau: g2[1] };
+        let opening = setup.open::<E>(&polynomial, z).unwrap();
+        let y = polynomial.iter().rev().fold(Fr::zero(), |acc, c| acc * z + c);
+        assert_eq!(opening.y, y);
+        assert_eq!(E::verify_openings(setup.tau, &[opening], randomness), Ok(()));
+        let other = setup.open::<E>(&[Fr::from(1_u64), Fr::from(2_u64)], z).unwrap();
+        assert_eq!(E::verify_openings(setup.tau, &[opening, other], randomness), Ok(()));
+
+        let tampered = KzgOpening { y: y + Fr::from(1_u64), ..opening };
+        assert_eq!(E::verify_openings(setup.tau, &[other, tampered], randomness), Err(CeremonyError::OpeningPairingFailed));
+        let tampered = KzgOpening { proof: other.proof, ..opening };
+        prop_assume!(other.proof != opening.proof);
+        assert_eq!(E::verify_openings(setup.tau, &[tampered], randomness), Err(CeremonyError::OpeningPairingFailed));
+    });
+}
+
+/// Checks that two engines produce identical results on the same inputs,
+/// including invalid ones.
+pub fn check_agree<A: Engine, B: Engine>() {
+    // Parsing
+    proptest!(|(p in arb_g1_encoding(), q in arb_g2_encoding())| {
+        assert_eq!(A::validate_g1(&[p]), B::validate_g1(&[p]));
+        assert_eq!(A::validate_g2(&[q]), B::validate_g2(&[q]));
+    });
+    for (point, _) in bad_g1() {
+        assert_eq!(A::validate_g1(&[point]), B::validate_g1(&[point]));
+    }
+    for (point, _) in bad_g2() {
+        assert_eq!(A::validate_g2(&[point]), B::validate_g2(&[point]));
+    }
+
+    // Pairings, both for correct and unrelated powers
+    proptest!(ProptestConfig::with_cases(PAIRING_CASES), |(tau in arb_fr(), other in arb_fr(), bad in arb_g1_encoding(), seed in any::<[u8; 32]>())| {
+        // Engines must agree exactly, so use the same factors.
+        let randomness = Randomness::Seeded(seed);
+        let (g1, g2, pubkey) = reference_powers(tau, NUM_G1, NUM_G2);
+        let (other_g1, other_g2, other_pubkey) = reference_powers(other, NUM_G1, NUM_G2);
+        for pubkey in [pubkey, other_pubkey] {
+            assert_eq!(
+                A::verify_pubkey(g1[1], G1::one(), pubkey),
+                B::verify_pubkey(g1[1], G1::one(), pubkey)
+            );
+        }
+        for tau in [g2[1], other_g2[1]] {
+            assert_eq!(A::verify_g1(&g1, tau, randomness), B::verify_g1(&g1, tau, randomness));
+        }
+        for g2 in [&g2, &other_g2] {
+            assert_eq!(A::verify_g2(&g1[..NUM_G2], g2, randomness), B::verify_g2(&g1[..NUM_G2], g2, randomness));
+        }
+        let mut with_bad = other_g1;
+        with_bad[1] = bad;
+        assert_eq!(A::verify_g1(&with_bad, g2[1], randomness), B::verify_g1(&with_bad, g2[1], randomness));
+        let contribution = reference_contribution(tau);
+        let other_contribution = reference_contribution(other);
+        for previous in [G1::one(), g1[1], bad] {
+            let batch = [(G1::one(), &contribution), (previous, &other_contribution)];
+            assert_eq!(A::verify_batch(&batch, randomness), B::verify_batch(&batch, randomness));
+        }
+    });
+
+    // Adding entropy
+    proptest!(ProptestConfig::with_cases(PAIRING_CASES), |(entropy in any::<[u8; 32]>(), tau in arb_fr())| {
+        let (g1, g2, pubkey) = reference_powers(tau, NUM_G1, NUM_G2);
+        let (mut a_g1, mut b_g1) = (g1.clone(), g1);
+        let (mut a_g2, mut b_g2) = (g2.clone(), g2);
+        let (mut a_pubkey, mut b_pubkey) = ([G2::zero(), pubkey], [G2::zero(), pubkey]);
+        assert_eq!(A::add_entropy_g1(&entropy.into(), &mut a_g1), B::add_entropy_g1(&entropy.into(), &mut b_g1));
+        assert_eq!(A::add_entropy_g2(&entropy.into(), &mut a_g2), B::add_entropy_g2(&entropy.into(), &mut b_g2));
+        assert_eq!(A::add_entropy_g2(&entropy.into(), &mut a_pubkey), B::add_entropy_g2(&entropy.into(), &mut b_pubkey));
+        assert_eq!(a_g1, b_g1);
+        assert_eq!(a_g2, b_g2);
+        assert_eq!(a_pubkey, b_pubkey);
+    });
+
+    // Linear combinations and openings
+    proptest!(ProptestConfig::with_cases(PAIRING_CASES), |(tau in arb_fr(), scalars in proptest::collection::vec(arb_fr(), NUM_G1), bad in arb_g1_encoding(), seed in any::<[u8; 32]>())| {
+        let randomness = Randomness::Seeded(seed);
+        let (g1, g2, _) = reference_powers(tau, NUM_G1, NUM_G2);
+        assert_eq!(A::g1_lincomb(&g1, &scalars), B::g1_lincomb(&g1, &scalars));
+        let mut with_bad = g1.clone();
+        with_bad[3] = bad;
+        assert_eq!(A::g1_lincomb(&with_bad, &scalars), B::g1_lincomb(&with_bad, &scalars));
+        let setup = KzgSetup { g1, tau: g2[1] };
+        let opening = setup.open::<A>(&scalars, scalars[0]).unwrap();
+        for proof in [opening.proof, bad] {
+            let openings = [KzgOpening { proof, ..opening }];
+            assert_eq!(A::verify_openings(setup.tau, &openings, randomness), B::verify_openings(setup.tau, &openings, randomness));
+        }
+    });
+}
+
+/// Instantiates the single-engine checks for an engine.
+macro_rules! conformance_tests {
+    ($module:ident, $feature:literal, $engine:ty) => {
+        #[cfg(feature = $feature)]
+        mod $module {
+            use super::*;
+
+            #[test]
+            fn validate() {
+                check_validate::<$engine>();
+            }
+
+            #[test]
+            fn pairings() {
+                check_pairings::<$engine>();
+            }
+
+            #[test]
+            fn add_entropy() {
+                check_add_entropy::<$engine>();
+            }
+
+            #[test]
+            fn kzg() {
+                check_kzg::<$engine>();
+            }
+        }
+    };
+}
+
+/// Instantiates the differential checks for a pair of engines.
+macro_rules! differential_tests {
+    ($name:ident, $feature_a:literal, $engine_a:ty, $feature_b:literal, $engine_b:ty) => {
+        #[cfg(all(feature = $feature_a, feature = $feature_b))]
+        #[test]
+        fn $name() {
+            check_agree::<$engine_a, $engine_b>();
+        }
+    };
+}
+
+conformance_tests!(arkworks, "arkworks", crate::Arkworks);
+conformance_tests!(blst, "blst", crate::Blst);
+differential_tests!(
+    arkworks_blst,
+    "arkworks",
+    crate::Arkworks,
+    "blst",
+    crate::Blst
+);
@@ -1,18 +1,18 @@
 //! Abstraction over the backend used for cryptographic operations.
-//!
-//! # To do
-//!
-//! * Add support for BLST backend.
-//! * Better API for passing entropy (`Secret<_>` etc.)
 
 #![allow(clippy::missing_errors_doc)] // TODO
 
 mod arkworks;
+mod blst;
+#[cfg(test)]
+mod conformance;
 
-use crate::{CeremonyError, G1, G2};
+use crate::{CeremonyError, Contribution, Entropy, KzgOpening, Randomness, Scalar, G1, G2};
 
 #[cfg(feature = "arkworks")]
 pub use self::arkworks::Arkworks;
+#[cfg(feature = "blst")]
+pub use self::blst::Blst;
 
 pub trait Engine {
     /// Verifies that the given G1 points are valid.
@@ -32,24 +32,60 @@ pub trait Engine {
     fn verify_pubkey(tau: G1, previous: G1, pubkey: G2) -> Result<(), CeremonyError>;
 
     /// Verify that `powers` contains a sequence of powers of `tau`.
-    fn verify_g1(powers: &[G1], tau: G2) -> Result<(), CeremonyError>;
+    ///
+    /// The `randomness` is used for the random linear combination.
+    fn verify_g1(powers: &[G1], tau: G2, randomness: Randomness) -> Result<(), CeremonyError>;
 
     /// Verify that `g1` and `g2` contain the same values.
-    fn verify_g2(g1: &[G1], g2: &[G2]) -> Result<(), CeremonyError>;
+    ///
+    /// The `randomness` is used for the random linear combination.
+    fn verify_g2(g1: &[G1], g2: &[G2], randomness: Randomness) -> Result<(), CeremonyError>;
+
+    /// Verify the pairing checks of [`Engine::verify_pubkey`],
+    /// [`Engine::verify_g1`] and [`Engine::verify_g2`] for a batch of
+    /// contributions at once.
+    ///
+    /// Each item is the previous `g1[1]` and the new contribution. The
+    /// contributions must pass [`Contribution::sanity_check`]. All checks are
+    /// combined with random factors and verified with a single multi-pairing,
+    /// so on failure this only returns [`CeremonyError::BatchPairingFailed`].
+    /// Use the individual checks to find out which one failed.
+    fn verify_batch(
+        contributions: &[(G1, &Contribution)],
+        randomness: Randomness,
+    ) -> Result<(), CeremonyError>;
 
     /// Derive a secret scalar $τ$ from the given entropy and multiply elements
     /// of `powers` by powers of $τ$.
-    fn add_entropy_g1(entropy: [u8; 32], powers: &mut [G1]) -> Result<(), CeremonyError>;
+    fn add_entropy_g1(entropy: &Entropy, powers: &mut [G1]) -> Result<(), CeremonyError>;
 
-    /// Derive a secret scalar $τ4 from the given entropy and multiply elements
+    /// Derive a secret scalar $τ$ from the given entropy and multiply elements
     /// of `powers` by powers of $τ$.
-    fn add_entropy_g2(entropy: [u8; 32], powers: &mut [G2]) -> Result<(), CeremonyError>;
+    fn add_entropy_g2(entropy: &Entropy, powers: &mut [G2]) -> Result<(), CeremonyError>;
+
+    /// Compute the linear combination of `points` with `scalars`.
+    ///
+    /// Both must have the same length.
+    fn g1_lincomb(points: &[G1], scalars: &[Scalar]) -> Result<G1, CeremonyError>;
+
+    /// Verify KZG opening proofs against the setup's `tau` in G2.
+    ///
+    /// Each opening is checked with `e(C - y·G1, G2) = e(π, τ - z·G2)`.
+    /// All checks are combined with random factors and verified with a single
+    /// multi-pairing, so on failure this only returns
+    /// [`CeremonyError::OpeningPairingFailed`].
+    fn verify_openings(
+        tau: G2,
+        openings: &[KzgOpening],
+        randomness: Randomness,
+    ) -> Result<(), CeremonyError>;
 }
 
 #[cfg(feature = "bench")]
 #[doc(hidden)]
 pub mod bench {
     use super::*;
+    use crate::BatchTranscript;
     use criterion::{BatchSize, BenchmarkId, Criterion};
     use rand::Rng;
     use std::iter;
@@ -57,6 +93,8 @@ pub mod bench {
     pub fn group(criterion: &mut Criterion) {
         #[cfg(feature = "arkworks")]
         arkworks::bench::group(criterion);
+        #[cfg(feature = "blst")]
+        blst::bench::group(criterion);
     }
 
     pub(super) fn bench_engine<E: Engine>(criterion: &mut Criterion, name: &str) {
@@ -65,6 +103,7 @@ pub mod bench {
         bench_verify_pubkey::<E>(criterion, name);
         bench_verify_g1::<E>(criterion, name);
         bench_verify_g2::<E>(criterion, name);
+        bench_verify_batch::<E>(criterion, name);
         bench_add_entropy_g1::<E>(criterion, name);
         bench_add_entropy_g2::<E>(criterion, name);
     }
@@ -80,9 +119,9 @@ pub mod bench {
         arkworks::bench::rand_g2().into()
     }
 
-    fn rand_entropy() -> [u8; 32] {
+    fn rand_entropy() -> Entropy {
         let mut rng = rand::thread_rng();
-        rng.gen()
+        Entropy::new(rng.gen())
     }
 
     fn bench_validate_g1<E: Engine>(criterion: &mut Criterion, name: &str) {
@@ -144,7 +183,7 @@ pub mod bench {
                                 rand_g2(),
                             )
                         },
-                        |(powers, tau)| E::verify_g1(powers, *tau),
+                        |(powers, tau)| E::verify_g1(powers, *tau, Randomness::Fresh),
                         BatchSize::LargeInput,
                     );
                 },
@@ -166,7 +205,7 @@ pub mod bench {
                                 iter::repeat(rand_g2()).take(size).collect::<Vec<_>>(),
                             )
                         },
-                        |(g1, g2)| E::verify_g2(g1, g2),
+                        |(g1, g2)| E::verify_g2(g1, g2, Randomness::Fresh),
                         BatchSize::LargeInput,
                     );
                 },
@@ -174,6 +213,31 @@ pub mod bench {
         }
     }
 
+    fn bench_verify_batch<E: Engine>(criterion: &mut Criterion, name: &str) {
+        let id = format!("engine/{name}/verify_batch");
+        criterion.bench_function(&id, move |bencher| {
+            bencher.iter_batched_ref(
+                || {
+                    let transcript =
+                        BatchTranscript::new(&[(4096, 65), (8192, 65), (16384, 65), (32768, 65)]);
+                    let mut contribution = transcript.contribution();
+                    contribution.add_entropy::<E>(&rand_entropy()).unwrap();
+                    (transcript, contribution)
+                },
+                |(transcript, contribution)| {
+                    let batch = transcript
+                        .transcripts
+                        .iter()
+                        .zip(&contribution.contributions)
+                        .map(|(t, c)| (t.powers.g1[1], c))
+                        .collect::<Vec<_>>();
+                    E::verify_batch(&batch, Randomness::Fresh).unwrap();
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
     fn bench_add_entropy_g1<E: Engine>(criterion: &mut Criterion, name: &str) {
         let id = format!("engine/{}/add_entropy_g1", name);
         for size in G1_SIZES {
@@ -188,7 +252,7 @@ pub mod bench {
                                 iter::repeat(rand_g1()).take(size).collect::<Vec<_>>(),
                             )
                         },
-                        |(entropy, powers)| E::add_entropy_g1(*entropy, powers).unwrap(),
+                        |(entropy, powers)| E::add_entropy_g1(entropy, powers).unwrap(),
                         BatchSize::LargeInput,
                     );
                 },
@@ -210,7 +274,7 @@ pub mod bench {
                                 iter::repeat(rand_g2()).take(size).collect::<Vec<_>>(),
                             )
                         },
-                        |(entropy, powers)| E::add_entropy_g2(*entropy, powers).unwrap(),
+                        |(entropy, powers)| E::add_entropy_g2(entropy, powers).unwrap(),
                         BatchSize::LargeInput,
                     );
                 },
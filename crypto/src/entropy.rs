@@ -0,0 +1,162 @@
+//! Secret entropy and the derivation of secret scalars from it.
+
+// The secret scalar is only used by the engines.
+#![cfg_attr(not(any(feature = "arkworks", feature = "blst")), allow(dead_code))]
+
+use ark_bls12_381::Fr;
+use ark_ff::PrimeField;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Domain separation tag for deriving per-ceremony entropy.
+const DERIVE_DST: &[u8] = b"KZG_CEREMONY_ENTROPY_V1";
+
+/// Domain separation tag for hashing entropy to the secret scalar $τ$.
+const SCALAR_DST: &[u8] = b"KZG_CEREMONY_BLS12381FR_XMD:SHA-256_V1";
+
+/// Bytes of uniform output per scalar, `ceil((ceil(log2(r)) + k) / 8)` with
+/// security parameter `k = 128`.
+const SCALAR_BYTES: usize = 48;
+
+/// Secret entropy for a contribution.
+///
+/// The bytes are erased from memory when the value is dropped and are never
+/// printed by [`fmt::Debug`]. The secret scalar $τ$ is derived using the
+/// `hash_to_field` method from RFC 9380 with `expand_message_xmd` over
+/// SHA-256.
+pub struct Entropy([u8; 32]);
+
+impl Entropy {
+    #[must_use]
+    pub const fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Derives independent entropy for the ceremony at `index`.
+    #[must_use]
+    pub fn derive(&self, index: usize) -> Self {
+        let mut message = Zeroizing::new([0_u8; 40]);
+        message[..32].copy_from_slice(&self.0);
+        message[32..].copy_from_slice(&(index as u64).to_le_bytes());
+        let bytes = expand_message_xmd(&message[..], DERIVE_DST, 32);
+        let mut result = Self([0; 32]);
+        result.0.copy_from_slice(&bytes);
+        result
+    }
+
+    /// The secret scalar $τ$.
+    pub(crate) fn tau(&self) -> Zeroizing<Fr> {
+        let bytes = expand_message_xmd(&self.0, SCALAR_DST, SCALAR_BYTES);
+        Zeroizing::new(Fr::from_be_bytes_mod_order(&bytes))
+    }
+}
+
+impl From<[u8; 32]> for Entropy {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl Drop for Entropy {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for Entropy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Entropy(..)")
+    }
+}
+
+/// `expand_message_xmd` from RFC 9380 section 5.3.1 with SHA-256.
+///
+/// # Panics
+///
+/// Panics if `len` exceeds `255 * 32` bytes or `dst` exceeds 255 bytes.
+fn expand_message_xmd(message: &[u8], dst: &[u8], len: usize) -> Zeroizing<Vec<u8>> {
+    let ell = u8::try_from((len + 31) / 32).expect("Output too long");
+    let len_bytes = u16::try_from(len).expect("Output too long").to_be_bytes();
+    let dst_len = u8::try_from(dst.len()).expect("Domain separation tag too long");
+    let dst_prime = |hasher: &mut Sha256| {
+        hasher.update(dst);
+        hasher.update([dst_len]);
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update([0_u8; 64]);
+    hasher.update(message);
+    hasher.update(len_bytes);
+    hasher.update([0_u8]);
+    dst_prime(&mut hasher);
+    let b_0 = Zeroizing::new(<[u8; 32]>::from(hasher.finalize()));
+
+    let mut output = Zeroizing::new(Vec::with_capacity(ell as usize * 32));
+    let mut b_i = Zeroizing::new([0_u8; 32]);
+    for i in 1..=ell {
+        let mut hasher = Sha256::new();
+        for (b, b_0) in b_i.iter_mut().zip(b_0.iter()) {
+            *b ^= b_0;
+        }
+        hasher.update(&b_i[..]);
+        hasher.update([i]);
+        dst_prime(&mut hasher);
+        b_i.copy_from_slice(&hasher.finalize());
+        output.extend_from_slice(&b_i[..]);
+    }
+    output.truncate(len);
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_expand_message_xmd() {
+        // Test vectors from RFC 9380 appendix K.1.
+        let dst = b"QUUX-V01-CS02-with-expander-SHA256-128";
+        assert_eq!(
+            expand_message_xmd(b"", dst, 0x20)[..],
+            hex!("68a985b87eb6b46952128911f2a4412bbc302a9d759667f87f7a21d803f07235")
+        );
+        assert_eq!(
+            expand_message_xmd(b"abc", dst, 0x20)[..],
+            hex!("d8ccab23b5985ccea865c6c97b6e5b8350e794e603b4b97902f53a8a0d605615")
+        );
+    }
+
+    #[cfg(feature = "blst")]
+    #[test]
+    fn test_expand_message_xmd_blst() {
+        for len in [32, 48, 200] {
+            let mut expected = vec![0_u8; len];
+            unsafe {
+                blst::blst_expand_message_xmd(
+                    expected.as_mut_ptr(),
+                    len,
+                    b"message".as_ptr(),
+                    7,
+                    SCALAR_DST.as_ptr(),
+                    SCALAR_DST.len(),
+                );
+            }
+            assert_eq!(
+                expand_message_xmd(b"message", SCALAR_DST, len)[..],
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_derive() {
+        let entropy = Entropy::from([1; 32]);
+        assert_ne!(entropy.derive(0).0, entropy.derive(1).0);
+        assert_eq!(entropy.derive(0).0, entropy.derive(0).0);
+        assert_ne!(entropy.derive(0).0, entropy.0);
+        assert_ne!(*entropy.derive(0).tau(), *entropy.tau());
+        assert_eq!(format!("{entropy:?}"), "Entropy(..)");
+    }
+}
@@ -0,0 +1,247 @@
+//! Sources of entropy for contributors, and a mixer that combines them.
+
+use crate::Entropy;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use zeroize::Zeroizing;
+
+/// Domain separation tag for mixing entropy sources.
+const MIX_DST: &[u8] = b"KZG_CEREMONY_ENTROPY_MIX_V1";
+
+/// The kind of an entropy source.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntropySourceKind {
+    /// The operating system random number generator.
+    OsRng,
+
+    /// Text typed by the user.
+    Text,
+
+    /// A byte stream, for example captured mouse movement.
+    Bytes,
+}
+
+impl EntropySourceKind {
+    const fn tag(self) -> u8 {
+        match self {
+            Self::OsRng => 0,
+            Self::Text => 1,
+            Self::Bytes => 2,
+        }
+    }
+}
+
+/// A source of secret bytes that can be mixed into [`Entropy`].
+pub trait EntropySource {
+    /// The kind of source, recorded by [`EntropyMixer`].
+    fn kind(&self) -> EntropySourceKind;
+
+    /// Returns the secret bytes from this source.
+    fn entropy(&mut self) -> Zeroizing<Vec<u8>>;
+}
+
+/// Entropy from the operating system random number generator.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct OsRngSource;
+
+impl EntropySource for OsRngSource {
+    fn kind(&self) -> EntropySourceKind {
+        EntropySourceKind::OsRng
+    }
+
+    fn entropy(&mut self) -> Zeroizing<Vec<u8>> {
+        let mut bytes = Zeroizing::new(vec![0; 32]);
+        OsRng.fill_bytes(&mut bytes);
+        bytes
+    }
+}
+
+/// Entropy from text typed by the user.
+pub struct TextSource(Zeroizing<String>);
+
+impl TextSource {
+    #[must_use]
+    pub fn new(text: String) -> Self {
+        Self(Zeroizing::new(text))
+    }
+}
+
+impl EntropySource for TextSource {
+    fn kind(&self) -> EntropySourceKind {
+        EntropySourceKind::Text
+    }
+
+    fn entropy(&mut self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(self.0.as_bytes().to_vec())
+    }
+}
+
+/// Entropy from an arbitrary byte stream.
+///
+/// Bytes are added with [`ByteStreamSource::update`] or through
+/// [`io::Write`], for example from mouse movement captured by a client. The
+/// stream is hashed as it arrives rather than buffered, so no copies of it
+/// are left behind in reallocated memory.
+#[derive(Default)]
+pub struct ByteStreamSource(Sha256);
+
+impl ByteStreamSource {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+impl io::Write for ByteStreamSource {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.update(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl EntropySource for ByteStreamSource {
+    fn kind(&self) -> EntropySourceKind {
+        EntropySourceKind::Bytes
+    }
+
+    fn entropy(&mut self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(self.0.clone().finalize().to_vec())
+    }
+}
+
+/// Hashes any number of entropy sources into a single [`Entropy`].
+///
+/// Each source is hashed with its kind and length, so the result depends on
+/// the kind, content and order of all sources. The result is as strong as
+/// the strongest source.
+pub struct EntropyMixer {
+    hasher: Sha256,
+    kinds:  Vec<EntropySourceKind>,
+}
+
+impl EntropyMixer {
+    #[must_use]
+    pub fn new() -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(MIX_DST);
+        Self {
+            hasher,
+            kinds: Vec::new(),
+        }
+    }
+
+    /// Mixes in the entropy from `source`.
+    pub fn add<S: EntropySource + ?Sized>(&mut self, source: &mut S) -> &mut Self {
+        let kind = source.kind();
+        let bytes = source.entropy();
+        self.hasher.update([kind.tag()]);
+        self.hasher.update((bytes.len() as u64).to_le_bytes());
+        self.hasher.update(&bytes[..]);
+        self.kinds.push(kind);
+        self
+    }
+
+    /// The kinds of the sources mixed in so far, in order.
+    #[must_use]
+    pub fn kinds(&self) -> &[EntropySourceKind] {
+        &self.kinds
+    }
+
+    /// Returns the mixed entropy, or `None` if no sources were added.
+    #[must_use]
+    pub fn finalize(self) -> Option<Entropy> {
+        if self.kinds.is_empty() {
+            return None;
+        }
+        let bytes = Zeroizing::new(<[u8; 32]>::from(self.hasher.finalize()));
+        Some(Entropy::new(*bytes))
+    }
+}
+
+impl Default for EntropyMixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ark_bls12_381::Fr;
+    use std::io::Write;
+
+    fn mix(sources: &mut [&mut dyn EntropySource]) -> Option<Entropy> {
+        let mut mixer = EntropyMixer::new();
+        for source in sources {
+            mixer.add(*source);
+        }
+        mixer.finalize()
+    }
+
+    fn tau(entropy: &Entropy) -> Fr {
+        *entropy.tau()
+    }
+
+    #[test]
+    fn test_mixer() {
+        let text = || TextSource::new("correct horse battery staple".to_string());
+        let stream = || {
+            let mut stream = ByteStreamSource::new();
+            stream.write_all(b"correct horse").unwrap();
+            stream.update(b" battery staple");
+            stream
+        };
+
+        let a = mix(&mut [&mut text(), &mut stream()]).unwrap();
+        assert_eq!(
+            tau(&a),
+            tau(&mix(&mut [&mut text(), &mut stream()]).unwrap())
+        );
+        assert_ne!(
+            tau(&a),
+            tau(&mix(&mut [&mut stream(), &mut text()]).unwrap())
+        );
+        assert_ne!(
+            tau(&mix(&mut [&mut text()]).unwrap()),
+            tau(&mix(&mut [&mut stream()]).unwrap())
+        );
+        assert_ne!(
+            tau(&a),
+            tau(&mix(&mut [&mut text(), &mut stream(), &mut OsRngSource]).unwrap())
+        );
+        assert_ne!(
+            tau(&mix(&mut [&mut OsRngSource]).unwrap()),
+            tau(&mix(&mut [&mut OsRngSource]).unwrap())
+        );
+        assert!(mix(&mut []).is_none());
+    }
+
+    #[test]
+    fn test_kinds() {
+        let mut mixer = EntropyMixer::new();
+        mixer
+            .add(&mut OsRngSource)
+            .add(&mut TextSource::new("text".to_string()))
+            .add(&mut ByteStreamSource::new());
+        assert_eq!(mixer.kinds(), [
+            EntropySourceKind::OsRng,
+            EntropySourceKind::Text,
+            EntropySourceKind::Bytes
+        ]);
+        assert_eq!(
+            serde_json::to_string(mixer.kinds()).unwrap(),
+            r#"["os_rng","text","bytes"]"#
+        );
+    }
+}
@@ -6,6 +6,24 @@ pub enum CeremoniesError {
     UnexpectedNumContributions(usize, usize),
     #[error("Error in contribution {0}: {1}")]
     InvalidCeremony(usize, #[source] CeremonyError),
+    #[error("Inconsistent number of contributions: ceremony 0 has {0}, ceremony {1} has {2}")]
+    InconsistentNumContributions(usize, usize, usize),
+    #[error("Ceremonies {0} and {1} have the same pubkey")]
+    DuplicatePubKey(usize, usize),
+    #[error("Ceremonies {0} and {1} have a G1 point in common")]
+    DuplicateG1(usize, usize),
+    #[error("Ceremonies {0} and {1} have a G2 point in common")]
+    DuplicateG2(usize, usize),
+    #[error("Transcript is finalized and accepts no more contributions")]
+    Finalized,
+    #[error("Transcript is not finalized with a beacon")]
+    MissingBeacon,
+    #[error("Last contribution to ceremony {0} is not the beacon")]
+    BeaconMismatch(usize),
+    #[error("Beacon has 2^{0} iterations, more than the supported maximum")]
+    BeaconTooManyIterations(u32),
+    #[error("Ceremony {0} does not start with the imported contribution")]
+    InconsistentImport(usize),
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
@@ -42,6 +60,8 @@ pub enum CeremonyError {
     G1PairingFailed,
     #[error("G2 pairing check failed")]
     G2PairingFailed,
+    #[error("Batched pairing check failed")]
+    BatchPairingFailed,
     #[error("pubkey is zero")]
     ZeroPubkey,
     #[error("g1[{0}] is zero")]
@@ -66,6 +86,38 @@ pub enum CeremonyError {
     ContributionNoEntropy,
     #[error("Mismatch in witness length: {0} products and {1} pubkeys")]
     WitnessLengthMismatch(usize, usize),
+    #[error("Witness is empty")]
+    EmptyWitness,
+    #[error("g1[{0}] must be the generator when there are no contributions")]
+    InvalidG1NoEntropy(usize),
+    #[error("g2[{0}] must be the generator when there are no contributions")]
+    InvalidG2NoEntropy(usize),
+    #[error("runningProducts[{0}] is zero")]
+    ZeroWitnessProduct(usize),
+    #[error("potPubkeys[{0}] is zero")]
+    ZeroWitnessPubKey(usize),
+    #[error("runningProducts[{0}] can not equal the generator")]
+    InvalidWitnessProductOne(usize),
+    #[error("potPubkeys[{0}] can not equal the generator")]
+    InvalidWitnessPubKeyOne(usize),
+    #[error("runningProducts[{0}] and runningProducts[{1}] are equal")]
+    DuplicateWitnessProduct(usize, usize),
+    #[error("potPubkeys[{0}] and potPubkeys[{1}] are equal")]
+    DuplicateWitnessPubKey(usize, usize),
+    #[error("g1[{0}] and runningProducts[{1}] are equal")]
+    DuplicateG1WitnessProduct(usize, usize),
+    #[error("g2[{0}] and potPubkeys[{1}] are equal")]
+    DuplicateG2WitnessPubKey(usize, usize),
+    #[error("runningProducts[0] must be the generator")]
+    InvalidWitnessFirstProduct,
+    #[error("potPubkeys[0] must be the generator")]
+    InvalidWitnessFirstPubKey,
+    #[error("Witness pairing check failed for contribution {0}")]
+    WitnessPairingFailed(usize),
+    #[error("Last running product does not equal g1[1]")]
+    WitnessProductMismatch,
+    #[error("KZG opening pairing check failed")]
+    OpeningPairingFailed,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
@@ -85,3 +137,63 @@ pub enum ParseError {
     #[error("curve point is not in prime order subgroup")]
     InvalidSubgroup,
 }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum BinaryError {
+    #[error("Invalid magic number")]
+    InvalidMagic,
+    #[error("Unsupported binary format version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+    #[error("Unexpected {0} trailing bytes")]
+    TrailingBytes(usize),
+    #[error("Beacon has 2^{0} iterations, more than the supported maximum")]
+    BeaconTooManyIterations(u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum TrustedSetupError {
+    #[error("Unexpected end of file")]
+    UnexpectedEnd,
+    #[error("Invalid number on line {0}")]
+    InvalidNumber(usize),
+    #[error("Invalid point on line {0}")]
+    InvalidPoint(usize),
+    #[error("Unexpected content on line {0}")]
+    TrailingContent(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum Phase1Error {
+    #[error("Invalid magic number")]
+    InvalidMagic,
+    #[error("Unsupported file format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Unexpected end of file")]
+    UnexpectedEnd,
+    #[error("Unexpected {0} trailing bytes")]
+    TrailingBytes(usize),
+    #[error("Missing section {0}")]
+    MissingSection(u32),
+    #[error("Duplicate section {0}")]
+    DuplicateSection(u32),
+    #[error("Section {0} has the wrong size")]
+    InvalidSectionSize(u32),
+    #[error("Curve is not BLS12-381")]
+    UnsupportedCurve,
+    #[error("Unsupported power {0}")]
+    UnsupportedPower(u32),
+    #[error("Invalid point at byte offset {0}")]
+    InvalidPoint(usize),
+    #[error("Invalid point: {0}")]
+    Ceremony(#[from] CeremonyError),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum KzgError {
+    #[error("Polynomial has {0} coefficients, but the setup supports at most {1}")]
+    TooManyCoefficients(usize, usize),
+    #[error("{0}")]
+    Ceremony(#[from] CeremonyError),
+}
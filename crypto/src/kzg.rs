@@ -0,0 +1,248 @@
+//! KZG polynomial commitments using the powers of a transcript.
+//!
+//! This is not meant to be a complete KZG library, but a way to check that a
+//! transcript works as a setup: commit to a polynomial, open it at points and
+//! verify the openings. Group operations go through the [`Engine`], scalar
+//! arithmetic is done with `ark-bls12-381`.
+
+use crate::{CeremonyError, Engine, KzgError, Randomness, Transcript, G1, G2};
+use ark_ff::Zero;
+use rayon::prelude::*;
+use tracing::instrument;
+
+/// An element of the BLS12-381 scalar field.
+pub type Scalar = ark_bls12_381::Fr;
+
+/// The powers of a transcript, used as a KZG setup.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KzgSetup {
+    /// Powers of tau in G1, in monomial form.
+    pub g1:  Vec<G1>,
+    /// Tau in G2.
+    pub tau: G2,
+}
+
+/// A claim that the committed polynomial evaluates to `y` at `z`, with proof.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KzgOpening {
+    pub commitment: G1,
+    pub z:          Scalar,
+    pub y:          Scalar,
+    pub proof:      G1,
+}
+
+impl KzgSetup {
+    /// Uses the powers of a transcript. Points are not validated.
+    #[must_use]
+    pub fn from_transcript(transcript: &Transcript) -> Self {
+        Self {
+            g1:  transcript.powers.g1.clone(),
+            tau: transcript.powers.g2[1],
+        }
+    }
+
+    /// The number of coefficients of the largest polynomial that can be
+    /// committed to.
+    #[must_use]
+    pub fn max_coefficients(&self) -> usize {
+        self.g1.len()
+    }
+
+    /// Commits to a polynomial given by its coefficients, lowest degree first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the polynomial is too large or a power can not be
+    /// parsed.
+    pub fn commit<E: Engine>(&self, polynomial: &[Scalar]) -> Result<G1, KzgError> {
+        if polynomial.len() > self.g1.len() {
+            return Err(KzgError::TooManyCoefficients(
+                polynomial.len(),
+                self.g1.len(),
+            ));
+        }
+        Ok(E::g1_lincomb(&self.g1[..polynomial.len()], polynomial)?)
+    }
+
+    /// Opens a polynomial at a single point.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the polynomial is too large or a power can not be
+    /// parsed.
+    pub fn open<E: Engine>(
+        &self,
+        polynomial: &[Scalar],
+        z: Scalar,
+    ) -> Result<KzgOpening, KzgError> {
+        let mut openings = self.open_batch::<E>(polynomial, &[z])?;
+        Ok(openings.remove(0))
+    }
+
+    /// Opens a polynomial at each of the given points, committing only once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the polynomial is too large or a power can not be
+    /// parsed.
+    #[instrument(level = "info", skip_all, fields(n=polynomial.len(), points=points.len()))]
+    pub fn open_batch<E: Engine>(
+        &self,
+        polynomial: &[Scalar],
+        points: &[Scalar],
+    ) -> Result<Vec<KzgOpening>, KzgError> {
+        let commitment = self.commit::<E>(polynomial)?;
+        points
+            .par_iter()
+            .map(|z| {
+                let (quotient, y) = divide_by_linear(polynomial, *z);
+                let proof = E::g1_lincomb(&self.g1[..quotient.len()], &quotient)?;
+                Ok(KzgOpening {
+                    commitment,
+                    z: *z,
+                    y,
+                    proof,
+                })
+            })
+            .collect()
+    }
+
+    /// Verifies a single opening.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the proof is invalid or a point can not be parsed.
+    pub fn verify<E: Engine>(&self, opening: &KzgOpening) -> Result<(), KzgError> {
+        self.verify_batch::<E>(&[*opening], Randomness::Fresh)
+    }
+
+    /// Verifies a batch of openings with a single pairing check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any proof is invalid or a point can not be parsed.
+    pub fn verify_batch<E: Engine>(
+        &self,
+        openings: &[KzgOpening],
+        randomness: Randomness,
+    ) -> Result<(), KzgError> {
+        Ok(E::verify_openings(self.tau, openings, randomness)?)
+    }
+
+    /// Commits to a random polynomial of maximal size, opens it at random
+    /// points and verifies the openings, both individually and batched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any step fails, which means the setup is not
+    /// usable.
+    #[instrument(level = "info", skip_all, fields(n=self.g1.len()))]
+    pub fn smoke_test<E: Engine>(&self, randomness: Randomness) -> Result<(), KzgError> {
+        let polynomial = randomness.scalars(b"smoke_test_polynomial", self.g1.len());
+        let points = randomness.scalars(b"smoke_test_points", 2);
+        let openings = self.open_batch::<E>(&polynomial, &points)?;
+        for opening in &openings {
+            self.verify::<E>(opening)?;
+        }
+        self.verify_batch::<E>(&openings, randomness)?;
+
+        // A wrong evaluation must be rejected.
+        let mut wrong = openings[0];
+        wrong.y += Scalar::from(1_u64);
+        match self.verify::<E>(&wrong) {
+            Err(KzgError::Ceremony(CeremonyError::OpeningPairingFailed)) => Ok(()),
+            Err(e) => Err(e),
+            Ok(()) => Err(CeremonyError::OpeningPairingFailed.into()),
+        }
+    }
+}
+
+/// Divides the polynomial by `X - z`, returning the quotient and the
+/// remainder, which is the evaluation at `z`.
+fn divide_by_linear(polynomial: &[Scalar], z: Scalar) -> (Vec<Scalar>, Scalar) {
+    let (constant, rest) = match polynomial.split_first() {
+        Some(split) => split,
+        None => return (Vec::new(), Scalar::zero()),
+    };
+    let mut quotient = vec![Scalar::zero(); rest.len()];
+    let mut acc = Scalar::zero();
+    for (q, p) in quotient.iter_mut().zip(rest).rev() {
+        acc = acc * z + p;
+        *q = acc;
+    }
+    (quotient, acc * z + constant)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_divide_by_linear() {
+        // (X^2 + 2X + 3) = (X + 4)(X - 2) + 11
+        let polynomial = [3, 2, 1].map(Scalar::from);
+        let (quotient, y) = divide_by_linear(&polynomial, Scalar::from(2_u64));
+        assert_eq!(quotient, [4, 1].map(Scalar::from));
+        assert_eq!(y, Scalar::from(11_u64));
+        assert_eq!(
+            divide_by_linear(&[], Scalar::from(2_u64)),
+            (vec![], Scalar::zero())
+        );
+    }
+
+    #[cfg(feature = "arkworks")]
+    mod arkworks {
+        use super::*;
+        use crate::Arkworks;
+
+        fn setup() -> KzgSetup {
+            let mut transcript = Transcript::new(8, 2);
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_entropy::<Arkworks>(&[3; 32].into())
+                .unwrap();
+            transcript.verify::<Arkworks>(&contribution).unwrap();
+            transcript.add(contribution);
+            KzgSetup::from_transcript(&transcript)
+        }
+
+        #[test]
+        fn test_smoke_test() {
+            let setup = setup();
+            setup
+                .smoke_test::<Arkworks>(Randomness::Seeded([1; 32]))
+                .unwrap();
+            setup.smoke_test::<Arkworks>(Randomness::Fresh).unwrap();
+        }
+
+        #[test]
+        fn test_open() {
+            let setup = setup();
+            let polynomial = [5, 0, 7].map(Scalar::from);
+            let opening = setup
+                .open::<Arkworks>(&polynomial, Scalar::from(2_u64))
+                .unwrap();
+            assert_eq!(opening.y, Scalar::from(33_u64));
+            setup.verify::<Arkworks>(&opening).unwrap();
+            let constant = setup
+                .open::<Arkworks>(&[Scalar::from(5_u64)], Scalar::from(2_u64))
+                .unwrap();
+            assert_eq!(constant.proof, G1::zero());
+            let openings = [opening, constant];
+            setup
+                .verify_batch::<Arkworks>(&openings, Randomness::Fresh)
+                .unwrap();
+
+            let mut swapped = openings;
+            swapped[0].commitment = constant.commitment;
+            assert_eq!(
+                setup.verify_batch::<Arkworks>(&swapped, Randomness::Fresh),
+                Err(CeremonyError::OpeningPairingFailed.into())
+            );
+            assert_eq!(
+                setup.commit::<Arkworks>(&[Scalar::zero(); 9]),
+                Err(KzgError::TooManyCoefficients(9, 8))
+            );
+        }
+    }
+}
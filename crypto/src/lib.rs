@@ -5,26 +5,51 @@
 
 mod batch_contribution;
 mod batch_transcript;
+mod beacon;
+mod binary;
 mod contribution;
 mod engine;
+mod entropy;
+mod entropy_source;
 mod error;
 mod group;
+mod kzg;
+mod phase1;
 mod powers;
+mod randomness;
 mod transcript;
+mod trusted_setup;
 
 pub use crate::{
     batch_contribution::BatchContribution,
     batch_transcript::BatchTranscript,
+    beacon::{Beacon, MAX_BEACON_LOG2_ITERATIONS},
+    binary::{BinaryFormat, BinaryReader, BinaryWriter, BINARY_VERSION},
     contribution::Contribution,
     engine::Engine,
-    error::{CeremoniesError, CeremonyError, ParseError},
+    entropy::Entropy,
+    entropy_source::{
+        ByteStreamSource, EntropyMixer, EntropySource, EntropySourceKind, OsRngSource, TextSource,
+    },
+    error::{
+        BinaryError, CeremoniesError, CeremonyError, KzgError, ParseError, Phase1Error,
+        TrustedSetupError,
+    },
     group::{G1, G2},
+    kzg::{KzgOpening, KzgSetup, Scalar},
     powers::Powers,
+    randomness::Randomness,
     transcript::Transcript,
 };
 
+#[cfg(feature = "blst")]
+pub use crate::engine::Blst;
 #[cfg(feature = "arkworks")]
-pub use crate::engine::Arkworks;
+pub use crate::{
+    engine::Arkworks,
+    phase1::{Phase1Accumulator, Ptau, ZcashChallenge, ZcashPublicKey, ZcashResponse},
+    trusted_setup::TrustedSetup,
+};
 
 #[cfg(feature = "bench")]
 #[doc(hidden)]
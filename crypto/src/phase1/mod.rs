@@ -0,0 +1,503 @@
+//! Import and export of Phase-1 powers of tau from other ceremonies.
+//!
+//! Supports the snarkjs `.ptau` layout ([`Ptau`]) and the `challenge` and
+//! `response` layouts of the Zcash / Filecoin `powersoftau` tool
+//! ([`ZcashChallenge`], [`ZcashResponse`]). All of them contain the same
+//! [`Phase1Accumulator`] of `2^power` powers, with `2^(power + 1) - 1` of them
+//! in G1.
+//!
+//! These formats store points uncompressed, so the base field arithmetic of
+//! arkworks is used to convert them. Imported points are validated using the
+//! [`Engine`].
+
+#![cfg(feature = "arkworks")]
+
+mod ptau;
+mod zcash;
+
+pub use self::{
+    ptau::Ptau,
+    zcash::{ZcashChallenge, ZcashPublicKey, ZcashResponse},
+};
+use crate::{CeremonyError, Engine, Phase1Error, Powers, G1, G2};
+use ark_bls12_381::{Fq, Fq2, FqParameters, G1Affine, G2Affine};
+use ark_ff::{BigInteger384, FpParameters, PrimeField, Zero};
+use rayon::prelude::*;
+
+/// Size of an uncompressed G1 point.
+const G1_UNCOMPRESSED: usize = 96;
+
+/// Size of an uncompressed G2 point.
+const G2_UNCOMPRESSED: usize = 192;
+
+/// Largest supported power, so that all lengths fit in `usize`.
+const MAX_POWER: u32 = 28;
+
+/// The Phase-1 parameters shared by all supported formats.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Phase1Accumulator {
+    pub tau_g1:       Vec<G1>,
+    pub tau_g2:       Vec<G2>,
+    pub alpha_tau_g1: Vec<G1>,
+    pub beta_tau_g1:  Vec<G1>,
+    pub beta_g2:      G2,
+}
+
+impl Phase1Accumulator {
+    /// The accumulator before any contributions, with all points generators.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `power` is larger than 28.
+    #[must_use]
+    pub fn new(power: u32) -> Self {
+        let (num_g1, num_g2) = num_powers(power).expect("Power too large");
+        Self {
+            tau_g1:       vec![G1::one(); num_g1],
+            tau_g2:       vec![G2::one(); num_g2],
+            alpha_tau_g1: vec![G1::one(); num_g2],
+            beta_tau_g1:  vec![G1::one(); num_g2],
+            beta_g2:      G2::one(),
+        }
+    }
+
+    /// The first `num_g1` and `num_g2` powers of tau, for seeding a
+    /// [`Transcript`](crate::Transcript).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the accumulator has fewer powers.
+    pub fn powers(&self, num_g1: usize, num_g2: usize) -> Result<Powers, CeremonyError> {
+        if self.tau_g1.len() < num_g1 {
+            return Err(CeremonyError::UnexpectedNumG1Powers(
+                num_g1,
+                self.tau_g1.len(),
+            ));
+        }
+        if self.tau_g2.len() < num_g2 {
+            return Err(CeremonyError::UnexpectedNumG2Powers(
+                num_g2,
+                self.tau_g2.len(),
+            ));
+        }
+        Ok(Powers {
+            g1: self.tau_g1[..num_g1].to_vec(),
+            g2: self.tau_g2[..num_g2].to_vec(),
+        })
+    }
+
+    /// Checks that the number of points matches `power` and that all points
+    /// are valid.
+    fn validate<E: Engine>(&self, power: u32) -> Result<(), Phase1Error> {
+        let (num_g1, num_g2) = num_powers(power)?;
+        if self.tau_g1.len() != num_g1 {
+            return Err(CeremonyError::UnexpectedNumG1Powers(num_g1, self.tau_g1.len()).into());
+        }
+        for points in [&self.alpha_tau_g1, &self.beta_tau_g1] {
+            if points.len() != num_g2 {
+                return Err(CeremonyError::UnexpectedNumG1Powers(num_g2, points.len()).into());
+            }
+        }
+        if self.tau_g2.len() != num_g2 {
+            return Err(CeremonyError::UnexpectedNumG2Powers(num_g2, self.tau_g2.len()).into());
+        }
+        E::validate_g1(&self.tau_g1)?;
+        E::validate_g1(&self.alpha_tau_g1)?;
+        E::validate_g1(&self.beta_tau_g1)?;
+        E::validate_g2(&self.tau_g2)?;
+        E::validate_g2(&[self.beta_g2])?;
+        Ok(())
+    }
+}
+
+/// Number of G1 and G2 powers for a given power of two.
+const fn num_powers(power: u32) -> Result<(usize, usize), Phase1Error> {
+    if power > MAX_POWER {
+        return Err(Phase1Error::UnsupportedPower(power));
+    }
+    let num_g2 = 1_usize << power;
+    Ok((2 * num_g2 - 1, num_g2))
+}
+
+/// Input buffer that keeps track of the offset for error reporting.
+struct Reader<'a> {
+    bytes:  &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Phase1Error> {
+        if self.bytes.len() < n {
+            return Err(Phase1Error::UnexpectedEnd);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        self.offset += n;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Phase1Error> {
+        let mut result = [0; N];
+        result.copy_from_slice(self.take(N)?);
+        Ok(result)
+    }
+
+    fn u32(&mut self) -> Result<u32, Phase1Error> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, Phase1Error> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    const fn finish(self) -> Result<(), Phase1Error> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(Phase1Error::TrailingBytes(self.bytes.len()))
+        }
+    }
+
+    /// Reads `n` points of `size` bytes each, decoding them in parallel.
+    fn points<T: Send>(
+        &mut self,
+        n: usize,
+        size: usize,
+        decode: impl Fn(&[u8]) -> Option<T> + Sync,
+    ) -> Result<Vec<T>, Phase1Error> {
+        let offset = self.offset;
+        self.take(n * size)?
+            .par_chunks_exact(size)
+            .enumerate()
+            .map(|(i, bytes)| decode(bytes).ok_or(Phase1Error::InvalidPoint(offset + i * size)))
+            .collect()
+    }
+
+    fn g1s(&mut self, n: usize, encoding: Encoding) -> Result<Vec<G1>, Phase1Error> {
+        self.points(n, G1_UNCOMPRESSED, |bytes| read_g1(bytes, encoding))
+    }
+
+    fn g2s(&mut self, n: usize, encoding: Encoding) -> Result<Vec<G2>, Phase1Error> {
+        self.points(n, G2_UNCOMPRESSED, |bytes| read_g2(bytes, encoding))
+    }
+}
+
+/// Encoding of uncompressed points.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Encoding {
+    /// Big-endian `x ‖ y` with flag bits in the first byte, extension field
+    /// elements written as `c1 ‖ c0`.
+    Zcash,
+    /// Little-endian `x ‖ y` in Montgomery form, extension field elements
+    /// written as `c0 ‖ c1`. The point at infinity is all zeros.
+    Snarkjs,
+}
+
+const FLAG_COMPRESSED: u8 = 0x80;
+const FLAG_INFINITY: u8 = 0x40;
+const FLAG_SIGN: u8 = 0x20;
+
+fn read_fq(bytes: &[u8], encoding: Encoding) -> Option<Fq> {
+    let mut limbs = [0_u64; 6];
+    match encoding {
+        Encoding::Zcash => {
+            for (limb, chunk) in limbs.iter_mut().rev().zip(bytes.chunks_exact(8)) {
+                *limb = u64::from_be_bytes(chunk.try_into().unwrap());
+            }
+        }
+        Encoding::Snarkjs => {
+            for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+                *limb = u64::from_le_bytes(chunk.try_into().unwrap());
+            }
+        }
+    }
+    let int = BigInteger384(limbs);
+    if int >= FqParameters::MODULUS {
+        return None;
+    }
+    match encoding {
+        Encoding::Zcash => Fq::from_repr(int),
+        Encoding::Snarkjs => Some(Fq::new(int)),
+    }
+}
+
+fn write_fq(fq: &Fq, encoding: Encoding, out: &mut Vec<u8>) {
+    match encoding {
+        Encoding::Zcash => {
+            for limb in fq.into_repr().0.iter().rev() {
+                out.extend_from_slice(&limb.to_be_bytes());
+            }
+        }
+        Encoding::Snarkjs => {
+            for limb in &(fq.0).0 {
+                out.extend_from_slice(&limb.to_le_bytes());
+            }
+        }
+    }
+}
+
+fn read_fq2(bytes: &[u8], encoding: Encoding) -> Option<Fq2> {
+    let (first, second) = bytes.split_at(48);
+    let first = read_fq(first, encoding)?;
+    let second = read_fq(second, encoding)?;
+    Some(match encoding {
+        Encoding::Zcash => Fq2::new(second, first),
+        Encoding::Snarkjs => Fq2::new(first, second),
+    })
+}
+
+fn write_fq2(fq2: &Fq2, encoding: Encoding, out: &mut Vec<u8>) {
+    match encoding {
+        Encoding::Zcash => {
+            write_fq(&fq2.c1, encoding, out);
+            write_fq(&fq2.c0, encoding, out);
+        }
+        Encoding::Snarkjs => {
+            write_fq(&fq2.c0, encoding, out);
+            write_fq(&fq2.c1, encoding, out);
+        }
+    }
+}
+
+/// Strips the flags of a Zcash encoded point. The point at infinity is
+/// returned as all zeros, which is not a valid affine point.
+fn strip_flags<const N: usize>(bytes: &[u8], encoding: Encoding) -> Option<[u8; N]> {
+    let mut bytes: [u8; N] = bytes.try_into().ok()?;
+    if encoding == Encoding::Zcash {
+        let flags = bytes[0];
+        if flags & (FLAG_COMPRESSED | FLAG_SIGN) != 0 {
+            return None;
+        }
+        bytes[0] &= !FLAG_INFINITY;
+        if (flags & FLAG_INFINITY != 0) != is_zero(&bytes) {
+            return None;
+        }
+    }
+    Some(bytes)
+}
+
+fn is_zero(bytes: &[u8]) -> bool {
+    bytes.iter().all(|b| *b == 0)
+}
+
+/// Sets the flag for the point at infinity.
+fn write_infinity(size: usize, encoding: Encoding, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.resize(start + size, 0);
+    if encoding == Encoding::Zcash {
+        out[start] = FLAG_INFINITY;
+    }
+}
+
+/// Decodes an uncompressed G1 point, checking that it is on the curve.
+fn read_g1(bytes: &[u8], encoding: Encoding) -> Option<G1> {
+    let bytes = strip_flags::<G1_UNCOMPRESSED>(bytes, encoding)?;
+    if is_zero(&bytes) {
+        return Some(G1::zero());
+    }
+    let x = read_fq(&bytes[..48], encoding)?;
+    let y = read_fq(&bytes[48..], encoding)?;
+    let point = G1Affine::new(x, y, false);
+    point.is_on_curve().then(|| point.into())
+}
+
+/// Decodes an uncompressed G2 point, checking that it is on the curve.
+fn read_g2(bytes: &[u8], encoding: Encoding) -> Option<G2> {
+    let bytes = strip_flags::<G2_UNCOMPRESSED>(bytes, encoding)?;
+    if is_zero(&bytes) {
+        return Some(G2::zero());
+    }
+    let x = read_fq2(&bytes[..96], encoding)?;
+    let y = read_fq2(&bytes[96..], encoding)?;
+    let point = G2Affine::new(x, y, false);
+    point.is_on_curve().then(|| point.into())
+}
+
+/// Encodes G1 points uncompressed.
+///
+/// # Errors
+///
+/// Returns an error if a point can not be decompressed.
+fn write_g1s(points: &[G1], encoding: Encoding, out: &mut Vec<u8>) -> Result<(), CeremonyError> {
+    let encoded = points
+        .par_iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let point =
+                G1Affine::try_from(*point).map_err(|e| CeremonyError::InvalidG1Power(i, e))?;
+            let mut out = Vec::with_capacity(G1_UNCOMPRESSED);
+            if point.is_zero() {
+                write_infinity(G1_UNCOMPRESSED, encoding, &mut out);
+            } else {
+                write_fq(&point.x, encoding, &mut out);
+                write_fq(&point.y, encoding, &mut out);
+            }
+            Ok(out)
+        })
+        .collect::<Result<Vec<_>, CeremonyError>>()?;
+    out.extend(encoded.into_iter().flatten());
+    Ok(())
+}
+
+/// Encodes G2 points uncompressed.
+///
+/// # Errors
+///
+/// Returns an error if a point can not be decompressed.
+fn write_g2s(points: &[G2], encoding: Encoding, out: &mut Vec<u8>) -> Result<(), CeremonyError> {
+    let encoded = points
+        .par_iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let point =
+                G2Affine::try_from(*point).map_err(|e| CeremonyError::InvalidG2Power(i, e))?;
+            let mut out = Vec::with_capacity(G2_UNCOMPRESSED);
+            if point.is_zero() {
+                write_infinity(G2_UNCOMPRESSED, encoding, &mut out);
+            } else {
+                write_fq2(&point.x, encoding, &mut out);
+                write_fq2(&point.y, encoding, &mut out);
+            }
+            Ok(out)
+        })
+        .collect::<Result<Vec<_>, CeremonyError>>()?;
+    out.extend(encoded.into_iter().flatten());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Arkworks, Transcript};
+    use ark_bls12_381::Fr;
+    use ark_ec::{AffineCurve, ProjectiveCurve};
+    use hex_literal::hex;
+
+    // Multiples of the generators, compressed
+    pub const G1_1: G1 = G1(hex!("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb"));
+    pub const G1_2: G1 = G1(hex!("a572cbea904d67468808c8eb50a9450c9721db309128012543902d0ac358a62ae28f75bb8f1c7c42c39a8c5529bf0f4e"));
+    pub const G1_3: G1 = G1(hex!("89ece308f9d1f0131765212deca99697b112d61f9be9a5f1f3780a51335b3ff981747a0b2ca2179b96d2c0c9024e5224"));
+    pub const G1_4: G1 = G1(hex!("ac9b60d5afcbd5663a8a44b7c5a02f19e9a77ab0a35bd65809bb5c67ec582c897feb04decc694b13e08587f3ff9b5b60"));
+    pub const G1_6: G1 = G1(hex!("a6e82f6da4520f85c5d27d8f329eccfa05944fd1096b20734c894966d12a9e2a9a9744529d7212d33883113a0cadb909"));
+    pub const G2_1: G2 = G2(hex!(
+        "93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e
+        024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8"
+    ));
+    pub const G2_2: G2 = G2(hex!(
+        "aa4edef9c1ed7f729f520e47730a124fd70662a904ba1074728114d1031e1572c6c886f6b57ec72a6178288c47c33577
+        1638533957d540a9d2370f17cc7ed5863bc0b995b8825e0ee1ea1e1e4d00dbae81f14b0bf3611b78c952aacab827a053"
+    ));
+
+    /// The accumulator of the known-answer tests, with `power` 1 for τ = 2,
+    /// α = 3 and β = 1.
+    pub fn known_accumulator() -> Phase1Accumulator {
+        Phase1Accumulator {
+            tau_g1:       vec![G1_1, G1_2, G1_4],
+            tau_g2:       vec![G2_1, G2_2],
+            alpha_tau_g1: vec![G1_3, G1_6],
+            beta_tau_g1:  vec![G1_1, G1_2],
+            beta_g2:      G2_1,
+        }
+    }
+
+    /// An accumulator with a real tau in the tau powers.
+    pub fn accumulator(power: u32) -> Phase1Accumulator {
+        let (num_g1, num_g2) = num_powers(power).unwrap();
+        let mut transcript = Transcript::new(num_g1 + 1, num_g2);
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[u8::try_from(power).unwrap(); 32].into())
+            .unwrap();
+        transcript.verify::<Arkworks>(&contribution).unwrap();
+        transcript.add(contribution);
+        let mut accumulator = Phase1Accumulator::new(power);
+        accumulator.tau_g1 = transcript.powers.g1[..num_g1].to_vec();
+        accumulator.tau_g2 = transcript.powers.g2;
+        accumulator.alpha_tau_g1[0] = G1::zero();
+        accumulator
+    }
+
+    #[test]
+    fn test_known_points() {
+        let g1 = G1Affine::prime_subgroup_generator();
+        for (k, point) in [(1, G1_1), (2, G1_2), (3, G1_3), (4, G1_4), (6, G1_6)] {
+            let expected = g1.mul(Fr::from(k).into_repr()).into_affine();
+            assert_eq!(G1::from(expected), point);
+        }
+        let g2 = G2Affine::prime_subgroup_generator();
+        for (k, point) in [(1, G2_1), (2, G2_2)] {
+            let expected = g2.mul(Fr::from(k).into_repr()).into_affine();
+            assert_eq!(G2::from(expected), point);
+        }
+    }
+
+    #[test]
+    fn test_point_roundtrip() {
+        let accumulator = accumulator(1);
+        for encoding in [Encoding::Zcash, Encoding::Snarkjs] {
+            let mut bytes = Vec::new();
+            write_g1s(&accumulator.alpha_tau_g1, encoding, &mut bytes).unwrap();
+            write_g1s(&accumulator.tau_g1, encoding, &mut bytes).unwrap();
+            write_g2s(&accumulator.tau_g2, encoding, &mut bytes).unwrap();
+            write_g2s(&[G2::zero()], encoding, &mut bytes).unwrap();
+            let mut reader = Reader::new(&bytes);
+            assert_eq!(reader.g1s(2, encoding).unwrap(), accumulator.alpha_tau_g1);
+            assert_eq!(reader.g1s(3, encoding).unwrap(), accumulator.tau_g1);
+            assert_eq!(reader.g2s(2, encoding).unwrap(), accumulator.tau_g2);
+            assert_eq!(reader.g2s(1, encoding).unwrap(), vec![G2::zero()]);
+            assert_eq!(reader.finish(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_invalid_point() {
+        for encoding in [Encoding::Zcash, Encoding::Snarkjs] {
+            let mut bytes = Vec::new();
+            write_g1s(&[G1::one(), G1::one()], encoding, &mut bytes).unwrap();
+            // Off the curve
+            bytes[G1_UNCOMPRESSED + 50] ^= 1;
+            assert_eq!(
+                Reader::new(&bytes).g1s(2, encoding),
+                Err(Phase1Error::InvalidPoint(G1_UNCOMPRESSED))
+            );
+            // Not reduced
+            bytes[..48].fill(0xff);
+            if encoding == Encoding::Zcash {
+                bytes[0] = 0x1f;
+            }
+            assert_eq!(
+                Reader::new(&bytes).g1s(2, encoding),
+                Err(Phase1Error::InvalidPoint(0))
+            );
+        }
+        // Compressed flag
+        let mut bytes = Vec::new();
+        write_g1s(&[G1::one()], Encoding::Zcash, &mut bytes).unwrap();
+        bytes[0] |= FLAG_COMPRESSED;
+        assert_eq!(
+            Reader::new(&bytes).g1s(1, Encoding::Zcash),
+            Err(Phase1Error::InvalidPoint(0))
+        );
+    }
+
+    #[test]
+    fn test_seed_transcript() {
+        let accumulator = accumulator(2);
+        accumulator.validate::<Arkworks>(2).unwrap();
+        assert_eq!(
+            accumulator.validate::<Arkworks>(3),
+            Err(CeremonyError::UnexpectedNumG1Powers(15, 7).into())
+        );
+        assert_eq!(
+            accumulator.powers(8, 4),
+            Err(CeremonyError::UnexpectedNumG1Powers(8, 7))
+        );
+        let transcript = Transcript::from_powers(accumulator.powers(4, 2).unwrap());
+        assert_eq!(transcript.num_contributions(), 1);
+        transcript.verify_full::<Arkworks>().unwrap();
+    }
+}
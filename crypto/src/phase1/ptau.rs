@@ -0,0 +1,327 @@
+//! The snarkjs `.ptau` format.
+//!
+//! The file starts with the magic `ptau`, a `u32` version and the number of
+//! sections. Each section is a `u32` type, a `u64` size and the data. All
+//! integers are little-endian and points are uncompressed in Montgomery form.
+//!
+//! ```text
+//! 1: header        n8 q power ceremonyPower
+//! 2: tauG1         2^(power + 1) - 1 points
+//! 3: tauG2         2^power points
+//! 4: alphaTauG1    2^power points
+//! 5: betaTauG1     2^power points
+//! 6: betaG2        1 point
+//! 7: contributions
+//! ```
+//!
+//! The contribution history is not imported, and exported as empty. Other
+//! sections, like the Lagrange form written by `snarkjs powersoftau
+//! prepare phase2`, are ignored.
+
+use super::{
+    num_powers, write_g1s, write_g2s, Encoding, Phase1Accumulator, Reader, G1_UNCOMPRESSED,
+    G2_UNCOMPRESSED,
+};
+use crate::{CeremonyError, Engine, Phase1Error};
+use ark_bls12_381::FqParameters;
+use ark_ff::FpParameters;
+use tracing::instrument;
+
+const MAGIC: [u8; 4] = *b"ptau";
+const VERSION: u32 = 1;
+const NUM_SECTIONS: u32 = 7;
+const FQ_BYTES: u32 = 48;
+
+/// A snarkjs powers of tau file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Ptau {
+    pub power:          u32,
+    pub ceremony_power: u32,
+    pub accumulator:    Phase1Accumulator,
+}
+
+impl Ptau {
+    /// Decodes a `.ptau` file and validates all points.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is malformed, is not for BLS12-381, or
+    /// contains an invalid point.
+    #[instrument(level = "info", skip_all, fields(size=bytes.len()))]
+    pub fn read<E: Engine>(bytes: &[u8]) -> Result<Self, Phase1Error> {
+        let mut reader = Reader::new(bytes);
+        if reader.array()? != MAGIC {
+            return Err(Phase1Error::InvalidMagic);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(Phase1Error::UnsupportedVersion(version));
+        }
+        let mut sections: [Option<Reader>; NUM_SECTIONS as usize] = Default::default();
+        for _ in 0..reader.u32()? {
+            let id = reader.u32()?;
+            let size = usize::try_from(reader.u64()?).map_err(|_| Phase1Error::UnexpectedEnd)?;
+            let offset = reader.offset;
+            let data = reader.take(size)?;
+            if let Some(section) = sections.get_mut((id as usize).wrapping_sub(1)) {
+                if section
+                    .replace(Reader {
+                        bytes: data,
+                        offset,
+                    })
+                    .is_some()
+                {
+                    return Err(Phase1Error::DuplicateSection(id));
+                }
+            }
+        }
+        reader.finish()?;
+        let mut section = |id: u32| {
+            sections[id as usize - 1]
+                .take()
+                .ok_or(Phase1Error::MissingSection(id))
+        };
+
+        let mut header = section(1)?;
+        if header.u32()? != FQ_BYTES || header.take(FQ_BYTES as usize)? != modulus() {
+            return Err(Phase1Error::UnsupportedCurve);
+        }
+        let power = header.u32()?;
+        let ceremony_power = header.u32()?;
+        header.finish()?;
+        let (num_g1, num_g2) = num_powers(power)?;
+
+        let accumulator = Phase1Accumulator {
+            tau_g1:       read_section(section(2)?, 2, |r| r.g1s(num_g1, Encoding::Snarkjs))?,
+            tau_g2:       read_section(section(3)?, 3, |r| r.g2s(num_g2, Encoding::Snarkjs))?,
+            alpha_tau_g1: read_section(section(4)?, 4, |r| r.g1s(num_g2, Encoding::Snarkjs))?,
+            beta_tau_g1:  read_section(section(5)?, 5, |r| r.g1s(num_g2, Encoding::Snarkjs))?,
+            beta_g2:      read_section(section(6)?, 6, |r| r.g2s(1, Encoding::Snarkjs))?[0],
+        };
+        section(7)?;
+        accumulator.validate::<E>(power)?;
+        Ok(Self {
+            power,
+            ceremony_power,
+            accumulator,
+        })
+    }
+
+    /// Encodes as a `.ptau` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a point can not be decompressed.
+    #[instrument(level = "info", skip_all, fields(power=self.power))]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CeremonyError> {
+        let accumulator = &self.accumulator;
+        let mut out = Vec::with_capacity(
+            64 + (accumulator.tau_g1.len() + 2 * accumulator.alpha_tau_g1.len()) * G1_UNCOMPRESSED
+                + (accumulator.tau_g2.len() + 1) * G2_UNCOMPRESSED,
+        );
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&NUM_SECTIONS.to_le_bytes());
+        section(&mut out, 1, |out| {
+            out.extend_from_slice(&FQ_BYTES.to_le_bytes());
+            out.extend_from_slice(&modulus());
+            out.extend_from_slice(&self.power.to_le_bytes());
+            out.extend_from_slice(&self.ceremony_power.to_le_bytes());
+            Ok(())
+        })?;
+        section(&mut out, 2, |out| {
+            write_g1s(&accumulator.tau_g1, Encoding::Snarkjs, out)
+        })?;
+        section(&mut out, 3, |out| {
+            write_g2s(&accumulator.tau_g2, Encoding::Snarkjs, out)
+        })?;
+        section(&mut out, 4, |out| {
+            write_g1s(&accumulator.alpha_tau_g1, Encoding::Snarkjs, out)
+        })?;
+        section(&mut out, 5, |out| {
+            write_g1s(&accumulator.beta_tau_g1, Encoding::Snarkjs, out)
+        })?;
+        section(&mut out, 6, |out| {
+            write_g2s(&[accumulator.beta_g2], Encoding::Snarkjs, out)
+        })?;
+        section(&mut out, 7, |out| {
+            out.extend_from_slice(&0_u32.to_le_bytes());
+            Ok(())
+        })?;
+        Ok(out)
+    }
+}
+
+/// Reads the full contents of a section.
+fn read_section<T>(
+    mut reader: Reader,
+    id: u32,
+    read: impl FnOnce(&mut Reader) -> Result<T, Phase1Error>,
+) -> Result<T, Phase1Error> {
+    let result = read(&mut reader).map_err(|e| match e {
+        Phase1Error::UnexpectedEnd => Phase1Error::InvalidSectionSize(id),
+        e => e,
+    })?;
+    reader
+        .finish()
+        .map_err(|_| Phase1Error::InvalidSectionSize(id))?;
+    Ok(result)
+}
+
+/// Writes a section header, filling in the size after writing the data.
+fn section(
+    out: &mut Vec<u8>,
+    id: u32,
+    write: impl FnOnce(&mut Vec<u8>) -> Result<(), CeremonyError>,
+) -> Result<(), CeremonyError> {
+    out.extend_from_slice(&id.to_le_bytes());
+    let size_offset = out.len();
+    out.extend_from_slice(&0_u64.to_le_bytes());
+    write(out)?;
+    let size = (out.len() - size_offset - 8) as u64;
+    out[size_offset..size_offset + 8].copy_from_slice(&size.to_le_bytes());
+    Ok(())
+}
+
+/// The base field modulus, little-endian.
+fn modulus() -> Vec<u8> {
+    FqParameters::MODULUS
+        .0
+        .iter()
+        .flat_map(|limb| limb.to_le_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        super::test::{accumulator, known_accumulator},
+        *,
+    };
+    use crate::Arkworks;
+    use hex_literal::hex;
+
+    fn ptau() -> Ptau {
+        Ptau {
+            power:          2,
+            ceremony_power: 28,
+            accumulator:    accumulator(2),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let ptau = ptau();
+        let bytes = ptau.to_bytes().unwrap();
+        assert_eq!(&bytes[..12], b"ptau\x01\x00\x00\x00\x07\x00\x00\x00");
+        assert_eq!(
+            bytes.len(),
+            12 + 7 * 12 + 60 + 4 + (7 + 4 + 4) * 96 + (4 + 1) * 192
+        );
+        assert_eq!(Ptau::read::<Arkworks>(&bytes), Ok(ptau));
+    }
+
+    #[test]
+    fn test_known_answer() {
+        // Points of `known_accumulator`, little-endian in Montgomery form
+        const G1_1: [u8; 96] = hex!(
+            "160c53fd9087b35cf5ff769967fc1778c1a13b14c7954f1547e7d0f3cd6aaef040f4db21cc6eceed75fb0b9e41770112
+            7122e70cd593acba8efd18791a63228cce250757135f59dd945140502958ac51c05900ad3f8c1c0e6aa20850fc3ebc0b"
+        );
+        const G1_2: [u8; 96] = hex!(
+            "3cbaa958ce78e953f9653d4f3c58a03e602901f047bb204dd9b5b2e54a664ca51fb27e9da352b5268587e6265d890800
+            40392998320b1170fc6a1f3f39c533da85a75a6ad1df6eb895c8b1e7c9d1c6ae2017d122b5c2cf25159bd0f8831c3606"
+        );
+        const G1_3: [u8; 96] = hex!(
+            "8293e03e4b3680ce66076e3a1b724e7ee0074af79e9a25cfd079ff5200b4d073baed0bb346656b6ca00857410613be10
+            68510c151f417af151dd9300595191d64972aef1830771ad6961ebd327a123e1a1219c3cf5f6c62d395aa1d0589a3816"
+        );
+        const G1_4: [u8; 96] = hex!(
+            "cb8eb692a79b820b2003a68cacbe41406eb13d746995ee0430ec363f3d0c40c64b118704c3632d0be743464f45860008
+            101e2a2dca8ec5ead9187348ff6ad8116f4e80a660b9ce74b398329ddb71c4de8c6531d47c213dec3bfaeaa4edb3bd18"
+        );
+        const G1_6: [u8; 96] = hex!(
+            "de172213f16d8ca805a7f52647758660f57ac8e4aba4e8fbf881f862e34821b8fd6e59f8e34bdd29aff01b77777da10d
+            f91bd84bde63c72f03a422e627d198eec102a1cf3c80d7725f54bd6ad90f118a14750ce9f44305ffede0dc37c98b1115"
+        );
+        const G2_1: [u8; 192] = hex!(
+            "100a9402a28ff2f51a96b48726fbf5b380e52a3eb593a8a1e9ae3c1a9d9994986b36631863b7676fd7bc504392918105
+            06f6239e75c0a9a5c360cdbc9dc5a0aa067886e2187eb13b67b34185ccb61a1b478515f20eedb6c2f3ed6073092a9211
+            4a4c4960f80a734c5a9c365e1ffa7c595a630aaa6c85e6e75f490d6ee9b5efbba225eff075a9d307e5da807e8efd8300
+            5db064df92fcc0addc61142b0a27aa18a0ebe43b6aacad863aa33dc94e5c4979edca3ca4505817e7f21bde63a1c22b0b"
+        );
+        const G2_2: [u8; 192] = hex!(
+            "8bf92096dae2d9e9367fb9469319f15427ed6b3720b8b33d4c4fb6b0c931dbcf9344358627c1d74164c055c294077105
+            6ed0a06ecad3c1d69f48955590bd0cda1d227934d452534fe0978c6f735dde8a0ef75e923384cc4881ef91ea71ead708
+            6f180d4beb26ba151ee0e9b7646d080d784c2f65dd48b8c84fae3b12a646cfee2a81dcb6d88d5e253ff9dc21af424116
+            b44d9895a8a1b4f948f7cfcc14b117d46e089fc81f305668dae331898777c74105216a0655b15635cf89cb25d3f7ac00"
+        );
+        const MODULUS: [u8; 48] = hex!("abaafffffffffeb9ffff53b1feffab1e24f6b0f6a0d23067bf1285f3844b7764d7ac4b43b6a71b4b9ae67f39ea11011a");
+
+        let section = |id: u32, parts: &[&[u8]]| {
+            let data = parts.concat();
+            [
+                &id.to_le_bytes()[..],
+                &(data.len() as u64).to_le_bytes(),
+                &data,
+            ]
+            .concat()
+        };
+        let header = [
+            &48_u32.to_le_bytes()[..],
+            &MODULUS,
+            &1_u32.to_le_bytes(),
+            &2_u32.to_le_bytes(),
+        ];
+        let bytes = [
+            &b"ptau\x01\x00\x00\x00\x07\x00\x00\x00"[..],
+            &section(1, &header),
+            &section(2, &[&G1_1, &G1_2, &G1_4]),
+            &section(3, &[&G2_1, &G2_2]),
+            &section(4, &[&G1_3, &G1_6]),
+            &section(5, &[&G1_1, &G1_2]),
+            &section(6, &[&G2_1]),
+            &section(7, &[&0_u32.to_le_bytes()]),
+        ]
+        .concat();
+
+        let ptau = Ptau::read::<Arkworks>(&bytes).unwrap();
+        assert_eq!(ptau.power, 1);
+        assert_eq!(ptau.ceremony_power, 2);
+        assert_eq!(ptau.accumulator, known_accumulator());
+        assert_eq!(ptau.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = ptau().to_bytes().unwrap();
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'x';
+        assert_eq!(
+            Ptau::read::<Arkworks>(&wrong_magic),
+            Err(Phase1Error::InvalidMagic)
+        );
+        let mut wrong_curve = bytes.clone();
+        wrong_curve[28] ^= 1;
+        assert_eq!(
+            Ptau::read::<Arkworks>(&wrong_curve),
+            Err(Phase1Error::UnsupportedCurve)
+        );
+        let mut wrong_power = bytes.clone();
+        wrong_power[76] = 3;
+        assert_eq!(
+            Ptau::read::<Arkworks>(&wrong_power),
+            Err(Phase1Error::InvalidSectionSize(2))
+        );
+        assert_eq!(
+            Ptau::read::<Arkworks>(&bytes[..bytes.len() - 1]),
+            Err(Phase1Error::UnexpectedEnd)
+        );
+        let mut missing = bytes;
+        missing[8] = 6;
+        assert_eq!(
+            Ptau::read::<Arkworks>(&missing[..missing.len() - 16]),
+            Err(Phase1Error::MissingSection(7))
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! The `challenge` and `response` files of the Zcash `powersoftau` tool, as
+//! also used by Filecoin.
+//!
+//! Both start with a 64 byte `BLAKE2b` hash of the previous file, followed by
+//! the accumulator: tau in G1, tau in G2, alpha tau in G1, beta tau in G1 and
+//! beta in G2. A challenge stores points uncompressed. A response stores them
+//! compressed and ends with the contributor's public key, which is
+//! uncompressed.
+//!
+//! The number of powers is not stored in the files and must be given. Hashes
+//! are passed through as-is and not verified.
+
+use super::{
+    num_powers, write_g1s, write_g2s, Encoding, Phase1Accumulator, Reader, G1_UNCOMPRESSED,
+    G2_UNCOMPRESSED,
+};
+use crate::{CeremonyError, Engine, Phase1Error, G1, G2};
+use tracing::instrument;
+
+/// A challenge file, the input to a contribution.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ZcashChallenge {
+    pub hash:        [u8; 64],
+    pub accumulator: Phase1Accumulator,
+}
+
+/// A response file, the output of a contribution.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ZcashResponse {
+    pub hash:        [u8; 64],
+    pub accumulator: Phase1Accumulator,
+    pub public_key:  ZcashPublicKey,
+}
+
+/// Proof of knowledge of a contribution's tau, alpha and beta.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ZcashPublicKey {
+    pub tau_g1:   [G1; 2],
+    pub alpha_g1: [G1; 2],
+    pub beta_g1:  [G1; 2],
+    pub tau_g2:   G2,
+    pub alpha_g2: G2,
+    pub beta_g2:  G2,
+}
+
+impl ZcashChallenge {
+    /// Decodes a challenge with `2^power` powers and validates all points.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file has the wrong size or contains an invalid
+    /// point.
+    #[instrument(level = "info", skip_all, fields(size=bytes.len(), power))]
+    pub fn read<E: Engine>(bytes: &[u8], power: u32) -> Result<Self, Phase1Error> {
+        let (num_g1, num_g2) = num_powers(power)?;
+        let mut reader = Reader::new(bytes);
+        let hash = reader.array()?;
+        let accumulator = Phase1Accumulator {
+            tau_g1:       reader.g1s(num_g1, Encoding::Zcash)?,
+            tau_g2:       reader.g2s(num_g2, Encoding::Zcash)?,
+            alpha_tau_g1: reader.g1s(num_g2, Encoding::Zcash)?,
+            beta_tau_g1:  reader.g1s(num_g2, Encoding::Zcash)?,
+            beta_g2:      reader.g2s(1, Encoding::Zcash)?[0],
+        };
+        reader.finish()?;
+        accumulator.validate::<E>(power)?;
+        Ok(Self { hash, accumulator })
+    }
+
+    /// Encodes as a challenge file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a point can not be decompressed.
+    #[instrument(level = "info", skip_all)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CeremonyError> {
+        let accumulator = &self.accumulator;
+        let mut out = Vec::with_capacity(
+            64 + (accumulator.tau_g1.len() + 2 * accumulator.alpha_tau_g1.len()) * G1_UNCOMPRESSED
+                + (accumulator.tau_g2.len() + 1) * G2_UNCOMPRESSED,
+        );
+        out.extend_from_slice(&self.hash);
+        write_g1s(&accumulator.tau_g1, Encoding::Zcash, &mut out)?;
+        write_g2s(&accumulator.tau_g2, Encoding::Zcash, &mut out)?;
+        write_g1s(&accumulator.alpha_tau_g1, Encoding::Zcash, &mut out)?;
+        write_g1s(&accumulator.beta_tau_g1, Encoding::Zcash, &mut out)?;
+        write_g2s(&[accumulator.beta_g2], Encoding::Zcash, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl ZcashResponse {
+    /// Decodes a response with `2^power` powers and validates all points.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file has the wrong size or contains an invalid
+    /// point.
+    #[instrument(level = "info", skip_all, fields(size=bytes.len(), power))]
+    pub fn read<E: Engine>(bytes: &[u8], power: u32) -> Result<Self, Phase1Error> {
+        let (num_g1, num_g2) = num_powers(power)?;
+        let mut reader = Reader::new(bytes);
+        let hash = reader.array()?;
+        let accumulator = Phase1Accumulator {
+            tau_g1:       compressed_g1s(&mut reader, num_g1)?,
+            tau_g2:       compressed_g2s(&mut reader, num_g2)?,
+            alpha_tau_g1: compressed_g1s(&mut reader, num_g2)?,
+            beta_tau_g1:  compressed_g1s(&mut reader, num_g2)?,
+            beta_g2:      G2(reader.array()?),
+        };
+        let g1 = reader.g1s(6, Encoding::Zcash)?;
+        let g2 = reader.g2s(3, Encoding::Zcash)?;
+        reader.finish()?;
+        let public_key = ZcashPublicKey {
+            tau_g1:   [g1[0], g1[1]],
+            alpha_g1: [g1[2], g1[3]],
+            beta_g1:  [g1[4], g1[5]],
+            tau_g2:   g2[0],
+            alpha_g2: g2[1],
+            beta_g2:  g2[2],
+        };
+        accumulator.validate::<E>(power)?;
+        E::validate_g1(&g1)?;
+        E::validate_g2(&g2)?;
+        Ok(Self {
+            hash,
+            accumulator,
+            public_key,
+        })
+    }
+
+    /// Encodes as a response file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a public key point can not be decompressed.
+    #[instrument(level = "info", skip_all)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CeremonyError> {
+        let accumulator = &self.accumulator;
+        let key = &self.public_key;
+        let mut out = Vec::with_capacity(
+            64 + (accumulator.tau_g1.len() + 2 * accumulator.alpha_tau_g1.len()) * 48
+                + (accumulator.tau_g2.len() + 1) * 96
+                + 6 * G1_UNCOMPRESSED
+                + 3 * G2_UNCOMPRESSED,
+        );
+        out.extend_from_slice(&self.hash);
+        for point in &accumulator.tau_g1 {
+            out.extend_from_slice(&point.0);
+        }
+        for point in &accumulator.tau_g2 {
+            out.extend_from_slice(&point.0);
+        }
+        for point in accumulator
+            .alpha_tau_g1
+            .iter()
+            .chain(&accumulator.beta_tau_g1)
+        {
+            out.extend_from_slice(&point.0);
+        }
+        out.extend_from_slice(&accumulator.beta_g2.0);
+        write_g1s(
+            &[
+                key.tau_g1[0],
+                key.tau_g1[1],
+                key.alpha_g1[0],
+                key.alpha_g1[1],
+                key.beta_g1[0],
+                key.beta_g1[1],
+            ],
+            Encoding::Zcash,
+            &mut out,
+        )?;
+        write_g2s(
+            &[key.tau_g2, key.alpha_g2, key.beta_g2],
+            Encoding::Zcash,
+            &mut out,
+        )?;
+        Ok(out)
+    }
+}
+
+fn compressed_g1s(reader: &mut Reader, n: usize) -> Result<Vec<G1>, Phase1Error> {
+    (0..n).map(|_| reader.array().map(G1)).collect()
+}
+
+fn compressed_g2s(reader: &mut Reader, n: usize) -> Result<Vec<G2>, Phase1Error> {
+    (0..n).map(|_| reader.array().map(G2)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        super::test::{self as compressed, accumulator, known_accumulator},
+        *,
+    };
+    use crate::{Arkworks, ParseError};
+    use hex_literal::hex;
+
+    fn public_key() -> ZcashPublicKey {
+        let accumulator = accumulator(1);
+        ZcashPublicKey {
+            tau_g1:   [G1::one(), accumulator.tau_g1[1]],
+            alpha_g1: [G1::one(), accumulator.tau_g1[2]],
+            beta_g1:  [G1::one(), G1::zero()],
+            tau_g2:   accumulator.tau_g2[1],
+            alpha_g2: G2::one(),
+            beta_g2:  G2::zero(),
+        }
+    }
+
+    #[test]
+    fn test_challenge_roundtrip() {
+        let challenge = ZcashChallenge {
+            hash:        [7; 64],
+            accumulator: accumulator(2),
+        };
+        let bytes = challenge.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 64 + (7 + 4 + 4) * 96 + (4 + 1) * 192);
+        assert_eq!(ZcashChallenge::read::<Arkworks>(&bytes, 2), Ok(challenge));
+        assert_eq!(
+            ZcashChallenge::read::<Arkworks>(&bytes[..bytes.len() - 1], 2),
+            Err(Phase1Error::UnexpectedEnd)
+        );
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert_eq!(
+            ZcashChallenge::read::<Arkworks>(&trailing, 2),
+            Err(Phase1Error::TrailingBytes(1))
+        );
+    }
+
+    #[test]
+    fn test_response_roundtrip() {
+        let response = ZcashResponse {
+            hash:        [9; 64],
+            accumulator: accumulator(2),
+            public_key:  public_key(),
+        };
+        let bytes = response.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 64 + (7 + 4 + 4) * 48 + (4 + 1) * 96 + 1152);
+        assert_eq!(ZcashResponse::read::<Arkworks>(&bytes, 2), Ok(response));
+    }
+
+    #[test]
+    fn test_known_answer() {
+        // Points of `known_accumulator`, uncompressed big-endian
+        const G1_1: [u8; 96] = hex!(
+            "17f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb
+            08b3f481e3aaa0f1a09e30ed741d8ae4fcf5e095d5d00af600db18cb2c04b3edd03cc744a2888ae40caa232946c5e7e1"
+        );
+        const G1_2: [u8; 96] = hex!(
+            "0572cbea904d67468808c8eb50a9450c9721db309128012543902d0ac358a62ae28f75bb8f1c7c42c39a8c5529bf0f4e
+            166a9d8cabc673a322fda673779d8e3822ba3ecb8670e461f73bb9021d5fd76a4c56d9d4cd16bd1bba86881979749d28"
+        );
+        const G1_3: [u8; 96] = hex!(
+            "09ece308f9d1f0131765212deca99697b112d61f9be9a5f1f3780a51335b3ff981747a0b2ca2179b96d2c0c9024e5224
+            032b80d3a6f5b09f8a84623389c5f80ca69a0cddabc3097f9d9c27310fd43be6e745256c634af45ca3473b0590ae30d1"
+        );
+        const G1_4: [u8; 96] = hex!(
+            "0c9b60d5afcbd5663a8a44b7c5a02f19e9a77ab0a35bd65809bb5c67ec582c897feb04decc694b13e08587f3ff9b5b60
+            143be6d078c2b79a7d4f1d1b21486a030ec93f56aa54e1de880db5a66dd833a652a95bee27c824084006cb5644cbd43f"
+        );
+        const G1_6: [u8; 96] = hex!(
+            "06e82f6da4520f85c5d27d8f329eccfa05944fd1096b20734c894966d12a9e2a9a9744529d7212d33883113a0cadb909
+            17d81038f7d60bee9110d9c0d6d1102fe2d998c957f28e31ec284cc04134df8e47e8f82ff3af2e60a6d9688a4563477c"
+        );
+        const G2_1: [u8; 192] = hex!(
+            "13e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e
+            024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8
+            0606c4a02ea734cc32acd2b02bc28b99cb3e287e85a763af267492ab572e99ab3f370d275cec1da1aaa9075ff05f79be
+            0ce5d527727d6e118cc9cdc6da2e351aadfd9baa8cbdd3a76d429a695160d12c923ac9cc3baca289e193548608b82801"
+        );
+        const G2_2: [u8; 192] = hex!(
+            "0a4edef9c1ed7f729f520e47730a124fd70662a904ba1074728114d1031e1572c6c886f6b57ec72a6178288c47c33577
+            1638533957d540a9d2370f17cc7ed5863bc0b995b8825e0ee1ea1e1e4d00dbae81f14b0bf3611b78c952aacab827a053
+            0f6d4552fa65dd2638b361543f887136a43253d9c66c411697003f7a13c308f5422e1aa0a59c8967acdefd8b6e36ccf3
+            0468fb440d82b0630aeb8dca2b5256789a66da69bf91009cbfe6bd221e47aa8ae88dece9764bf3bd999d95d71e4c9899"
+        );
+        let hash = [0xab; 64];
+
+        let challenge = [
+            &hash[..],
+            &G1_1,
+            &G1_2,
+            &G1_4,
+            &G2_1,
+            &G2_2,
+            &G1_3,
+            &G1_6,
+            &G1_1,
+            &G1_2,
+            &G2_1,
+        ]
+        .concat();
+        let read = ZcashChallenge::read::<Arkworks>(&challenge, 1).unwrap();
+        assert_eq!(read.hash, hash);
+        assert_eq!(read.accumulator, known_accumulator());
+        assert_eq!(read.to_bytes().unwrap(), challenge);
+
+        // Responses store the accumulator compressed and the public key
+        // uncompressed
+        let response = [
+            &hash[..],
+            &compressed::G1_1.0,
+            &compressed::G1_2.0,
+            &compressed::G1_4.0,
+            &compressed::G2_1.0,
+            &compressed::G2_2.0,
+            &compressed::G1_3.0,
+            &compressed::G1_6.0,
+            &compressed::G1_1.0,
+            &compressed::G1_2.0,
+            &compressed::G2_1.0,
+            &G1_1,
+            &G1_2,
+            &G1_1,
+            &G1_3,
+            &G1_1,
+            &G1_1,
+            &G2_2,
+            &G2_1,
+            &G2_1,
+        ]
+        .concat();
+        let read = ZcashResponse::read::<Arkworks>(&response, 1).unwrap();
+        assert_eq!(read.hash, hash);
+        assert_eq!(read.accumulator, known_accumulator());
+        assert_eq!(read.public_key, ZcashPublicKey {
+            tau_g1:   [compressed::G1_1, compressed::G1_2],
+            alpha_g1: [compressed::G1_1, compressed::G1_3],
+            beta_g1:  [compressed::G1_1, compressed::G1_1],
+            tau_g2:   compressed::G2_2,
+            alpha_g2: compressed::G2_1,
+            beta_g2:  compressed::G2_1,
+        });
+        assert_eq!(read.to_bytes().unwrap(), response);
+    }
+
+    #[test]
+    fn test_response_invalid() {
+        let response = ZcashResponse {
+            hash:        [9; 64],
+            accumulator: accumulator(1),
+            public_key:  public_key(),
+        };
+        let mut bytes = response.to_bytes().unwrap();
+        // Compressed points are validated by the engine.
+        bytes[64 + 48] ^= 0x80;
+        assert_eq!(
+            ZcashResponse::read::<Arkworks>(&bytes, 1),
+            Err(CeremonyError::InvalidG1Power(1, ParseError::NotCompressed).into())
+        );
+    }
+}
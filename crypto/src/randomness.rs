@@ -0,0 +1,205 @@
+//! Randomness for the linear combinations in pairing checks.
+
+use crate::{BatchContribution, BatchTranscript, Contribution, Scalar, Transcript, G1, G2};
+use ark_ff::PrimeField;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+/// Domain separation tag for Fiat–Shamir seeds.
+const SEED_DST: &[u8] = b"KZG_CEREMONY_VERIFICATION_SEED_V1";
+
+/// Number of bits in the random factors. Sums of up to four factors fit in
+/// 128 bits.
+pub const FACTOR_BITS: u32 = 126;
+
+/// Source of the random factors used to combine pairing checks.
+///
+/// The engines combine many pairing equations into one using random linear
+/// combinations. With [`Randomness::Fresh`] the factors come from the
+/// operating system and a verification can not be reproduced. With
+/// [`Randomness::Seeded`] they are derived from a seed, and a seed created by
+/// [`Randomness::fiat_shamir`] commits to everything that is verified. Third
+/// parties can then replay the exact verification.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Randomness {
+    /// Fresh randomness from the thread local RNG.
+    #[default]
+    Fresh,
+
+    /// Factors derived deterministically from a seed.
+    Seeded([u8; 32]),
+}
+
+impl Randomness {
+    /// Derives a seed from a hash of the transcript and the contribution that
+    /// is verified against it.
+    #[must_use]
+    pub fn fiat_shamir(transcript: &Transcript, contribution: &Contribution) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(SEED_DST);
+        hash_transcript(&mut hasher, transcript);
+        hash_contribution(&mut hasher, contribution);
+        Self::Seeded(hasher.finalize().into())
+    }
+
+    /// Derives a seed from a hash of the batch transcript and the batch
+    /// contribution that is verified against it.
+    #[must_use]
+    pub fn fiat_shamir_batch(
+        transcript: &BatchTranscript,
+        contribution: &BatchContribution,
+    ) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(SEED_DST);
+        hasher.update((transcript.transcripts.len() as u64).to_le_bytes());
+        for transcript in &transcript.transcripts {
+            hash_transcript(&mut hasher, transcript);
+        }
+        hasher.update((contribution.contributions.len() as u64).to_le_bytes());
+        for contribution in &contribution.contributions {
+            hash_contribution(&mut hasher, contribution);
+        }
+        Self::Seeded(hasher.finalize().into())
+    }
+
+    /// Derives independent randomness for a sub-computation.
+    #[must_use]
+    pub fn derive(&self, label: &[u8]) -> Self {
+        match self {
+            Self::Fresh => Self::Fresh,
+            Self::Seeded(seed) => {
+                let mut hasher = Sha256::new();
+                hasher.update(seed);
+                hasher.update(label);
+                Self::Seeded(hasher.finalize().into())
+            }
+        }
+    }
+
+    /// Returns `n` random factors of [`FACTOR_BITS`] bits for linear
+    /// combinations of pairing checks.
+    ///
+    /// Every engine uses these values as they are, so a seeded verification
+    /// has the same outcome whichever engine runs it. The `domain` separates
+    /// factors used for different purposes under the same seed.
+    #[must_use]
+    pub fn factors(&self, domain: &[u8], n: usize) -> Vec<u128> {
+        self.bytes(domain, n)
+            .iter()
+            .map(|bytes| {
+                let mut low = [0; 16];
+                low.copy_from_slice(&bytes[..16]);
+                u128::from_le_bytes(low) >> (128 - FACTOR_BITS)
+            })
+            .collect()
+    }
+
+    /// Returns `n` uniformly random scalars.
+    #[must_use]
+    pub fn scalars(&self, domain: &[u8], n: usize) -> Vec<Scalar> {
+        self.bytes(domain, n)
+            .iter()
+            .map(|bytes| Scalar::from_le_bytes_mod_order(bytes))
+            .collect()
+    }
+
+    /// Returns `n` uniformly random 32 byte strings.
+    fn bytes(&self, domain: &[u8], n: usize) -> Vec<[u8; 32]> {
+        match self {
+            Self::Fresh => {
+                let mut rng = rand::thread_rng();
+                (0..n).map(|_| rng.gen()).collect()
+            }
+            Self::Seeded(seed) => {
+                let mut hasher = Sha256::new();
+                hasher.update(seed);
+                hasher.update((domain.len() as u64).to_le_bytes());
+                hasher.update(domain);
+                (0..n as u64)
+                    .map(|i| {
+                        let mut hasher = hasher.clone();
+                        hasher.update(i.to_le_bytes());
+                        hasher.finalize().into()
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+fn hash_g1s(hasher: &mut Sha256, points: &[G1]) {
+    hasher.update((points.len() as u64).to_le_bytes());
+    for point in points {
+        hasher.update(point.0);
+    }
+}
+
+fn hash_g2s(hasher: &mut Sha256, points: &[G2]) {
+    hasher.update((points.len() as u64).to_le_bytes());
+    for point in points {
+        hasher.update(point.0);
+    }
+}
+
+fn hash_transcript(hasher: &mut Sha256, transcript: &Transcript) {
+    hash_g1s(hasher, &transcript.powers.g1);
+    hash_g2s(hasher, &transcript.powers.g2);
+    hash_g1s(hasher, &transcript.witness.products);
+    hash_g2s(hasher, &transcript.witness.pubkeys);
+}
+
+fn hash_contribution(hasher: &mut Sha256, contribution: &Contribution) {
+    hash_g1s(hasher, &contribution.powers.g1);
+    hash_g2s(hasher, &contribution.powers.g2);
+    hash_g2s(hasher, &[contribution.pubkey]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seeded_factors() {
+        let transcript = Transcript::new(4, 2);
+        let contribution = transcript.contribution();
+        let randomness = Randomness::fiat_shamir(&transcript, &contribution);
+        assert_eq!(
+            randomness,
+            Randomness::fiat_shamir(&transcript, &contribution)
+        );
+        assert_eq!(randomness.factors(b"a", 3), randomness.factors(b"a", 3));
+        assert_eq!(
+            randomness.factors(b"a", 3)[..2],
+            randomness.factors(b"a", 2)
+        );
+        assert_ne!(randomness.factors(b"a", 1), randomness.factors(b"b", 1));
+        assert_ne!(randomness.derive(b"a"), randomness.derive(b"b"));
+
+        let mut other = contribution;
+        other.pubkey = G2::zero();
+        assert_ne!(randomness, Randomness::fiat_shamir(&transcript, &other));
+    }
+
+    #[test]
+    fn test_factor_values() {
+        // Engines must agree on these, so they are pinned here
+        let randomness = Randomness::Seeded([0; 32]);
+        let factors = randomness.factors(b"verify_g1", 2);
+        assert_eq!(factors, [
+            0x28ef_537a_0a64_3459_b91e_73e7_5f65_dbe8,
+            0x09fd_c6b2_9c4a_d9e7_ca03_0619_8bc1_af83
+        ]);
+        assert!(factors.iter().all(|f| f >> FACTOR_BITS == 0));
+        assert_eq!(
+            randomness.scalars(b"verify_g1", 1)[0],
+            Scalar::from_le_bytes_mod_order(&randomness.bytes(b"verify_g1", 1)[0])
+        );
+    }
+
+    #[test]
+    fn test_fresh_factors() {
+        let randomness = Randomness::Fresh;
+        assert_eq!(randomness.derive(b"a"), Randomness::Fresh);
+        assert_ne!(randomness.factors(b"a", 1), randomness.factors(b"a", 1));
+    }
+}
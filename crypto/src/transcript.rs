@@ -1,6 +1,8 @@
-use super::{CeremonyError, Contribution, Powers, G1, G2};
+use super::{CeremonyError, Contribution, Powers, Randomness, G1, G2};
 use crate::engine::Engine;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tracing::instrument;
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -51,6 +53,26 @@ impl Transcript {
         }
     }
 
+    /// Create a transcript continuing from existing powers, for example those
+    /// of a Phase-1 ceremony. The previous ceremony is recorded as a single
+    /// contribution with pubkey `g2[1]`.
+    ///
+    /// # Panics
+    ///
+    /// There must be at least two g1 and two g2 points.
+    #[must_use]
+    pub fn from_powers(powers: Powers) -> Self {
+        assert!(powers.g1.len() >= 2);
+        assert!(powers.g2.len() >= 2);
+        Self {
+            witness: Witness {
+                products: vec![G1::one(), powers.g1[1]],
+                pubkeys:  vec![G2::one(), powers.g2[1]],
+            },
+            powers,
+        }
+    }
+
     /// Creates the start of a new contribution.
     #[must_use]
     pub fn contribution(&self) -> Contribution {
@@ -60,9 +82,38 @@ impl Transcript {
         }
     }
 
-    /// Verifies a contribution.
-    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
+    /// Verifies a contribution using fresh randomness.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first check the contribution fails.
     pub fn verify<E: Engine>(&self, contribution: &Contribution) -> Result<(), CeremonyError> {
+        self.verify_with::<E>(contribution, Randomness::Fresh)
+    }
+
+    /// Verifies a contribution using the given randomness for the pairing
+    /// checks.
+    ///
+    /// Use [`Randomness::fiat_shamir`] to make the verification reproducible.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first check the contribution fails.
+    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
+    pub fn verify_with<E: Engine>(
+        &self,
+        contribution: &Contribution,
+        randomness: Randomness,
+    ) -> Result<(), CeremonyError> {
+        self.verify_inputs::<E>(contribution)?;
+        self.verify_pairings::<E>(contribution, randomness)
+    }
+
+    /// Verifies everything about a contribution except for the pairing checks.
+    pub(crate) fn verify_inputs<E: Engine>(
+        &self,
+        contribution: &Contribution,
+    ) -> Result<(), CeremonyError> {
         // Sanity checks
         self.sanity_check()?;
         if !contribution.has_entropy() {
@@ -70,11 +121,9 @@ impl Transcript {
         }
         contribution.sanity_check()?;
 
-        // TODO: More sanity checks:
-        // - No values are zero.
-        // - All g1 values (both in transcript and contribution) must be unique
-        // - All g2 values (both in transcript and contribution) must be unique, except
-        //   for pubkey on the first contribution.
+        // Zero, duplicate and entropy-free values are rejected by the sanity checks
+        // above. Whether the contribution repeats values of the transcript it
+        // extends is not checked.
 
         // Compatibility checks
         if self.powers.g1.len() != contribution.powers.g1.len() {
@@ -94,17 +143,89 @@ impl Transcript {
         E::validate_g1(&contribution.powers.g1)?;
         E::validate_g2(&contribution.powers.g2)?;
         E::validate_g2(&[contribution.pubkey])?;
+        Ok(())
+    }
 
-        // Verify pairings.
+    /// Verifies the pairing checks of [`Self::verify`] one by one.
+    ///
+    /// The contribution must pass the other checks of [`Self::verify`].
+    pub(crate) fn verify_pairings<E: Engine>(
+        &self,
+        contribution: &Contribution,
+        randomness: Randomness,
+    ) -> Result<(), CeremonyError> {
         E::verify_pubkey(
             contribution.powers.g1[1],
             self.powers.g1[1],
             contribution.pubkey,
         )?;
-        E::verify_g1(&contribution.powers.g1, contribution.powers.g2[1])?;
+        E::verify_g1(
+            &contribution.powers.g1,
+            contribution.powers.g2[1],
+            randomness,
+        )?;
         E::verify_g2(
             &contribution.powers.g1[..contribution.powers.g2.len()],
             &contribution.powers.g2,
+            randomness,
+        )?;
+
+        // Accept
+        Ok(())
+    }
+
+    /// Verifies the whole transcript, including the witness chain.
+    ///
+    /// Unlike [`Self::verify`], this does not require the previous state, so
+    /// it can be used to audit a published transcript end to end. It checks
+    /// that all points are valid, that each running product is the previous
+    /// one multiplied by the matching pubkey, that the last running product
+    /// equals `g1[1]` and that the powers are consistent powers of tau.
+    #[instrument(level = "info", skip_all, fields(
+        n1=self.powers.g1.len(),
+        n2=self.powers.g2.len(),
+        n=self.witness.products.len()
+    ))]
+    pub fn verify_full<E: Engine>(&self) -> Result<(), CeremonyError> {
+        // Sanity checks
+        self.sanity_check()?;
+        if self.witness.products.last() != Some(&self.powers.g1[1]) {
+            return Err(CeremonyError::WitnessProductMismatch);
+        }
+
+        // Verify all points (encoding and subgroup checks).
+        E::validate_g1(&self.powers.g1)?;
+        E::validate_g2(&self.powers.g2)?;
+        E::validate_g1(&self.witness.products).map_err(|e| match e {
+            CeremonyError::InvalidG1Power(i, e) => CeremonyError::InvalidWitnessProduct(i, e),
+            e => e,
+        })?;
+        E::validate_g2(&self.witness.pubkeys).map_err(|e| match e {
+            CeremonyError::InvalidG2Power(i, e) => CeremonyError::InvalidWitnessPubKey(i, e),
+            e => e,
+        })?;
+
+        // Verify the witness chain.
+        (1..self.witness.products.len())
+            .into_par_iter()
+            .try_for_each(|i| {
+                E::verify_pubkey(
+                    self.witness.products[i],
+                    self.witness.products[i - 1],
+                    self.witness.pubkeys[i],
+                )
+                .map_err(|e| match e {
+                    CeremonyError::PubKeyPairingFailed => CeremonyError::WitnessPairingFailed(i),
+                    e => e,
+                })
+            })?;
+
+        // Verify the powers.
+        E::verify_g1(&self.powers.g1, self.powers.g2[1], Randomness::Fresh)?;
+        E::verify_g2(
+            &self.powers.g1[..self.powers.g2.len()],
+            &self.powers.g2,
+            Randomness::Fresh,
         )?;
 
         // Accept
@@ -149,15 +270,114 @@ impl Transcript {
             ));
         }
 
+        if self.witness.pubkeys.is_empty() {
+            return Err(CeremonyError::EmptyWitness);
+        }
+
+        // The first values in the witness must be the generator
+        if self.witness.products[0] != G1::one() {
+            return Err(CeremonyError::InvalidWitnessFirstProduct);
+        }
+        if self.witness.pubkeys[0] != G2::one() {
+            return Err(CeremonyError::InvalidWitnessFirstPubKey);
+        }
+
         // If there is no entropy all values must be one.
         if !self.has_entropy() {
-            // TODO
+            for (i, g1) in self.powers.g1.iter().enumerate() {
+                if *g1 != G1::one() {
+                    return Err(CeremonyError::InvalidG1NoEntropy(i));
+                }
+            }
+            for (i, g2) in self.powers.g2.iter().enumerate() {
+                if *g2 != G2::one() {
+                    return Err(CeremonyError::InvalidG2NoEntropy(i));
+                }
+            }
+            return Ok(());
         }
 
         // Otherwise, the first values in powers and witness must be one, and all
         // other values non-zero, non-one and unique (also unique between powers and
-        // witness, except for g2[1] == pubkey[1] when n=2 and g1[2] == product.last()).
-        // TODO
+        // witness, except for g2[1] == pubkey[1] when n=2 and g1[1] == product.last()).
+        for (i, g1) in self.powers.g1.iter().enumerate() {
+            if *g1 == G1::zero() {
+                return Err(CeremonyError::ZeroG1(i));
+            }
+        }
+        for (i, g2) in self.powers.g2.iter().enumerate() {
+            if *g2 == G2::zero() {
+                return Err(CeremonyError::ZeroG2(i));
+            }
+        }
+        for (i, product) in self.witness.products.iter().enumerate() {
+            if *product == G1::zero() {
+                return Err(CeremonyError::ZeroWitnessProduct(i));
+            }
+        }
+        for (i, pubkey) in self.witness.pubkeys.iter().enumerate() {
+            if *pubkey == G2::zero() {
+                return Err(CeremonyError::ZeroWitnessPubKey(i));
+            }
+        }
+        if self.powers.g1[0] != G1::one() {
+            return Err(CeremonyError::InvalidG1FirstValue);
+        }
+        if self.powers.g2[0] != G2::one() {
+            return Err(CeremonyError::InvalidG2FirstValue);
+        }
+
+        // All g1 values and running products must be unique
+        let mut g1s = HashMap::<G1, usize>::new();
+        for (i, g1) in self.powers.g1.iter().enumerate().skip(1) {
+            if *g1 == G1::one() {
+                return Err(CeremonyError::InvalidG1One(i));
+            }
+            if let Some(j) = g1s.insert(*g1, i) {
+                return Err(CeremonyError::DuplicateG1(j, i));
+            }
+        }
+        let last = self.witness.products.len() - 1;
+        let mut products = HashMap::<G1, usize>::new();
+        for (i, product) in self.witness.products.iter().enumerate().skip(1) {
+            if *product == G1::one() {
+                return Err(CeremonyError::InvalidWitnessProductOne(i));
+            }
+            if let Some(j) = products.insert(*product, i) {
+                return Err(CeremonyError::DuplicateWitnessProduct(j, i));
+            }
+            match g1s.get(product) {
+                Some(1) if i == last => {}
+                Some(j) => return Err(CeremonyError::DuplicateG1WitnessProduct(*j, i)),
+                None => {}
+            }
+        }
+
+        // All g2 values and pubkeys must be unique
+        let mut g2s = HashMap::<G2, usize>::new();
+        for (i, g2) in self.powers.g2.iter().enumerate().skip(1) {
+            if *g2 == G2::one() {
+                return Err(CeremonyError::InvalidG2One(i));
+            }
+            if let Some(j) = g2s.insert(*g2, i) {
+                return Err(CeremonyError::DuplicateG2(j, i));
+            }
+        }
+        let mut pubkeys = HashMap::<G2, usize>::new();
+        for (i, pubkey) in self.witness.pubkeys.iter().enumerate().skip(1) {
+            if *pubkey == G2::one() {
+                return Err(CeremonyError::InvalidWitnessPubKeyOne(i));
+            }
+            if let Some(j) = pubkeys.insert(*pubkey, i) {
+                return Err(CeremonyError::DuplicateWitnessPubKey(j, i));
+            }
+            match g2s.get(pubkey) {
+                Some(1) if last == 1 => {}
+                Some(j) => return Err(CeremonyError::DuplicateG2WitnessPubKey(*j, i)),
+                None => {}
+            }
+        }
+
         Ok(())
     }
 }
@@ -165,6 +385,162 @@ impl Transcript {
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(feature = "arkworks")]
+    use crate::Arkworks;
+    use proptest::{arbitrary::any, proptest, sample::Index, strategy::Strategy};
+
+    /// Distinct placeholder encodings. The sanity checks only compare points,
+    /// so these do not need to be valid curve points.
+    fn g1(seed: u64, i: usize) -> G1 {
+        let mut bytes = [0_u8; 48];
+        bytes[0] = 0x01;
+        bytes[1..9].copy_from_slice(&seed.to_le_bytes());
+        bytes[9..17].copy_from_slice(&(i as u64).to_le_bytes());
+        G1(bytes)
+    }
+
+    fn g2(seed: u64, i: usize) -> G2 {
+        let mut bytes = [0_u8; 96];
+        bytes[0] = 0x01;
+        bytes[1..9].copy_from_slice(&seed.to_le_bytes());
+        bytes[9..17].copy_from_slice(&(i as u64).to_le_bytes());
+        G2(bytes)
+    }
+
+    /// Transcripts with entropy that satisfy the sanity checks.
+    fn arb_transcript() -> impl Strategy<Value = Transcript> {
+        (2_usize..16)
+            .prop_flat_map(|n1| (2..=n1, 1_usize..5, any::<u64>()).prop_map(move |t| (n1, t)))
+            .prop_map(|(num_g1, (num_g2, n, seed))| {
+                let mut transcript = Transcript::new(num_g1, num_g2);
+                for i in 1..num_g1 {
+                    transcript.powers.g1[i] = g1(seed, i);
+                }
+                for i in 1..num_g2 {
+                    transcript.powers.g2[i] = g2(seed, i);
+                }
+                for i in 1..=n {
+                    transcript.witness.products.push(g1(seed, 1000 + i));
+                    transcript.witness.pubkeys.push(g2(seed, 1000 + i));
+                }
+                transcript.witness.products[n] = transcript.powers.g1[1];
+                if n == 1 {
+                    transcript.witness.pubkeys[1] = transcript.powers.g2[1];
+                }
+                transcript
+            })
+    }
+
+    #[test]
+    fn sanity_check_empty_witness() {
+        let mut transcript = Transcript::new(4, 2);
+        transcript.witness.products.clear();
+        transcript.witness.pubkeys.clear();
+        assert_eq!(transcript.sanity_check(), Err(CeremonyError::EmptyWitness));
+    }
+
+    #[test]
+    fn sanity_check_no_entropy() {
+        proptest!(|(n1 in 2_usize..16, i in any::<Index>(), j in any::<Index>())| {
+            let transcript = Transcript::new(n1, 2);
+            assert_eq!(transcript.sanity_check(), Ok(()));
+
+            let i = i.index(n1);
+            let mut t = transcript.clone();
+            t.powers.g1[i] = g1(0, i);
+            assert_eq!(t.sanity_check(), Err(CeremonyError::InvalidG1NoEntropy(i)));
+
+            let j = j.index(2);
+            let mut t = transcript.clone();
+            t.powers.g2[j] = G2::zero();
+            assert_eq!(t.sanity_check(), Err(CeremonyError::InvalidG2NoEntropy(j)));
+
+            let mut t = transcript;
+            t.witness.pubkeys[0] = G2::zero();
+            assert_eq!(t.sanity_check(), Err(CeremonyError::InvalidWitnessFirstPubKey));
+        });
+    }
+
+    #[test]
+    fn sanity_check_valid() {
+        proptest!(|(transcript in arb_transcript())| {
+            assert_eq!(transcript.sanity_check(), Ok(()));
+        });
+    }
+
+    #[test]
+    fn sanity_check_zero_and_one() {
+        proptest!(|(transcript in arb_transcript(), i in any::<Index>())| {
+            let n1 = transcript.powers.g1.len();
+            let n = transcript.witness.products.len();
+
+            let k = i.index(n1);
+            let mut t = transcript.clone();
+            t.powers.g1[k] = G1::zero();
+            assert_eq!(t.sanity_check(), Err(CeremonyError::ZeroG1(k)));
+
+            let k = i.index(n - 1) + 1;
+            let mut t = transcript.clone();
+            t.witness.pubkeys[k] = G2::zero();
+            assert_eq!(t.sanity_check(), Err(CeremonyError::ZeroWitnessPubKey(k)));
+
+            let k = i.index(n1 - 1) + 1;
+            let mut t = transcript.clone();
+            t.powers.g1[k] = G1::one();
+            assert_eq!(t.sanity_check(), Err(CeremonyError::InvalidG1One(k)));
+
+            let k = i.index(n - 1) + 1;
+            let mut t = transcript;
+            t.witness.products[k] = G1::one();
+            assert_eq!(t.sanity_check(), Err(CeremonyError::InvalidWitnessProductOne(k)));
+        });
+    }
+
+    #[test]
+    fn sanity_check_duplicates() {
+        proptest!(|(transcript in arb_transcript(), i in any::<Index>(), j in any::<Index>())| {
+            let n1 = transcript.powers.g1.len();
+            let n2 = transcript.powers.g2.len();
+            let n = transcript.witness.products.len();
+
+            // Within powers
+            let (a, b) = (i.index(n1 - 1) + 1, j.index(n1 - 1) + 1);
+            if a != b {
+                let (a, b) = (a.min(b), a.max(b));
+                let mut t = transcript.clone();
+                t.powers.g1[b] = t.powers.g1[a];
+                assert_eq!(t.sanity_check(), Err(CeremonyError::DuplicateG1(a, b)));
+            }
+            let (a, b) = (i.index(n2 - 1) + 1, j.index(n2 - 1) + 1);
+            if a != b {
+                let (a, b) = (a.min(b), a.max(b));
+                let mut t = transcript.clone();
+                t.powers.g2[b] = t.powers.g2[a];
+                assert_eq!(t.sanity_check(), Err(CeremonyError::DuplicateG2(a, b)));
+            }
+
+            // Between powers and witness
+            let (a, b) = (i.index(n1 - 1) + 1, j.index(n - 1) + 1);
+            if a != 1 || b != n - 1 {
+                let mut t = transcript.clone();
+                t.witness.products[b] = t.powers.g1[a];
+                assert_eq!(t.sanity_check(), Err(CeremonyError::DuplicateG1WitnessProduct(a, b)));
+            }
+            let (a, b) = (i.index(n2 - 1) + 1, j.index(n - 1) + 1);
+            if n > 2 {
+                let mut t = transcript.clone();
+                t.witness.pubkeys[b] = t.powers.g2[a];
+                assert_eq!(t.sanity_check(), Err(CeremonyError::DuplicateG2WitnessPubKey(a, b)));
+            }
+
+            // Within witness
+            if n > 2 {
+                let mut t = transcript;
+                t.witness.pubkeys[2] = t.witness.pubkeys[1];
+                assert_eq!(t.sanity_check(), Err(CeremonyError::DuplicateWitnessPubKey(1, 2)));
+            }
+        });
+    }
 
     #[test]
     fn transcript_json() {
@@ -200,4 +576,87 @@ mod test {
         let deser = serde_json::from_value::<Transcript>(json).unwrap();
         assert_eq!(deser, t);
     }
+
+    #[cfg(feature = "arkworks")]
+    fn transcript_with_contributions(n: u8) -> Transcript {
+        let mut transcript = Transcript::new(8, 4);
+        for i in 1..=n {
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_entropy::<Arkworks>(&[i; 32].into())
+                .unwrap();
+            transcript.verify::<Arkworks>(&contribution).unwrap();
+            transcript.add(contribution);
+        }
+        transcript
+    }
+
+    #[test]
+    #[cfg(feature = "arkworks")]
+    fn verify_full() {
+        assert_eq!(Transcript::new(8, 4).verify_full::<Arkworks>(), Ok(()));
+        assert_eq!(
+            transcript_with_contributions(3).verify_full::<Arkworks>(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "arkworks")]
+    fn verify_full_rejects_tampered_witness() {
+        let transcript = transcript_with_contributions(3);
+
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[42; 32].into())
+            .unwrap();
+        let mut tampered = transcript.clone();
+        tampered.witness.pubkeys[2] = contribution.pubkey;
+        assert_eq!(
+            tampered.verify_full::<Arkworks>(),
+            Err(CeremonyError::WitnessPairingFailed(2))
+        );
+
+        let mut tampered = transcript.clone();
+        tampered.witness.products[3] = contribution.powers.g1[1];
+        assert_eq!(
+            tampered.verify_full::<Arkworks>(),
+            Err(CeremonyError::WitnessProductMismatch)
+        );
+
+        let mut tampered = transcript.clone();
+        tampered.witness.products[0] = transcript.witness.products[1];
+        assert_eq!(
+            tampered.verify_full::<Arkworks>(),
+            Err(CeremonyError::InvalidWitnessFirstProduct)
+        );
+
+        let mut tampered = transcript;
+        tampered.witness.products[2] = G1([0x80; 48]);
+        assert!(matches!(
+            tampered.verify_full::<Arkworks>(),
+            Err(CeremonyError::InvalidWitnessProduct(2, _))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "arkworks")]
+    fn verify_full_rejects_tampered_powers() {
+        let transcript = transcript_with_contributions(2);
+        let other = transcript_with_contributions(1);
+
+        let mut tampered = transcript.clone();
+        tampered.powers.g1[3] = other.powers.g1[3];
+        assert_eq!(
+            tampered.verify_full::<Arkworks>(),
+            Err(CeremonyError::G1PairingFailed)
+        );
+
+        let mut tampered = transcript;
+        tampered.powers.g2[3] = other.powers.g2[3];
+        assert_eq!(
+            tampered.verify_full::<Arkworks>(),
+            Err(CeremonyError::G2PairingFailed)
+        );
+    }
 }
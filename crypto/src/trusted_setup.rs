@@ -0,0 +1,315 @@
+//! Export of a finished ceremony as a c-kzg / go-kzg `trusted_setup.txt`.
+//!
+//! The text format is the number of G1 points, the number of G2 points, the
+//! G1 points in Lagrange form and the G2 points in monomial form, each on its
+//! own line. Points are compressed and hex encoded without `0x` prefix.
+//!
+//! The Lagrange form is over the multiplicative subgroup of size `n` of the
+//! scalar field, generated by $ω = 7^{(r - 1) / n}$, in natural order.
+
+#![cfg(feature = "arkworks")]
+
+use crate::{CeremonyError, Powers, Transcript, TrustedSetupError, G1, G2};
+use ark_bls12_381::{Fr, G1Affine, G1Projective};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{FftField, Field, One, PrimeField};
+use rayon::prelude::*;
+use std::{fmt::Write, iter};
+use tracing::instrument;
+
+/// A setup in the form loaded by EIP-4844 clients.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrustedSetup {
+    pub g1_lagrange: Vec<G1>,
+    pub g2_monomial: Vec<G2>,
+}
+
+impl TrustedSetup {
+    /// Converts the powers of a transcript.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of G1 powers is not a power of two, or
+    /// if a G1 point can not be parsed.
+    pub fn from_transcript(transcript: &Transcript) -> Result<Self, CeremonyError> {
+        Self::from_powers(&transcript.powers)
+    }
+
+    /// Converts the G1 powers to Lagrange form.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of G1 powers is not a power of two, or
+    /// if a G1 point can not be parsed.
+    #[instrument(level = "info", skip_all, fields(n1=powers.g1.len(), n2=powers.g2.len()))]
+    pub fn from_powers(powers: &Powers) -> Result<Self, CeremonyError> {
+        let n = powers.g1.len();
+        let omega = root_of_unity(n)?;
+        let mut points = parse_g1s(&powers.g1)?;
+        fft(
+            &mut points,
+            omega.inverse().expect("roots of unity are non-zero"),
+        );
+        let n_inv = Fr::from(n as u64)
+            .inverse()
+            .expect("n is smaller than the modulus")
+            .into_repr();
+        points.par_iter_mut().for_each(|p| *p = p.mul(n_inv));
+        Ok(Self {
+            g1_lagrange: write_g1s(points),
+            g2_monomial: powers.g2.clone(),
+        })
+    }
+
+    /// Converts the G1 points back to monomial form.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of G1 points is not a power of two, or
+    /// if a G1 point can not be parsed.
+    #[instrument(level = "info", skip_all, fields(n1=self.g1_lagrange.len(), n2=self.g2_monomial.len()))]
+    pub fn to_powers(&self) -> Result<Powers, CeremonyError> {
+        let omega = root_of_unity(self.g1_lagrange.len())?;
+        let mut points = parse_g1s(&self.g1_lagrange)?;
+        fft(&mut points, omega);
+        Ok(Powers {
+            g1: write_g1s(points),
+            g2: self.g2_monomial.clone(),
+        })
+    }
+
+    /// Encodes in the `trusted_setup.txt` format.
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "{}", self.g1_lagrange.len()).unwrap();
+        writeln!(text, "{}", self.g2_monomial.len()).unwrap();
+        for point in &self.g1_lagrange {
+            writeln!(text, "{}", hex::encode(point.0)).unwrap();
+        }
+        for point in &self.g2_monomial {
+            writeln!(text, "{}", hex::encode(point.0)).unwrap();
+        }
+        text
+    }
+
+    /// Decodes from the `trusted_setup.txt` format. Points are not validated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text is not well formed.
+    pub fn from_text(text: &str) -> Result<Self, TrustedSetupError> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .map(|(i, line)| (i + 1, line));
+        let mut next = || lines.next().ok_or(TrustedSetupError::UnexpectedEnd);
+        let num_g1 = parse_number(next()?)?;
+        let num_g2 = parse_number(next()?)?;
+        let g1_lagrange = (0..num_g1)
+            .map(|_| parse_point(next()?).map(G1))
+            .collect::<Result<_, _>>()?;
+        let g2_monomial = (0..num_g2)
+            .map(|_| parse_point(next()?).map(G2))
+            .collect::<Result<_, _>>()?;
+        if let Some((line, _)) = lines.find(|(_, text)| !text.is_empty()) {
+            return Err(TrustedSetupError::TrailingContent(line));
+        }
+        Ok(Self {
+            g1_lagrange,
+            g2_monomial,
+        })
+    }
+}
+
+fn parse_number((line, text): (usize, &str)) -> Result<usize, TrustedSetupError> {
+    text.parse()
+        .map_err(|_| TrustedSetupError::InvalidNumber(line))
+}
+
+fn parse_point<const N: usize>((line, text): (usize, &str)) -> Result<[u8; N], TrustedSetupError> {
+    let mut bytes = [0; N];
+    hex::decode_to_slice(text, &mut bytes).map_err(|_| TrustedSetupError::InvalidPoint(line))?;
+    Ok(bytes)
+}
+
+/// Generator of the subgroup of size `n`, which must be a power of two.
+fn root_of_unity(n: usize) -> Result<Fr, CeremonyError> {
+    if !n.is_power_of_two() {
+        return Err(CeremonyError::UnsupportedNumG1Powers(n));
+    }
+    Fr::get_root_of_unity(n).ok_or(CeremonyError::UnsupportedNumG1Powers(n))
+}
+
+fn parse_g1s(points: &[G1]) -> Result<Vec<G1Projective>, CeremonyError> {
+    points
+        .par_iter()
+        .enumerate()
+        .map(|(i, p)| {
+            G1Affine::try_from(*p)
+                .map(|p| p.into_projective())
+                .map_err(|e| CeremonyError::InvalidG1Power(i, e))
+        })
+        .collect()
+}
+
+fn write_g1s(mut points: Vec<G1Projective>) -> Vec<G1> {
+    G1Projective::batch_normalization(&mut points);
+    points
+        .into_par_iter()
+        .map(|p| p.into_affine().into())
+        .collect()
+}
+
+/// In-place radix-2 FFT, replacing `a[i]` by the sum of `ω^(i j) a[j]` over
+/// `j`.
+fn fft(values: &mut [G1Projective], omega: Fr) {
+    let n = values.len();
+    let log_n = n.trailing_zeros();
+    if n <= 1 {
+        return;
+    }
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - log_n);
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+    let mut size = 2;
+    while size <= n {
+        let step = omega.pow([(n / size) as u64]);
+        let twiddles = iter::successors(Some(Fr::one()), |w| Some(*w * step))
+            .take(size / 2)
+            .map(|w| w.into_repr())
+            .collect::<Vec<_>>();
+        values.par_chunks_mut(size).for_each(|chunk| {
+            let (low, high) = chunk.split_at_mut(size / 2);
+            low.par_iter_mut()
+                .zip(high.par_iter_mut())
+                .zip(twiddles.par_iter())
+                .for_each(|((a, b), w)| {
+                    let t = b.mul(*w);
+                    *b = *a - t;
+                    *a += t;
+                });
+        });
+        size *= 2;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Arkworks;
+    use ark_bls12_381::FrParameters;
+    use ark_ff::{BigInteger, BigInteger256, FpParameters};
+
+    fn transcript(num_g1: usize) -> Transcript {
+        let mut transcript = Transcript::new(num_g1, 2);
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<Arkworks>(&[u8::try_from(num_g1).unwrap(); 32].into())
+            .unwrap();
+        transcript.verify::<Arkworks>(&contribution).unwrap();
+        transcript.add(contribution);
+        transcript
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for n in [4, 8, 16] {
+            let transcript = transcript(n);
+            let setup = TrustedSetup::from_transcript(&transcript).unwrap();
+            assert_eq!(setup.g1_lagrange.len(), n);
+            assert_ne!(setup.g1_lagrange, transcript.powers.g1);
+            let text = setup.to_text();
+            assert_eq!(text.lines().count(), 2 + n + 2);
+            assert!(text.starts_with(&format!("{n}\n2\n")));
+            let parsed = TrustedSetup::from_text(&text).unwrap();
+            assert_eq!(parsed, setup);
+            assert_eq!(parsed.to_powers().unwrap(), transcript.powers);
+        }
+    }
+
+    #[test]
+    fn test_lagrange_sum() {
+        // The Lagrange basis polynomials sum to one.
+        let setup = TrustedSetup::from_transcript(&transcript(8)).unwrap();
+        let sum = parse_g1s(&setup.g1_lagrange)
+            .unwrap()
+            .into_iter()
+            .sum::<G1Projective>();
+        assert_eq!(G1::from(sum.into_affine()), G1::one());
+    }
+
+    #[test]
+    fn test_known_answer() {
+        // Points in the ZCash compressed encoding
+        const G1_GENERATOR: &str = "97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb";
+        const G1_INFINITY: &str = "c00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000";
+        const G2_GENERATOR: &str = "93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8";
+
+        // ω = 7^((r - 1) / 4), computed without the library root of unity
+        let mut exponent = FrParameters::MODULUS;
+        exponent.sub_noborrow(&BigInteger256::from(1));
+        exponent.divn(2);
+        let omega = Fr::from(7_u64).pow(exponent);
+        assert_eq!(omega.pow([4]), Fr::one());
+        assert_ne!(omega.pow([2]), Fr::one());
+
+        // With τ = ω, the Lagrange polynomial L_i(τ) is one for i = 1 and zero
+        // otherwise, so the generator must be on the first line after the
+        // header point. A bit-reversed order would put it on the second.
+        let powers = Powers {
+            g1: (0..4)
+                .map(|i| {
+                    G1Affine::prime_subgroup_generator()
+                        .mul(omega.pow([i]).into_repr())
+                        .into_affine()
+                        .into()
+                })
+                .collect(),
+            g2: Powers::new(4, 2).g2,
+        };
+        let text = TrustedSetup::from_powers(&powers).unwrap().to_text();
+        let expected = [
+            "4",
+            "2",
+            G1_INFINITY,
+            G1_GENERATOR,
+            G1_INFINITY,
+            G1_INFINITY,
+            G2_GENERATOR,
+            G2_GENERATOR,
+        ];
+        assert_eq!(text, format!("{}\n", expected.join("\n")));
+        assert_eq!(
+            TrustedSetup::from_text(&text).unwrap().to_powers().unwrap(),
+            powers
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            TrustedSetup::from_powers(&Transcript::new(6, 2).powers),
+            Err(CeremonyError::UnsupportedNumG1Powers(6))
+        );
+        let text = TrustedSetup::from_transcript(&transcript(4))
+            .unwrap()
+            .to_text();
+        assert_eq!(
+            TrustedSetup::from_text(&text.replace("\n2\n", "\n3\n")),
+            Err(TrustedSetupError::UnexpectedEnd)
+        );
+        assert_eq!(
+            TrustedSetup::from_text(&text.replacen('4', "x", 1)),
+            Err(TrustedSetupError::InvalidNumber(1))
+        );
+        assert_eq!(
+            TrustedSetup::from_text(&format!("{text}00\n")),
+            Err(TrustedSetupError::TrailingContent(9))
+        );
+    }
+}
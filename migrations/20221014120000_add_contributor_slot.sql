@@ -0,0 +1 @@
+ALTER TABLE contributors ADD COLUMN slot TEXT NOT NULL DEFAULT 'online';
@@ -0,0 +1,27 @@
+-- Participants waiting in the lobby, in queue order. Times are unix
+-- milliseconds.
+CREATE TABLE IF NOT EXISTS lobby (
+    session_id            TEXT    PRIMARY KEY NOT NULL,
+    position              BIGINT              NOT NULL,
+    token                 TEXT                NOT NULL,
+    last_ping_at          BIGINT              NOT NULL,
+    is_first_ping_attempt BOOLEAN             NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS csrf_tokens (
+    token                 TEXT    PRIMARY KEY NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    uid                   TEXT    PRIMARY KEY NOT NULL,
+    session_id            TEXT                NOT NULL
+);
+
+-- At most one row, for the participant holding the contribution slot.
+CREATE TABLE IF NOT EXISTS active_contributor (
+    id                    INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+    session_id            TEXT                NOT NULL,
+    token                 TEXT                NOT NULL,
+    slot                  TEXT                NOT NULL,
+    started_at            BIGINT              NOT NULL
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS banned_users (
+    uid          TEXT     PRIMARY KEY NOT NULL,
+    banned_at    INTEGER              NOT NULL
+);
+
+-- Audit log of the actions taken through the admin API.
+CREATE TABLE IF NOT EXISTS admin_actions (
+    performed_at INTEGER              NOT NULL,
+    action       TEXT                 NOT NULL,
+    details      TEXT
+);
@@ -0,0 +1,45 @@
+use crate::{lobby, util::Secret};
+use clap::Parser;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::RwLock;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Bearer key for the `/admin` API. The API is disabled when not set.
+    #[clap(long, env)]
+    pub admin_key: Option<Secret>,
+}
+
+/// Controls set by operators through `/admin`. They are kept in memory only,
+/// so a restart goes back to the command line options.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AdminState {
+    /// Stops new participants from signing in and from taking the slot.
+    /// Participants in the lobby keep their place in the queue.
+    pub admissions_paused:        bool,
+    pub max_lobby_size:           Option<usize>,
+    /// Applies to slots started after the change.
+    pub compute_deadline:         Option<Duration>,
+    /// Applies to slots started after the change.
+    pub offline_compute_deadline: Option<Duration>,
+}
+
+impl AdminState {
+    /// The lobby options with the operator's overrides applied.
+    #[must_use]
+    pub fn lobby_options(&self, options: &lobby::Options) -> lobby::Options {
+        let mut options = options.clone();
+        if let Some(max_lobby_size) = self.max_lobby_size {
+            options.max_lobby_size = max_lobby_size;
+        }
+        if let Some(compute_deadline) = self.compute_deadline {
+            options.compute_deadline = compute_deadline;
+        }
+        if let Some(offline_compute_deadline) = self.offline_compute_deadline {
+            options.offline_compute_deadline = offline_compute_deadline;
+        }
+        options
+    }
+}
+
+pub type SharedAdminState = Arc<RwLock<AdminState>>;
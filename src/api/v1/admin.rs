@@ -0,0 +1,601 @@
+use crate::{
+    admin::SharedAdminState,
+    lobby::{expire_current_contributor, Expiry, SharedContributorState, SharedLobbyState},
+    oauth::SharedAuthState,
+    storage::{PersistentStorage, StorageError},
+    Options, SessionId,
+};
+use async_session::async_trait;
+use axum::{
+    extract::{FromRequest, Path, RequestParts},
+    middleware::from_extractor,
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
+    Extension, Json, Router, TypedHeader,
+};
+use headers::{authorization::Bearer, Authorization};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::info;
+
+/// Longest compute deadline the admin API accepts, one week.
+pub const MAX_DEADLINE_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Error)]
+pub enum AdminError {
+    #[error("admin api is disabled")]
+    Disabled,
+    #[error("invalid admin key")]
+    InvalidKey,
+    #[error("unknown session id")]
+    UnknownSessionId,
+    #[error("no contribution in progress")]
+    NoActiveContributor,
+    #[error("contribution is being verified")]
+    UploadReceived,
+    #[error("deadline must be between 1 and {MAX_DEADLINE_SECONDS} seconds, got {0}")]
+    InvalidDeadline(u64),
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Disabled => StatusCode::FORBIDDEN,
+            Self::InvalidKey => StatusCode::UNAUTHORIZED,
+            Self::UnknownSessionId | Self::NoActiveContributor => StatusCode::NOT_FOUND,
+            Self::UploadReceived => StatusCode::CONFLICT,
+            Self::InvalidDeadline(_) => StatusCode::BAD_REQUEST,
+            Self::Storage(storage_error) => return storage_error.into_response(),
+        };
+        let body = Json(json!({ "error": self.to_string() }));
+        (status, body).into_response()
+    }
+}
+
+/// Extractor that only accepts requests with the `--admin-key` as bearer
+/// token.
+pub struct AdminKey;
+
+#[async_trait]
+impl<B> FromRequest<B> for AdminKey
+where
+    B: Send,
+{
+    type Rejection = AdminError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(options) = Extension::<Options>::from_request(req)
+            .await
+            .map_err(|_| AdminError::Disabled)?;
+        let key = options.admin.admin_key.ok_or(AdminError::Disabled)?;
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request(req)
+                .await
+                .map_err(|_| AdminError::InvalidKey)?;
+        if constant_time_eq(bearer.token().as_bytes(), key.get_secret().as_bytes()) {
+            Ok(Self)
+        } else {
+            Err(AdminError::InvalidKey)
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Routes of the admin API, all protected by [`AdminKey`].
+pub fn router() -> Router {
+    Router::new()
+        .route("/lobby", get(lobby_overview))
+        .route("/lobby/:session_id", delete(kick))
+        .route("/expire", post(expire))
+        .route("/bans/:uid", put(ban).delete(unban))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/lobby_options", patch(update_lobby_options))
+        .route("/actions", get(actions))
+        .route_layer(from_extractor::<AdminKey>())
+}
+
+#[derive(Debug, Serialize)]
+pub struct LobbyOptionsResponse {
+    max_lobby_size:                   usize,
+    compute_deadline_seconds:         u64,
+    offline_compute_deadline_seconds: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParticipantOverview {
+    session_id:             String,
+    uid:                    String,
+    nickname:               String,
+    provider:               String,
+    seconds_since_check_in: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActiveContributorOverview {
+    session_id:   String,
+    uid:          String,
+    nickname:     String,
+    provider:     String,
+    slot:         &'static str,
+    held_seconds: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LobbyOverview {
+    admissions_paused:  bool,
+    lobby_options:      LobbyOptionsResponse,
+    active_contributor: Option<ActiveContributorOverview>,
+    participants:       Vec<ParticipantOverview>,
+}
+
+async fn lobby_options_response(
+    admin_state: &SharedAdminState,
+    options: &Options,
+) -> LobbyOptionsResponse {
+    let lobby_options = admin_state.read().await.lobby_options(&options.lobby);
+    LobbyOptionsResponse {
+        max_lobby_size:                   lobby_options.max_lobby_size,
+        compute_deadline_seconds:         lobby_options.compute_deadline.as_secs(),
+        offline_compute_deadline_seconds: lobby_options.offline_compute_deadline.as_secs(),
+    }
+}
+
+/// Lists the active contributor and the participants in the lobby, in queue
+/// order.
+pub async fn lobby_overview(
+    Extension(contributor_state): Extension<SharedContributorState>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(admin_state): Extension<SharedAdminState>,
+    Extension(options): Extension<Options>,
+) -> Json<LobbyOverview> {
+    let active_contributor = {
+        let contributor = contributor_state.read().await;
+        contributor
+            .active
+            .as_ref()
+            .map(
+                |(session_id, session_info, slot)| ActiveContributorOverview {
+                    session_id:   session_id.to_string(),
+                    uid:          session_info.token.unique_identifier().to_owned(),
+                    nickname:     session_info.token.nickname.clone(),
+                    provider:     session_info.token.provider.clone(),
+                    slot:         slot.as_str(),
+                    held_seconds: session_info.last_ping_time.elapsed().as_secs(),
+                },
+            )
+    };
+    let participants = lobby_state
+        .read()
+        .await
+        .participants
+        .iter()
+        .map(|(session_id, session_info)| ParticipantOverview {
+            session_id:             session_id.to_string(),
+            uid:                    session_info.token.unique_identifier().to_owned(),
+            nickname:               session_info.token.nickname.clone(),
+            provider:               session_info.token.provider.clone(),
+            seconds_since_check_in: session_info.last_ping_time.elapsed().as_secs(),
+        })
+        .collect();
+    Json(LobbyOverview {
+        admissions_paused: admin_state.read().await.admissions_paused,
+        lobby_options: lobby_options_response(&admin_state, &options).await,
+        active_contributor,
+        participants,
+    })
+}
+
+/// Removes a participant from the lobby. They can sign in again.
+pub async fn kick(
+    Path(session_id): Path<String>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Value>, AdminError> {
+    let session_id = SessionId::from(session_id);
+    lobby_state
+        .write()
+        .await
+        .participants
+        .shift_remove(&session_id)
+        .ok_or(AdminError::UnknownSessionId)?;
+    info!(%session_id, "Participant kicked from the lobby");
+    storage
+        .log_admin_action("kick", Some(&session_id.to_string()))
+        .await?;
+    Ok(Json(
+        json!({ "message": "participant removed from the lobby" }),
+    ))
+}
+
+/// Expires the current contribution slot, as if its deadline passed. A slot
+/// whose upload is being verified is kept.
+pub async fn expire(
+    Extension(contributor_state): Extension<SharedContributorState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Value>, AdminError> {
+    let uid = match expire_current_contributor(contributor_state, &storage, |_| true).await? {
+        Expiry::Expired(uid) => uid,
+        Expiry::UploadReceived => return Err(AdminError::UploadReceived),
+        Expiry::NotHeld => return Err(AdminError::NoActiveContributor),
+    };
+    info!(%uid, "Contribution slot expired by operator");
+    storage.log_admin_action("expire", Some(&uid)).await?;
+    Ok(Json(json!({ "message": "contribution slot expired" })))
+}
+
+/// Bans a participant from signing in. Signs them out and expires their slot
+/// if they hold it, unless their upload is already being verified.
+pub async fn ban(
+    Path(uid): Path<String>,
+    Extension(contributor_state): Extension<SharedContributorState>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(auth_state): Extension<SharedAuthState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Value>, AdminError> {
+    storage.ban(&uid).await?;
+
+    let session_id = auth_state.write().await.unique_id_session.remove(&uid);
+    if let Some(session_id) = session_id {
+        lobby_state
+            .write()
+            .await
+            .participants
+            .shift_remove(&session_id);
+    }
+
+    let expiry = expire_current_contributor(contributor_state, &storage, |(_, session_info, _)| {
+        session_info.token.unique_identifier() == uid
+    })
+    .await?;
+    if expiry == Expiry::UploadReceived {
+        info!(%uid, "Banned participant keeps the slot while their upload is verified");
+    }
+
+    info!(%uid, "Participant banned");
+    storage.log_admin_action("ban", Some(&uid)).await?;
+    Ok(Json(json!({ "message": "participant banned" })))
+}
+
+pub async fn unban(
+    Path(uid): Path<String>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Value>, AdminError> {
+    storage.unban(&uid).await?;
+    info!(%uid, "Participant unbanned");
+    storage.log_admin_action("unban", Some(&uid)).await?;
+    Ok(Json(json!({ "message": "participant unbanned" })))
+}
+
+pub async fn pause(
+    Extension(admin_state): Extension<SharedAdminState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Value>, AdminError> {
+    admin_state.write().await.admissions_paused = true;
+    info!("Admissions paused");
+    storage.log_admin_action("pause", None).await?;
+    Ok(Json(json!({ "message": "admissions paused" })))
+}
+
+pub async fn resume(
+    Extension(admin_state): Extension<SharedAdminState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Value>, AdminError> {
+    admin_state.write().await.admissions_paused = false;
+    info!("Admissions resumed");
+    storage.log_admin_action("resume", None).await?;
+    Ok(Json(json!({ "message": "admissions resumed" })))
+}
+
+/// Lobby options to change. Options that are left out keep their value.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LobbyOptionsUpdate {
+    max_lobby_size:                   Option<usize>,
+    compute_deadline_seconds:         Option<u64>,
+    offline_compute_deadline_seconds: Option<u64>,
+}
+
+pub async fn update_lobby_options(
+    Json(update): Json<LobbyOptionsUpdate>,
+    Extension(admin_state): Extension<SharedAdminState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(options): Extension<Options>,
+) -> Result<Json<LobbyOptionsResponse>, AdminError> {
+    let deadlines = [
+        update.compute_deadline_seconds,
+        update.offline_compute_deadline_seconds,
+    ];
+    if let Some(seconds) = deadlines
+        .into_iter()
+        .flatten()
+        .find(|seconds| !(1..=MAX_DEADLINE_SECONDS).contains(seconds))
+    {
+        return Err(AdminError::InvalidDeadline(seconds));
+    }
+    {
+        let mut admin = admin_state.write().await;
+        if let Some(max_lobby_size) = update.max_lobby_size {
+            admin.max_lobby_size = Some(max_lobby_size);
+        }
+        if let Some(seconds) = update.compute_deadline_seconds {
+            admin.compute_deadline = Some(Duration::from_secs(seconds));
+        }
+        if let Some(seconds) = update.offline_compute_deadline_seconds {
+            admin.offline_compute_deadline = Some(Duration::from_secs(seconds));
+        }
+    }
+    let details = serde_json::to_string(&update).expect("update can be serialized");
+    info!(%details, "Lobby options updated");
+    storage
+        .log_admin_action("update_lobby_options", Some(&details))
+        .await?;
+    Ok(Json(lobby_options_response(&admin_state, &options).await))
+}
+
+/// The audit log of admin actions, oldest first.
+pub async fn actions(
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Value>, AdminError> {
+    let actions = storage
+        .admin_actions()
+        .await?
+        .into_iter()
+        .map(|(action, details)| json!({ "action": action, "details": details }))
+        .collect::<Vec<_>>();
+    Ok(Json(json!({ "actions": actions })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::v1::lobby::{try_contribute, TryContributeError},
+        format::Format,
+        lobby::{clear_current_contributor, set_current_contributor, ContributionSlot},
+        storage::storage_client,
+        test_util::{create_test_session_info, test_options},
+        tests::test_transcript,
+        SharedTranscript,
+    };
+
+    #[tokio::test]
+    async fn pause_holds_the_slot() {
+        let opts = test_options();
+        let contributor_state = SharedContributorState::default();
+        let lobby_state = SharedLobbyState::default();
+        let admin_state = SharedAdminState::default();
+        let transcript = SharedTranscript::new(test_transcript());
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        tokio::time::pause();
+
+        let session_id = SessionId::new();
+        lobby_state
+            .write()
+            .await
+            .participants
+            .insert(session_id.clone(), create_test_session_info(100));
+
+        pause(Extension(admin_state.clone()), Extension(db.clone()))
+            .await
+            .unwrap();
+        let check_in = || {
+            try_contribute(
+                session_id.clone(),
+                Extension(contributor_state.clone()),
+                Extension(lobby_state.clone()),
+                Extension(admin_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+                Format::Json,
+            )
+        };
+        assert!(matches!(
+            check_in().await,
+            Err(TryContributeError::WaitingInQueue { position: 1, .. })
+        ));
+        assert!(contributor_state.read().await.active.is_none());
+
+        resume(Extension(admin_state.clone()), Extension(db.clone()))
+            .await
+            .unwrap();
+        tokio::time::advance(opts.lobby.lobby_checkin_frequency).await;
+        check_in().await.unwrap();
+        assert!(contributor_state.read().await.active.is_some());
+
+        expire(Extension(contributor_state.clone()), Extension(db.clone()))
+            .await
+            .unwrap();
+        assert!(contributor_state.read().await.active.is_none());
+        assert!(matches!(
+            expire(Extension(contributor_state.clone()), Extension(db.clone())).await,
+            Err(AdminError::NoActiveContributor)
+        ));
+
+        let logged = db.admin_actions().await.unwrap();
+        let logged = logged
+            .iter()
+            .map(|(action, _)| action.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(logged, ["pause", "resume", "expire"]);
+    }
+
+    #[tokio::test]
+    async fn expire_keeps_upload_being_verified() {
+        let opts = test_options();
+        let contributor_state = SharedContributorState::default();
+        let lobby_state = SharedLobbyState::default();
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let verified = SessionId::new();
+        lobby_state
+            .write()
+            .await
+            .participants
+            .insert(verified.clone(), create_test_session_info(100));
+        set_current_contributor(
+            contributor_state.clone(),
+            lobby_state.clone(),
+            &db,
+            verified.clone(),
+            ContributionSlot::Online,
+        )
+        .await
+        .unwrap();
+        contributor_state.write().await.upload_received = true;
+
+        assert!(matches!(
+            expire(Extension(contributor_state.clone()), Extension(db.clone())).await,
+            Err(AdminError::UploadReceived)
+        ));
+        assert!(contributor_state.read().await.active.is_some());
+
+        // Once the verification releases the slot, the next participant takes
+        // it. A late release of the earlier session leaves it with them.
+        clear_current_contributor(contributor_state.clone(), &db, &verified)
+            .await
+            .unwrap();
+        let next = SessionId::new();
+        lobby_state
+            .write()
+            .await
+            .participants
+            .insert(next.clone(), create_test_session_info(100));
+        set_current_contributor(
+            contributor_state.clone(),
+            lobby_state.clone(),
+            &db,
+            next.clone(),
+            ContributionSlot::Online,
+        )
+        .await
+        .unwrap();
+        clear_current_contributor(contributor_state.clone(), &db, &verified)
+            .await
+            .unwrap();
+        assert!(matches!(
+            &contributor_state.read().await.active,
+            Some((id, ..)) if id == &next
+        ));
+        assert!(db.admin_actions().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ban_signs_out() {
+        let opts = test_options();
+        let contributor_state = SharedContributorState::default();
+        let lobby_state = SharedLobbyState::default();
+        let auth_state = SharedAuthState::default();
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let session_id = SessionId::new();
+        let session_info = create_test_session_info(100);
+        let uid = session_info.token.unique_identifier().to_owned();
+        lobby_state
+            .write()
+            .await
+            .participants
+            .insert(session_id.clone(), session_info);
+        auth_state
+            .write()
+            .await
+            .unique_id_session
+            .insert(uid.clone(), session_id.clone());
+
+        ban(
+            Path(uid.clone()),
+            Extension(contributor_state.clone()),
+            Extension(lobby_state.clone()),
+            Extension(auth_state.clone()),
+            Extension(db.clone()),
+        )
+        .await
+        .unwrap();
+        assert!(db.is_banned(&uid).await.unwrap());
+        assert!(lobby_state.read().await.participants.is_empty());
+        assert!(auth_state.read().await.unique_id_session.is_empty());
+        assert!(matches!(
+            kick(
+                Path(session_id.to_string()),
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+            )
+            .await,
+            Err(AdminError::UnknownSessionId)
+        ));
+
+        unban(Path(uid.clone()), Extension(db.clone()))
+            .await
+            .unwrap();
+        assert!(!db.is_banned(&uid).await.unwrap());
+        assert_eq!(db.admin_actions().await.unwrap(), [
+            ("ban".to_owned(), Some(uid.clone())),
+            ("unban".to_owned(), Some(uid)),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn lobby_options_overrides() {
+        let opts = test_options();
+        let admin_state = SharedAdminState::default();
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let update = LobbyOptionsUpdate {
+            max_lobby_size:                   Some(3),
+            compute_deadline_seconds:         None,
+            offline_compute_deadline_seconds: Some(90),
+        };
+        let Json(response) = update_lobby_options(
+            Json(update),
+            Extension(admin_state.clone()),
+            Extension(db.clone()),
+            Extension(opts.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.max_lobby_size, 3);
+        assert_eq!(
+            response.compute_deadline_seconds,
+            opts.lobby.compute_deadline.as_secs()
+        );
+        assert_eq!(response.offline_compute_deadline_seconds, 90);
+
+        let lobby_options = admin_state.read().await.lobby_options(&opts.lobby);
+        assert_eq!(lobby_options.max_lobby_size, 3);
+        assert_eq!(lobby_options.compute_deadline, opts.lobby.compute_deadline);
+        assert_eq!(
+            lobby_options.offline_compute_deadline,
+            Duration::from_secs(90)
+        );
+
+        for seconds in [0, MAX_DEADLINE_SECONDS + 1, u64::MAX] {
+            let update = LobbyOptionsUpdate {
+                max_lobby_size:                   None,
+                compute_deadline_seconds:         Some(seconds),
+                offline_compute_deadline_seconds: None,
+            };
+            assert!(matches!(
+                update_lobby_options(
+                    Json(update),
+                    Extension(admin_state.clone()),
+                    Extension(db.clone()),
+                    Extension(opts.clone()),
+                )
+                .await,
+                Err(AdminError::InvalidDeadline(s)) if s == seconds
+            ));
+        }
+        let lobby_options = admin_state.read().await.lobby_options(&opts.lobby);
+        assert_eq!(lobby_options.compute_deadline, opts.lobby.compute_deadline);
+        assert_eq!(db.admin_actions().await.unwrap().len(), 1);
+    }
+}
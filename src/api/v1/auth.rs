@@ -1,5 +1,7 @@
 use crate::{
+    admin::SharedAdminState,
     lobby::SharedLobbyState,
+    metrics::{set_lobby_size, variant_name, AUTH_ATTEMPTS},
     oauth::{EthOAuthClient, GithubOAuthClient, SharedAuthState},
     sessions::IdToken,
     storage::{PersistentStorage, StorageError},
@@ -43,6 +45,10 @@ pub enum AuthError {
     LobbyIsFull,
     #[error("user already contributed")]
     UserAlreadyContributed,
+    #[error("user is banned")]
+    UserBanned,
+    #[error("admissions are paused")]
+    AdmissionsPaused,
     #[error("invalid csrf token")]
     InvalidCsrf,
     #[error("invalid auth code")]
@@ -124,6 +130,14 @@ impl IntoResponse for AuthError {
                 let body = Json(json!({ "error": "user has already contributed" }));
                 (StatusCode::BAD_REQUEST, body)
             }
+            Self::UserBanned => {
+                let body = Json(json!({ "error": "user is banned" }));
+                (StatusCode::FORBIDDEN, body)
+            }
+            Self::AdmissionsPaused => {
+                let body = Json(json!({ "error": "admissions are paused" }));
+                (StatusCode::SERVICE_UNAVAILABLE, body)
+            }
             Self::UserCreatedAfterDeadline => {
                 let body = Json(json!({ "error": "user account was created after the deadline"}));
                 (StatusCode::UNAUTHORIZED, body)
@@ -146,6 +160,7 @@ pub async fn auth_client_link(
     Extension(options): Extension<Options>,
     Extension(auth_state): Extension<SharedAuthState>,
     Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(admin_state): Extension<SharedAdminState>,
     Extension(eth_client): Extension<EthOAuthClient>,
     Extension(gh_client): Extension<GithubOAuthClient>,
 ) -> Result<AuthUrl, AuthError> {
@@ -153,8 +168,12 @@ pub async fn auth_client_link(
     // Note: we use CSRF tokens, so just copying the url will not work either
     //
     {
+        let admin = admin_state.read().await;
+        if admin.admissions_paused {
+            return Err(AuthError::AdmissionsPaused);
+        }
         let lobby_size = lobby_state.read().await.participants.len();
-        if lobby_size >= options.lobby.max_lobby_size {
+        if lobby_size >= admin.lobby_options(&options.lobby).max_lobby_size {
             return Err(AuthError::LobbyIsFull);
         }
     }
@@ -233,34 +252,39 @@ pub async fn github_callback(
     Extension(gh_oauth_client): Extension<GithubOAuthClient>,
     Extension(http_client): Extension<reqwest::Client>,
 ) -> Result<UserVerified, AuthError> {
-    verify_csrf(&payload, &auth_state).await?;
-    let token = gh_oauth_client
-        .exchange_code(AuthorizationCode::new(payload.code))
-        .request_async(async_http_client)
-        .await
-        .map_err(|_| AuthError::InvalidAuthCode)?;
-
-    let response = http_client
-        .get(options.github.gh_userinfo_url)
-        .bearer_auth(token.access_token().secret())
-        .header("User-Agent", "ethereum-kzg-ceremony-sequencer")
-        .send()
-        .await
-        .map_err(|_| AuthError::FetchUserDataError)?;
-    let gh_user_info = response
-        .json::<GhUserInfo>()
-        .await
-        .map_err(|_| AuthError::CouldNotExtractUserData)?;
-    let creation_time = DateTime::parse_from_rfc3339(&gh_user_info.created_at)
-        .map_err(|_| AuthError::CouldNotExtractUserData)?;
-    if creation_time > options.github.gh_max_account_creation_time {
-        return Err(AuthError::UserCreatedAfterDeadline);
+    let result = async {
+        verify_csrf(&payload, &auth_state).await?;
+        let token = gh_oauth_client
+            .exchange_code(AuthorizationCode::new(payload.code))
+            .request_async(async_http_client)
+            .await
+            .map_err(|_| AuthError::InvalidAuthCode)?;
+
+        let response = http_client
+            .get(options.github.gh_userinfo_url)
+            .bearer_auth(token.access_token().secret())
+            .header("User-Agent", "ethereum-kzg-ceremony-sequencer")
+            .send()
+            .await
+            .map_err(|_| AuthError::FetchUserDataError)?;
+        let gh_user_info = response
+            .json::<GhUserInfo>()
+            .await
+            .map_err(|_| AuthError::CouldNotExtractUserData)?;
+        let creation_time = DateTime::parse_from_rfc3339(&gh_user_info.created_at)
+            .map_err(|_| AuthError::CouldNotExtractUserData)?;
+        if creation_time > options.github.gh_max_account_creation_time {
+            return Err(AuthError::UserCreatedAfterDeadline);
+        }
+        let user = AuthenticatedUser {
+            uid:      format!("github | {}", gh_user_info.login),
+            nickname: gh_user_info.login,
+        };
+        post_authenticate(auth_state, lobby_state, storage, user, AuthProvider::Github).await
     }
-    let user = AuthenticatedUser {
-        uid:      format!("github | {}", gh_user_info.login),
-        nickname: gh_user_info.login,
-    };
-    post_authenticate(auth_state, lobby_state, storage, user, AuthProvider::Github).await
+    .await;
+    count_auth_attempt(&AuthProvider::Github, &result);
+    result
 }
 
 #[derive(Debug, Deserialize)]
@@ -288,57 +312,62 @@ pub async fn eth_callback(
     Extension(oauth_client): Extension<EthOAuthClient>,
     Extension(http_client): Extension<reqwest::Client>,
 ) -> Result<UserVerified, AuthError> {
-    verify_csrf(&payload, &auth_state).await?;
-    let token = oauth_client
-        .exchange_code(AuthorizationCode::new(payload.code))
-        .request_async(async_http_client)
+    let result = async {
+        verify_csrf(&payload, &auth_state).await?;
+        let token = oauth_client
+            .exchange_code(AuthorizationCode::new(payload.code))
+            .request_async(async_http_client)
+            .await
+            .map_err(|_| AuthError::InvalidAuthCode)?;
+
+        let response = http_client
+            .get(&options.ethereum.eth_userinfo_url)
+            .bearer_auth(token.access_token().secret())
+            .send()
+            .await
+            .map_err(|_| AuthError::FetchUserDataError)?;
+
+        let eth_user = response
+            .json::<EthUserInfo>()
+            .await
+            .map_err(|_| AuthError::CouldNotExtractUserData)?;
+
+        let addr_parts: Vec<_> = eth_user.sub.split(':').collect();
+        let address = (*addr_parts
+            .get(2)
+            .ok_or(AuthError::CouldNotExtractUserData)?)
+        .to_string();
+
+        let tx_count = get_tx_count(
+            &address,
+            &options.ethereum.eth_nonce_verification_block,
+            &http_client,
+            &options.ethereum,
+        )
         .await
-        .map_err(|_| AuthError::InvalidAuthCode)?;
+        .ok_or(AuthError::CouldNotExtractUserData)?;
 
-    let response = http_client
-        .get(&options.ethereum.eth_userinfo_url)
-        .bearer_auth(token.access_token().secret())
-        .send()
-        .await
-        .map_err(|_| AuthError::FetchUserDataError)?;
+        if tx_count < options.ethereum.eth_min_nonce {
+            return Err(AuthError::UserCreatedAfterDeadline);
+        }
 
-    let eth_user = response
-        .json::<EthUserInfo>()
+        let user_data = AuthenticatedUser {
+            uid:      format!("eth | {}", address),
+            nickname: eth_user.preferred_username,
+        };
+
+        post_authenticate(
+            auth_state,
+            lobby_state,
+            storage,
+            user_data,
+            AuthProvider::Ethereum,
+        )
         .await
-        .map_err(|_| AuthError::CouldNotExtractUserData)?;
-
-    let addr_parts: Vec<_> = eth_user.sub.split(':').collect();
-    let address = (*addr_parts
-        .get(2)
-        .ok_or(AuthError::CouldNotExtractUserData)?)
-    .to_string();
-
-    let tx_count = get_tx_count(
-        &address,
-        &options.ethereum.eth_nonce_verification_block,
-        &http_client,
-        &options.ethereum,
-    )
-    .await
-    .ok_or(AuthError::CouldNotExtractUserData)?;
-
-    if tx_count < options.ethereum.eth_min_nonce {
-        return Err(AuthError::UserCreatedAfterDeadline);
     }
-
-    let user_data = AuthenticatedUser {
-        uid:      format!("eth | {}", address),
-        nickname: eth_user.preferred_username,
-    };
-
-    post_authenticate(
-        auth_state,
-        lobby_state,
-        storage,
-        user_data,
-        AuthProvider::Ethereum,
-    )
-    .await
+    .await;
+    count_auth_attempt(&AuthProvider::Ethereum, &result);
+    result
 }
 
 // TODO: This has many failure modes and should return and eyre::Result.
@@ -369,6 +398,16 @@ async fn get_tx_count(
     u64::from_str_radix(rpc_result.trim_start_matches("0x"), 16).ok()
 }
 
+fn count_auth_attempt(provider: &AuthProvider, result: &Result<UserVerified, AuthError>) {
+    let outcome = match result {
+        Ok(_) => "Success".to_owned(),
+        Err(error) => variant_name(error),
+    };
+    AUTH_ATTEMPTS
+        .with_label_values(&[provider.to_string(), &outcome])
+        .inc();
+}
+
 async fn verify_csrf(payload: &AuthPayload, store: &SharedAuthState) -> Result<(), AuthError> {
     let auth_state = store.read().await;
     if auth_state.csrf_tokens.contains(&payload.state) {
@@ -391,6 +430,9 @@ async fn post_authenticate(
         Ok(true) => return Err(AuthError::UserAlreadyContributed),
         Ok(false) => (),
     }
+    if storage.is_banned(&user_data.uid).await? {
+        return Err(AuthError::UserBanned);
+    }
 
     // Check if this user is already in the lobby
     // If so, we send them back their session id
@@ -422,6 +464,7 @@ async fn post_authenticate(
             last_ping_time:        Instant::now(),
             is_first_ping_attempt: true,
         });
+        set_lobby_size(lobby.participants.len());
     }
 
     Ok(UserVerified {
@@ -1,24 +1,48 @@
 use crate::{
+    ceremony::{CeremonyPhase, PhaseError},
+    format::Payload,
     io::write_json_file,
     keys::{SharedKeys, Signature, SignatureError},
-    lobby::{clear_current_contributor, SharedContributorState},
+    lobby::{clear_current_contributor, ContributionSlot, SharedContributorState},
+    metrics::{
+        rejection_label, variant_name, CONTRIBUTIONS_ACCEPTED, CONTRIBUTIONS_REJECTED,
+        TRANSCRIPT_WRITE_SECONDS, VERIFICATION_SECONDS,
+    },
     receipt::Receipt,
+    sessions::IdToken,
     storage::{PersistentStorage, StorageError},
+    transcript::Frozen,
     Engine, Options, SessionId, SharedCeremonyStatus, SharedTranscript,
 };
+use async_session::async_trait;
 use axum::{
+    extract::{FromRequest, Path, RequestParts},
     response::{IntoResponse, Response},
     Extension, Json,
 };
 use axum_extra::response::ErasedJson;
 use http::StatusCode;
-use kzg_ceremony_crypto::{BatchContribution, CeremoniesError, G2};
+use indexmap::IndexMap;
+use kzg_ceremony_crypto::{
+    BatchContribution, BatchTranscript, CeremoniesError, KzgError, KzgSetup, Randomness, G2,
+};
 use serde::Serialize;
 use serde_json::json;
-use std::sync::atomic::Ordering;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{atomic::Ordering, Arc},
+    time::Instant,
+};
 use thiserror::Error;
+use tokio::{sync::RwLock, task::JoinHandle};
+use tracing::{error, info};
+use uuid::Uuid;
 
-#[derive(Serialize)]
+/// Number of finished jobs kept for status requests.
+const MAX_FINISHED_JOBS: usize = 1000;
+
+#[derive(Clone, Serialize)]
 pub struct ContributeReceipt<T> {
     receipt:   Receipt<T>,
     signature: Signature,
@@ -30,12 +54,160 @@ impl<T: Serialize> IntoResponse for ContributeReceipt<T> {
     }
 }
 
+/// Set by the `Prefer: respond-async` header. The upload is then accepted
+/// right away and verified in the background, see [`contribute_status`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RespondAsync(pub bool);
+
+#[async_trait]
+impl<B> FromRequest<B> for RespondAsync
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let respond_async = req
+            .headers()
+            .get_all("prefer")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|preference| preference.trim().eq_ignore_ascii_case("respond-async"));
+        Ok(Self(respond_async))
+    }
+}
+
+/// Status of a contribution submitted with [`RespondAsync`].
+#[derive(Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    Accepted(ContributeReceipt<Vec<G2>>),
+    Rejected { error: Rejection },
+}
+
+impl From<Result<ContributeReceipt<Vec<G2>>, ContributeError>> for JobStatus {
+    fn from(result: Result<ContributeReceipt<Vec<G2>>, ContributeError>) -> Self {
+        match result {
+            Ok(receipt) => Self::Accepted(receipt),
+            Err(error) => Self::Rejected {
+                error: error.into(),
+            },
+        }
+    }
+}
+
+/// Why a contribution submitted with [`RespondAsync`] was not accepted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Rejection {
+    /// The failed check, as in the `contributions_rejected_total` metric, or
+    /// the error variant if the contribution was not checked.
+    pub kind:     String,
+    /// The sub-ceremony that failed the check, if it was a single one.
+    pub ceremony: Option<usize>,
+    pub message:  String,
+}
+
+impl From<ContributeError> for Rejection {
+    fn from(error: ContributeError) -> Self {
+        let (kind, ceremony) = match &error {
+            ContributeError::InvalidContribution(e) => {
+                let ceremony = match e {
+                    CeremoniesError::InvalidCeremony(i, _) => Some(*i),
+                    _ => None,
+                };
+                (rejection_label(e), ceremony)
+            }
+            error => (variant_name(error), None),
+        };
+        Self {
+            kind,
+            ceremony,
+            message: error.to_string(),
+        }
+    }
+}
+
+struct Job {
+    session_id: SessionId,
+    status:     JobStatus,
+}
+
+/// Contributions submitted with [`RespondAsync`]. Jobs are kept in memory
+/// only, so they are lost on a restart.
+#[derive(Default)]
+pub struct ContributionJobs {
+    /// Jobs that are still being verified, by the session that submitted
+    /// them. Only the holder of the slot can submit, so this stays small and
+    /// is never evicted.
+    pending:  HashMap<String, SessionId>,
+    /// Finished jobs, oldest first.
+    finished: IndexMap<String, Job>,
+}
+
+impl ContributionJobs {
+    fn start(&mut self, session_id: SessionId) -> String {
+        let id = Uuid::new_v4().to_string();
+        self.pending.insert(id.clone(), session_id);
+        id
+    }
+
+    fn finish(&mut self, id: &str, status: JobStatus) {
+        if let Some(session_id) = self.pending.remove(id) {
+            while self.finished.len() >= MAX_FINISHED_JOBS {
+                self.finished.shift_remove_index(0);
+            }
+            self.finished
+                .insert(id.to_owned(), Job { session_id, status });
+        }
+    }
+
+    /// The status of a job, if it was submitted by `session_id`.
+    fn status(&self, id: &str, session_id: &SessionId) -> Option<JobStatus> {
+        if let Some(owner) = self.pending.get(id) {
+            return (owner == session_id).then_some(JobStatus::Pending);
+        }
+        self.finished
+            .get(id)
+            .filter(|job| &job.session_id == session_id)
+            .map(|job| job.status.clone())
+    }
+}
+
+pub type SharedContributionJobs = Arc<RwLock<ContributionJobs>>;
+
+pub enum ContributeResponse {
+    Receipt(ContributeReceipt<Vec<G2>>),
+    Accepted { job_id: String },
+}
+
+impl IntoResponse for ContributeResponse {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Receipt(receipt) => receipt.into_response(),
+            Self::Accepted { job_id } => {
+                let body = Json(json!({ "job_id": job_id }));
+                (StatusCode::ACCEPTED, body).into_response()
+            }
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ContributeError {
     #[error("not your turn to participate")]
     NotUsersTurn,
+    #[error("contribution already received")]
+    AlreadyReceived,
+    #[error("unknown job id")]
+    UnknownJob,
     #[error("contribution invalid: {0}")]
     InvalidContribution(#[from] CeremoniesError),
+    #[error("ceremony is finalized")]
+    Finalized(#[from] Frozen),
+    #[error("verification did not complete")]
+    VerificationIncomplete,
     #[error("signature error: {0}")]
     Signature(SignatureError),
     #[error("storage error: {0}")]
@@ -49,10 +221,23 @@ impl IntoResponse for ContributeError {
                 let body = Json(json!({"error" : "not your turn to participate"}));
                 (StatusCode::BAD_REQUEST, body)
             }
+            Self::AlreadyReceived => {
+                let body = Json(json!({"error" : "contribution already received"}));
+                (StatusCode::CONFLICT, body)
+            }
+            Self::UnknownJob => {
+                let body = Json(json!({"error" : "unknown job id"}));
+                (StatusCode::NOT_FOUND, body)
+            }
             Self::InvalidContribution(e) => {
                 let body = Json(json!({ "error": format!("contribution invalid: {}", e) }));
                 (StatusCode::BAD_REQUEST, body)
             }
+            Self::VerificationIncomplete => {
+                let body = Json(json!({"error" : "verification did not complete"}));
+                (StatusCode::INTERNAL_SERVER_ERROR, body)
+            }
+            Self::Finalized(_) => return PhaseError(CeremonyPhase::Finalized).into_response(),
             Self::Signature(err) => return err.into_response(),
             Self::StorageError(err) => return err.into_response(),
         };
@@ -64,76 +249,309 @@ impl IntoResponse for ContributeError {
 #[allow(clippy::too_many_arguments)]
 pub async fn contribute(
     session_id: SessionId,
-    Json(contribution): Json<BatchContribution>,
+    respond_async: RespondAsync,
+    Payload(contribution): Payload<BatchContribution>,
     Extension(contributor_state): Extension<SharedContributorState>,
     Extension(options): Extension<Options>,
     Extension(shared_transcript): Extension<SharedTranscript>,
     Extension(storage): Extension<PersistentStorage>,
     Extension(num_contributions): Extension<SharedCeremonyStatus>,
     Extension(keys): Extension<SharedKeys>,
-) -> Result<ContributeReceipt<Vec<G2>>, ContributeError> {
-    // 1. Check if this person should be contributing
+    Extension(jobs): Extension<SharedContributionJobs>,
+) -> Result<ContributeResponse, ContributeError> {
+    contribute_in_slot(
+        session_id,
+        ContributionSlot::Online,
+        respond_async,
+        contribution,
+        contributor_state,
+        options,
+        shared_transcript,
+        storage,
+        num_contributions,
+        keys,
+        jobs,
+    )
+    .await
+}
+
+/// Accepts the response of a participant holding an offline slot.
+#[allow(clippy::too_many_arguments)]
+pub async fn contribute_offline(
+    session_id: SessionId,
+    respond_async: RespondAsync,
+    Payload(contribution): Payload<BatchContribution>,
+    Extension(contributor_state): Extension<SharedContributorState>,
+    Extension(options): Extension<Options>,
+    Extension(shared_transcript): Extension<SharedTranscript>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(num_contributions): Extension<SharedCeremonyStatus>,
+    Extension(keys): Extension<SharedKeys>,
+    Extension(jobs): Extension<SharedContributionJobs>,
+) -> Result<ContributeResponse, ContributeError> {
+    contribute_in_slot(
+        session_id,
+        ContributionSlot::Offline,
+        respond_async,
+        contribution,
+        contributor_state,
+        options,
+        shared_transcript,
+        storage,
+        num_contributions,
+        keys,
+        jobs,
+    )
+    .await
+}
+
+/// Status of a contribution submitted with `Prefer: respond-async`. Only the
+/// session that submitted it can see it.
+pub async fn contribute_status(
+    session_id: SessionId,
+    Path(job_id): Path<String>,
+    Extension(jobs): Extension<SharedContributionJobs>,
+) -> Result<Json<JobStatus>, ContributeError> {
+    jobs.read()
+        .await
+        .status(&job_id, &session_id)
+        .map(Json)
+        .ok_or(ContributeError::UnknownJob)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn contribute_in_slot(
+    session_id: SessionId,
+    slot: ContributionSlot,
+    RespondAsync(respond_async): RespondAsync,
+    contribution: BatchContribution,
+    contributor_state: SharedContributorState,
+    options: Options,
+    shared_transcript: SharedTranscript,
+    storage: PersistentStorage,
+    num_contributions: SharedCeremonyStatus,
+    keys: SharedKeys,
+    jobs: SharedContributionJobs,
+) -> Result<ContributeResponse, ContributeError> {
+    // 1. Check if this person should be contributing, through the endpoint of
+    // their slot type. From here on the deadline of the slot no longer applies.
     let id_token = {
-        let active_contributor = contributor_state.read().await;
-        let (id, session_info) = active_contributor
+        let mut contributor = contributor_state.write().await;
+        let (id, session_info, active_slot) = contributor
+            .active
             .as_ref()
             .ok_or(ContributeError::NotUsersTurn)?;
-        if &session_id != id {
+        if &session_id != id || slot != *active_slot {
             return Err(ContributeError::NotUsersTurn);
         }
-        session_info.token.clone()
+        let id_token = session_info.token.clone();
+        if contributor.upload_received {
+            return Err(ContributeError::AlreadyReceived);
+        }
+        contributor.upload_received = true;
+        id_token
     };
 
     // We also know that if they were in the lobby
     // then they did not participate already because
     // when we auth participants, this is checked
 
-    // 2. Check if the program state transition was correct
-    let result = {
-        let mut transcript = shared_transcript.write().await;
-        transcript
-            .verify_add::<Engine>(contribution.clone())
-            .map_err(ContributeError::InvalidContribution)
+    // Verification runs in its own task, so a client that disconnects can not
+    // cancel it halfway and leave the slot taken.
+    let verify = tokio::spawn(verify_contribution(
+        session_id.clone(),
+        id_token,
+        contribution,
+        contributor_state,
+        options,
+        shared_transcript,
+        storage,
+        num_contributions,
+        keys,
+    ));
+    if !respond_async {
+        return join_verification(verify)
+            .await
+            .map(ContributeResponse::Receipt);
+    }
+
+    let job_id = jobs.write().await.start(session_id);
+    info!(%job_id, "Verifying contribution in the background");
+    let job = job_id.clone();
+    tokio::spawn(async move {
+        let status = join_verification(verify).await.into();
+        jobs.write().await.finish(&job, status);
+    });
+    Ok(ContributeResponse::Accepted { job_id })
+}
+
+/// Waits for a verification task. If it panicked, its [`SlotGuard`] has
+/// released the slot and the contribution is reported as not verified.
+async fn join_verification(
+    verify: JoinHandle<Result<ContributeReceipt<Vec<G2>>, ContributeError>>,
+) -> Result<ContributeReceipt<Vec<G2>>, ContributeError> {
+    verify.await.unwrap_or_else(|error| {
+        error!(%error, "Contribution verification failed");
+        Err(ContributeError::VerificationIncomplete)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn verify_contribution(
+    session_id: SessionId,
+    id_token: IdToken,
+    contribution: BatchContribution,
+    contributor_state: SharedContributorState,
+    options: Options,
+    shared_transcript: SharedTranscript,
+    storage: PersistentStorage,
+    num_contributions: SharedCeremonyStatus,
+    keys: SharedKeys,
+) -> Result<ContributeReceipt<Vec<G2>>, ContributeError> {
+    let mut slot = SlotGuard {
+        contributor_state,
+        storage,
+        session_id,
+        uid: id_token.unique_identifier().to_owned(),
+        accepted: false,
+        released: false,
+    };
+
+    // 2. Check if the program state transition was correct. This runs on a
+    // copy of the transcript, so readers are not blocked in the meantime.
+    let verification_randomness = options.verification_randomness;
+    let verified = contribution.clone();
+    let start = Instant::now();
+    let result: Result<_, ContributeError> = shared_transcript
+        .update(move |transcript| {
+            let randomness = verification_randomness.randomness(transcript, &verified);
+            transcript.verify_add_with::<Engine>(verified, randomness)?;
+            Ok(())
+        })
+        .await;
+    let outcome = if result.is_ok() {
+        "accepted"
+    } else {
+        "rejected"
     };
-    if let Err(e) = result {
-        clear_current_contributor(contributor_state).await;
-        storage
-            .expire_contribution(id_token.unique_identifier())
-            .await?;
-        return Err(e);
+    VERIFICATION_SECONDS
+        .with_label_values(&[outcome])
+        .observe(start.elapsed().as_secs_f64());
+    let transcript = match result {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            if let ContributeError::InvalidContribution(e) = &e {
+                CONTRIBUTIONS_REJECTED
+                    .with_label_values(&[&rejection_label(e)])
+                    .inc();
+            }
+            slot.release().await?;
+            return Err(e);
+        }
+    };
+    slot.accepted = true;
+    num_contributions.fetch_add(1, Ordering::Relaxed);
+    CONTRIBUTIONS_ACCEPTED.inc();
+
+    // The smoke test is a diagnostic of the published transcript and does not
+    // hold up the receipt.
+    if options.kzg_smoke_test {
+        let transcript = transcript.clone();
+        tokio::task::spawn_blocking(move || match kzg_smoke_test(&transcript) {
+            Ok(()) => info!("KZG smoke test passed"),
+            Err(e) => error!(%e, "KZG smoke test failed"),
+        });
     }
 
     let receipt = Receipt {
         id_token,
         witness: contribution.receipt(),
     };
+    let signature = receipt.sign(&keys).await;
 
-    let signature = receipt
-        .sign(&keys)
-        .await
-        .map_err(ContributeError::Signature)?;
-
+    let timer = TRANSCRIPT_WRITE_SECONDS.start_timer();
     write_json_file(
         options.transcript_file,
         options.transcript_in_progress_file,
-        shared_transcript,
+        transcript,
     )
     .await;
+    timer.observe_duration();
 
-    let uid = {
-        let guard = contributor_state.read().await;
-        guard
-            .as_ref()
-            .expect("participant is guaranteed non-empty here")
-            .0
-            .to_string()
-    };
+    slot.release().await?;
+    let signature = signature.map_err(ContributeError::Signature)?;
+    Ok(ContributeReceipt { receipt, signature })
+}
 
-    clear_current_contributor(contributor_state).await;
-    storage.finish_contribution(&uid).await?;
-    num_contributions.fetch_add(1, Ordering::Relaxed);
+/// Holds the contribution slot while a contribution is verified. It is freed
+/// by [`SlotGuard::release`], or in the background if the guard is dropped
+/// without it, for example when verification panics.
+struct SlotGuard {
+    contributor_state: SharedContributorState,
+    storage:           PersistentStorage,
+    session_id:        SessionId,
+    uid:               String,
+    /// Whether the contribution is in the published transcript.
+    accepted:          bool,
+    released:          bool,
+}
 
-    Ok(ContributeReceipt { receipt, signature })
+impl SlotGuard {
+    async fn release(&mut self) -> Result<(), StorageError> {
+        self.released = true;
+        release_slot(
+            self.contributor_state.clone(),
+            &self.storage,
+            &self.session_id,
+            &self.uid,
+            self.accepted,
+        )
+        .await
+    }
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let contributor_state = self.contributor_state.clone();
+        let storage = self.storage.clone();
+        let session_id = self.session_id.clone();
+        let uid = std::mem::take(&mut self.uid);
+        let accepted = self.accepted;
+        tokio::spawn(async move {
+            if let Err(error) =
+                release_slot(contributor_state, &storage, &session_id, &uid, accepted).await
+            {
+                error!(%error, "Failed to release the contribution slot");
+            }
+        });
+    }
+}
+
+/// Frees the slot of `session_id` for the next participant and records the
+/// outcome of the contribution.
+async fn release_slot(
+    contributor_state: SharedContributorState,
+    storage: &PersistentStorage,
+    session_id: &SessionId,
+    uid: &str,
+    accepted: bool,
+) -> Result<(), StorageError> {
+    clear_current_contributor(contributor_state, storage, session_id).await?;
+    if accepted {
+        storage.finish_contribution(uid).await
+    } else {
+        storage.expire_contribution(uid).await
+    }
+}
+
+/// Commits to and opens a random polynomial with the powers of each ceremony.
+pub fn kzg_smoke_test(transcript: &BatchTranscript) -> Result<(), KzgError> {
+    transcript.transcripts.iter().try_for_each(|transcript| {
+        KzgSetup::from_transcript(transcript).smoke_test::<Engine>(Randomness::Fresh)
+    })
 }
 
 #[cfg(test)]
@@ -151,11 +569,13 @@ mod tests {
         tests::{invalid_contribution, test_transcript, valid_contribution},
         Keys, SessionId,
     };
-    use axum::{Extension, Json};
+    use axum::Extension;
     use clap::Parser;
-    use kzg_ceremony_crypto::BatchTranscript;
-    use std::sync::{atomic::AtomicUsize, Arc};
-    use tokio::sync::RwLock;
+    use kzg_ceremony_crypto::{BatchTranscript, CeremonyError};
+    use std::{
+        sync::{atomic::AtomicUsize, Arc},
+        time::Duration,
+    };
 
     fn shared_keys() -> SharedKeys {
         let options = keys::Options::parse_from(Vec::<&str>::new());
@@ -171,13 +591,15 @@ mod tests {
         let contrbution = valid_contribution(&transcript, 1);
         let result = contribute(
             SessionId::new(),
-            Json(contrbution),
+            RespondAsync(false),
+            Payload(contrbution),
             Extension(contributor_state),
             Extension(opts),
-            Extension(Arc::new(RwLock::new(transcript))),
+            Extension(SharedTranscript::new(transcript)),
             Extension(db),
             Extension(Arc::new(AtomicUsize::new(0))),
             Extension(shared_keys()),
+            Extension(SharedContributionJobs::default()),
         )
         .await;
         assert!(matches!(result, Err(ContributeError::NotUsersTurn)));
@@ -189,19 +611,24 @@ mod tests {
         let db = storage_client(&opts.storage).await.unwrap();
         let contributor_state = SharedContributorState::default();
         let participant = SessionId::new();
-        *contributor_state.write().await =
-            Some((participant.clone(), create_test_session_info(100)));
+        contributor_state.write().await.active = Some((
+            participant.clone(),
+            create_test_session_info(100),
+            ContributionSlot::Online,
+        ));
         let transcript = test_transcript();
         let contribution = invalid_contribution(&transcript, 1);
         let result = contribute(
             participant,
-            Json(contribution),
+            RespondAsync(false),
+            Payload(contribution),
             Extension(contributor_state),
             Extension(opts),
-            Extension(Arc::new(RwLock::new(transcript))),
+            Extension(SharedTranscript::new(transcript)),
             Extension(db),
             Extension(Arc::new(AtomicUsize::new(0))),
             Extension(shared_keys()),
+            Extension(SharedContributionJobs::default()),
         )
         .await;
         assert!(matches!(
@@ -234,19 +661,24 @@ mod tests {
                 .unwrap();
             transcript
         };
-        let shared_transcript = Arc::new(RwLock::new(transcript));
+        let shared_transcript = SharedTranscript::new(transcript);
 
-        *contributor_state.write().await =
-            Some((participant.clone(), create_test_session_info(100)));
+        contributor_state.write().await.active = Some((
+            participant.clone(),
+            create_test_session_info(100),
+            ContributionSlot::Online,
+        ));
         let result = contribute(
             participant.clone(),
-            Json(contribution_1),
+            RespondAsync(false),
+            Payload(contribution_1),
             Extension(contributor_state.clone()),
             Extension(cfg.clone()),
             Extension(shared_transcript.clone()),
             Extension(db.clone()),
             Extension(Arc::new(AtomicUsize::new(0))),
             Extension(keys.clone()),
+            Extension(SharedContributionJobs::default()),
         )
         .await;
 
@@ -254,17 +686,22 @@ mod tests {
         let transcript = read_json_file::<BatchTranscript>(cfg.transcript_file.clone()).await;
         assert_eq!(transcript, transcript_1);
 
-        *contributor_state.write().await =
-            Some((participant.clone(), create_test_session_info(100)));
+        contributor_state.write().await.active = Some((
+            participant.clone(),
+            create_test_session_info(100),
+            ContributionSlot::Online,
+        ));
         let result = contribute(
             participant.clone(),
-            Json(contribution_2),
+            RespondAsync(false),
+            Payload(contribution_2),
             Extension(contributor_state.clone()),
             Extension(cfg.clone()),
             Extension(shared_transcript.clone()),
             Extension(db.clone()),
             Extension(Arc::new(AtomicUsize::new(0))),
             Extension(keys.clone()),
+            Extension(SharedContributionJobs::default()),
         )
         .await;
 
@@ -272,4 +709,252 @@ mod tests {
         let transcript = read_json_file::<BatchTranscript>(cfg.transcript_file.clone()).await;
         assert_eq!(transcript, transcript_2);
     }
+
+    #[tokio::test]
+    async fn offline_slot_uses_offline_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = test_options();
+        opts.transcript_file = dir.path().join("transcript.json");
+        opts.transcript_in_progress_file = dir.path().join("transcript.json.next");
+        let db = storage_client(&opts.storage).await.unwrap();
+        let contributor_state = SharedContributorState::default();
+        let participant = SessionId::new();
+        contributor_state.write().await.active = Some((
+            participant.clone(),
+            create_test_session_info(100),
+            ContributionSlot::Offline,
+        ));
+        let transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        let shared_transcript = SharedTranscript::new(transcript);
+
+        let result = contribute(
+            participant.clone(),
+            RespondAsync(false),
+            Payload(contribution.clone()),
+            Extension(contributor_state.clone()),
+            Extension(opts.clone()),
+            Extension(shared_transcript.clone()),
+            Extension(db.clone()),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(shared_keys()),
+            Extension(SharedContributionJobs::default()),
+        )
+        .await;
+        assert!(matches!(result, Err(ContributeError::NotUsersTurn)));
+
+        let result = contribute_offline(
+            participant,
+            RespondAsync(false),
+            Payload(contribution),
+            Extension(contributor_state.clone()),
+            Extension(opts),
+            Extension(shared_transcript.clone()),
+            Extension(db),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(shared_keys()),
+            Extension(SharedContributionJobs::default()),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(contributor_state.read().await.active.is_none());
+        assert_eq!(
+            shared_transcript.load().transcripts[0].num_contributions(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn verifies_in_background() {
+        let opts = test_options();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let contributor_state = SharedContributorState::default();
+        let jobs = SharedContributionJobs::default();
+        let participant = SessionId::new();
+        contributor_state.write().await.active = Some((
+            participant.clone(),
+            create_test_session_info(100),
+            ContributionSlot::Online,
+        ));
+        let transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        let shared_transcript = SharedTranscript::new(transcript);
+
+        let submit = || {
+            contribute(
+                participant.clone(),
+                RespondAsync(true),
+                Payload(contribution.clone()),
+                Extension(contributor_state.clone()),
+                Extension(opts.clone()),
+                Extension(shared_transcript.clone()),
+                Extension(db.clone()),
+                Extension(Arc::new(AtomicUsize::new(0))),
+                Extension(shared_keys()),
+                Extension(jobs.clone()),
+            )
+        };
+        let job_id = match submit().await {
+            Ok(ContributeResponse::Accepted { job_id }) => job_id,
+            _ => panic!("expected a job id"),
+        };
+
+        let status = || {
+            contribute_status(
+                participant.clone(),
+                Path(job_id.clone()),
+                Extension(jobs.clone()),
+            )
+        };
+        let mut attempts = 0;
+        while matches!(status().await, Ok(Json(JobStatus::Pending))) {
+            attempts += 1;
+            assert!(attempts < 1000, "verification did not finish");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(matches!(status().await, Ok(Json(JobStatus::Accepted(_)))));
+        assert_eq!(
+            shared_transcript.load().transcripts[0].num_contributions(),
+            1
+        );
+
+        // Only the submitter can see the job
+        let result = contribute_status(
+            SessionId::new(),
+            Path(job_id.clone()),
+            Extension(jobs.clone()),
+        )
+        .await;
+        assert!(matches!(result, Err(ContributeError::UnknownJob)));
+
+        // The slot is released after verification
+        assert!(matches!(submit().await, Err(ContributeError::NotUsersTurn)));
+    }
+
+    #[tokio::test]
+    async fn releases_slot_when_request_is_dropped() {
+        let opts = test_options();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let contributor_state = SharedContributorState::default();
+        let participant = SessionId::new();
+        contributor_state.write().await.active = Some((
+            participant.clone(),
+            create_test_session_info(100),
+            ContributionSlot::Online,
+        ));
+        let transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        let shared_transcript = SharedTranscript::new(transcript);
+
+        // Poll the request once, so it is waiting on verification, then drop
+        // it as a disconnecting client would.
+        let request = contribute(
+            participant,
+            RespondAsync(false),
+            Payload(contribution),
+            Extension(contributor_state.clone()),
+            Extension(opts),
+            Extension(shared_transcript.clone()),
+            Extension(db),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(shared_keys()),
+            Extension(SharedContributionJobs::default()),
+        );
+        let result = tokio::time::timeout(Duration::ZERO, request).await;
+        assert!(result.is_err(), "verification finished before the drop");
+        assert!(contributor_state.read().await.upload_received);
+
+        let mut attempts = 0;
+        while contributor_state.read().await.active.is_some() {
+            attempts += 1;
+            assert!(attempts < 1000, "slot was not released");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!contributor_state.read().await.upload_received);
+        assert_eq!(
+            shared_transcript.load().transcripts[0].num_contributions(),
+            1
+        );
+    }
+
+    #[test]
+    fn structured_rejections() {
+        let status = JobStatus::from(Err(ContributeError::InvalidContribution(
+            CeremoniesError::InvalidCeremony(1, CeremonyError::DuplicateG1(1, 3)),
+        )));
+        let json = serde_json::to_value(status).unwrap();
+        assert_eq!(json["status"], "rejected");
+        assert_eq!(json["error"]["kind"], "DuplicateG1");
+        assert_eq!(json["error"]["ceremony"], 1);
+        assert!(json["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("contribution invalid"));
+
+        let rejection = Rejection::from(ContributeError::Finalized(Frozen));
+        assert_eq!(rejection.kind, "Finalized");
+        assert_eq!(rejection.ceremony, None);
+    }
+
+    #[tokio::test]
+    async fn reports_panicked_verification() {
+        let verify = tokio::spawn(async { panic!("verification panicked") });
+        assert!(matches!(
+            join_verification(verify).await,
+            Err(ContributeError::VerificationIncomplete)
+        ));
+        assert_eq!(
+            ContributeError::VerificationIncomplete
+                .into_response()
+                .status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn evicts_only_finished_jobs() {
+        let mut jobs = ContributionJobs::default();
+        let session_id = SessionId::new();
+        let pending = jobs.start(session_id.clone());
+        for _ in 0..=MAX_FINISHED_JOBS {
+            let id = jobs.start(session_id.clone());
+            jobs.finish(&id, JobStatus::Rejected {
+                error: ContributeError::NotUsersTurn.into(),
+            });
+        }
+        assert_eq!(jobs.finished.len(), MAX_FINISHED_JOBS);
+        assert!(matches!(
+            jobs.status(&pending, &session_id),
+            Some(JobStatus::Pending)
+        ));
+        assert!(jobs.status(&pending, &SessionId::new()).is_none());
+    }
+
+    #[tokio::test]
+    async fn respond_async_preference() {
+        let parse = |value: &'static str| async move {
+            let request = http::Request::builder()
+                .header("Prefer", value)
+                .body(())
+                .unwrap();
+            RespondAsync::from_request(&mut RequestParts::new(request))
+                .await
+                .unwrap()
+        };
+        assert_eq!(parse("respond-async").await, RespondAsync(true));
+        assert_eq!(parse("wait=10, Respond-Async").await, RespondAsync(true));
+        assert_eq!(parse("return=minimal").await, RespondAsync(false));
+    }
+
+    #[test]
+    fn kzg_smoke_test_passes_after_contribution() {
+        let mut transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        transcript.verify_add::<Engine>(contribution).unwrap();
+        assert_eq!(kzg_smoke_test(&transcript), Ok(()));
+
+        // Powers that are not of a single tau fail.
+        transcript.transcripts[0].powers.g1.swap(1, 2);
+        assert!(kzg_smoke_test(&transcript).is_err());
+    }
 }
@@ -1,7 +1,9 @@
 use crate::{
+    ceremony::{CeremonyPhase, SharedCeremonyPhase},
+    format::Format,
     keys::{Address, SharedKeys},
     lobby::SharedLobbyState,
-    Options, SharedCeremonyStatus,
+    Options, SharedCeremonyStatus, SharedTranscript,
 };
 use axum::{
     body::StreamBody,
@@ -16,6 +18,7 @@ use tokio_util::io::ReaderStream;
 
 #[derive(Debug, Serialize, PartialEq, Eq)]
 pub struct StatusResponse {
+    phase:             CeremonyPhase,
     lobby_size:        usize,
     num_contributions: usize,
     sequencer_address: Address,
@@ -32,7 +35,9 @@ pub async fn status(
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(ceremony_status): Extension<SharedCeremonyStatus>,
     Extension(keys): Extension<SharedKeys>,
+    Extension(phase): Extension<SharedCeremonyPhase>,
 ) -> StatusResponse {
+    let phase = *phase.read().await;
     let lobby_size = {
         let state = lobby_state.read().await;
         state.participants.len()
@@ -42,13 +47,21 @@ pub async fn status(
     let sequencer_address = keys.address();
 
     StatusResponse {
+        phase,
         lobby_size,
         num_contributions,
         sequencer_address,
     }
 }
 
-pub async fn current_state(Extension(options): Extension<Options>) -> impl IntoResponse {
+pub async fn current_state(
+    Extension(options): Extension<Options>,
+    Extension(transcript): Extension<SharedTranscript>,
+    format: Format,
+) -> impl IntoResponse {
+    if format == Format::Binary {
+        return Ok(format.response(&*transcript.load()));
+    }
     let f = match File::open(options.transcript_file).await {
         Ok(file) => file,
         Err(_) => {
@@ -60,5 +73,5 @@ pub async fn current_state(Extension(options): Extension<Options>) -> impl IntoR
     };
     let stream = ReaderStream::new(f);
     let body = StreamBody::new(stream);
-    Ok((StatusCode::OK, body))
+    Ok((StatusCode::OK, body).into_response())
 }
@@ -1,22 +1,34 @@
 use crate::{
+    admin::SharedAdminState,
+    format::Format,
     lobby,
     lobby::{
-        clear_current_contributor, set_current_contributor, SharedContributorState,
-        SharedLobbyState,
+        expire_current_contributor, set_current_contributor, ContributionSlot, Expiry,
+        SharedContributorState, SharedLobbyState,
     },
+    metrics::{variant_name, DEADLINE_EXPIRATIONS, TRY_CONTRIBUTE},
     storage::{PersistentStorage, StorageError},
     SessionId, SharedTranscript,
 };
 use axum::{
-    response::{IntoResponse, Response},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Extension, Json,
 };
 use http::StatusCode;
-use kzg_ceremony_crypto::BatchContribution;
+use kzg_ceremony_crypto::{BatchContribution, BinaryFormat};
 use serde::Serialize;
 use serde_json::json;
+use std::{convert::Infallible, time::Duration};
 use thiserror::Error;
-use tokio::time::Instant;
+use tokio::{
+    sync::mpsc,
+    time::{interval, Instant},
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tracing::info;
 
 #[derive(Debug, Error)]
 pub enum TryContributeError {
@@ -24,8 +36,14 @@ pub enum TryContributeError {
     UnknownSessionId,
     #[error("call came too early. rate limited")]
     RateLimited,
-    #[error("another contribution in progress")]
-    AnotherContributionInProgress,
+    #[error("waiting in queue at position {position}")]
+    WaitingInQueue {
+        /// Position in the queue, starting at one for the next contributor.
+        position:       usize,
+        estimated_wait: Duration,
+    },
+    #[error("not approved for an offline slot")]
+    NotApprovedForOffline,
     #[error("error in storage layer: {0}")]
     StorageError(#[from] StorageError),
 }
@@ -47,12 +65,23 @@ impl IntoResponse for TryContributeError {
                 (StatusCode::BAD_REQUEST, body)
             }
 
-            Self::AnotherContributionInProgress => {
+            Self::WaitingInQueue {
+                position,
+                estimated_wait,
+            } => {
                 let body = Json(json!({
-                    "message": "another contribution in progress",
+                    "message": "waiting in queue",
+                    "position": position,
+                    "estimated_wait_seconds": estimated_wait.as_secs(),
                 }));
                 (StatusCode::OK, body)
             }
+            Self::NotApprovedForOffline => {
+                let body = Json(json!({
+                    "error": "not approved for an offline slot",
+                }));
+                (StatusCode::FORBIDDEN, body)
+            }
             Self::StorageError(err) => return err.into_response(),
         };
 
@@ -63,23 +92,292 @@ impl IntoResponse for TryContributeError {
 #[derive(Debug)]
 pub struct TryContributeResponse<C> {
     contribution: C,
+    format:       Format,
 }
 
-impl<C: Serialize> IntoResponse for TryContributeResponse<C> {
+impl<C: Serialize + BinaryFormat> IntoResponse for TryContributeResponse<C> {
     fn into_response(self) -> Response {
-        (StatusCode::OK, Json(self.contribution)).into_response()
+        self.format.response(&self.contribution)
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn try_contribute(
     session_id: SessionId,
     Extension(contributor_state): Extension<SharedContributorState>,
     Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(admin_state): Extension<SharedAdminState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(options): Extension<crate::Options>,
+    format: Format,
+) -> Result<TryContributeResponse<BatchContribution>, TryContributeError> {
+    let result = start_contribution(
+        session_id,
+        ContributionSlot::Online,
+        true,
+        contributor_state,
+        lobby_state,
+        admin_state,
+        storage,
+        transcript,
+        options,
+        format,
+    )
+    .await;
+    count_try_contribute(ContributionSlot::Online, &result);
+    result
+}
+
+/// Like [`try_contribute`], but for participants approved to compute on an
+/// air-gapped machine. The slot is held until the response is submitted to
+/// `/contribute/offline` or `offline_compute_deadline` passes.
+#[allow(clippy::too_many_arguments)]
+pub async fn try_contribute_offline(
+    session_id: SessionId,
+    Extension(contributor_state): Extension<SharedContributorState>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(admin_state): Extension<SharedAdminState>,
     Extension(storage): Extension<PersistentStorage>,
     Extension(transcript): Extension<SharedTranscript>,
     Extension(options): Extension<crate::Options>,
+    format: Format,
+) -> Result<TryContributeResponse<BatchContribution>, TryContributeError> {
+    let result = start_contribution(
+        session_id,
+        ContributionSlot::Offline,
+        true,
+        contributor_state,
+        lobby_state,
+        admin_state,
+        storage,
+        transcript,
+        options,
+        format,
+    )
+    .await;
+    count_try_contribute(ContributionSlot::Offline, &result);
+    result
+}
+
+fn count_try_contribute<T>(slot: ContributionSlot, result: &Result<T, TryContributeError>) {
+    let outcome = match result {
+        Ok(_) => "YourTurn".to_owned(),
+        Err(error) => variant_name(error),
+    };
+    TRY_CONTRIBUTE
+        .with_label_values(&[slot.as_str(), &outcome])
+        .inc();
+}
+
+/// Events pushed to participants streaming `/lobby/stream`.
+#[derive(Debug)]
+pub enum LobbyEvent {
+    /// Sent when the position in the queue changes.
+    QueuePosition {
+        position:       usize,
+        estimated_wait: Duration,
+    },
+    /// The slot was taken for this participant.
+    YourTurn {
+        contribution: BatchContribution,
+        deadline:     Duration,
+    },
+    /// Sent once when `compute_deadline_warning` is left to contribute.
+    DeadlineWarning { remaining: Duration },
+    /// The participant can no longer wait in the lobby. Ends the stream.
+    Error(String),
+}
+
+impl LobbyEvent {
+    fn to_sse(&self) -> Event {
+        let (name, data) = match self {
+            Self::QueuePosition {
+                position,
+                estimated_wait,
+            } => (
+                "queue_position",
+                json!({
+                    "position": position,
+                    "estimated_wait_seconds": estimated_wait.as_secs(),
+                }),
+            ),
+            Self::YourTurn {
+                contribution,
+                deadline,
+            } => (
+                "your_turn",
+                json!({
+                    "contribution": contribution,
+                    "deadline_seconds": deadline.as_secs(),
+                }),
+            ),
+            Self::DeadlineWarning { remaining } => (
+                "deadline_warning",
+                json!({ "remaining_seconds": remaining.as_secs() }),
+            ),
+            Self::Error(error) => ("error", json!({ "error": error })),
+        };
+        Event::default()
+            .event(name)
+            .json_data(data)
+            .expect("json values can be serialized")
+    }
+}
+
+/// Streams [`LobbyEvent`]s as server-sent events. While the stream is open
+/// the participant is checked in every `lobby_stream_interval`, and takes the
+/// slot when it is their turn, so they don't need to poll
+/// `/lobby/try_contribute`.
+pub async fn lobby_stream(
+    session_id: SessionId,
+    Extension(contributor_state): Extension<SharedContributorState>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(admin_state): Extension<SharedAdminState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(options): Extension<crate::Options>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, TryContributeError> {
+    if !lobby_state
+        .read()
+        .await
+        .participants
+        .contains_key(&session_id)
+    {
+        return Err(TryContributeError::UnknownSessionId);
+    }
+
+    let (sender, receiver) = mpsc::channel(4);
+    tokio::spawn(stream_lobby_events(
+        sender,
+        session_id,
+        contributor_state,
+        lobby_state,
+        admin_state,
+        storage,
+        transcript,
+        options,
+    ));
+    let stream = ReceiverStream::new(receiver).map(|event| Ok(event.to_sse()));
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Checks the participant in on every tick until they get the slot, then
+/// warns them before the deadline. Returns when the receiver is dropped, the
+/// slot is released or an error is sent.
+#[allow(clippy::too_many_arguments)]
+async fn stream_lobby_events(
+    sender: mpsc::Sender<LobbyEvent>,
+    session_id: SessionId,
+    contributor_state: SharedContributorState,
+    lobby_state: SharedLobbyState,
+    admin_state: SharedAdminState,
+    storage: PersistentStorage,
+    transcript: SharedTranscript,
+    options: crate::Options,
+) {
+    let mut ticks = interval(options.lobby.lobby_stream_interval);
+    let mut last_position = None;
+    let mut deadline = options.lobby.compute_deadline;
+    loop {
+        ticks.tick().await;
+        if sender.is_closed() {
+            return;
+        }
+        let event = match start_contribution(
+            session_id.clone(),
+            ContributionSlot::Online,
+            false,
+            contributor_state.clone(),
+            lobby_state.clone(),
+            admin_state.clone(),
+            storage.clone(),
+            transcript.clone(),
+            options.clone(),
+            Format::Json,
+        )
+        .await
+        {
+            Ok(response) => {
+                deadline = admin_state
+                    .read()
+                    .await
+                    .lobby_options(&options.lobby)
+                    .compute_deadline;
+                LobbyEvent::YourTurn {
+                    contribution: response.contribution,
+                    deadline,
+                }
+            }
+            Err(TryContributeError::WaitingInQueue {
+                position,
+                estimated_wait,
+            }) => {
+                if last_position == Some(position) {
+                    continue;
+                }
+                last_position = Some(position);
+                LobbyEvent::QueuePosition {
+                    position,
+                    estimated_wait,
+                }
+            }
+            Err(error) => {
+                // Nothing to do if the receiver is gone
+                let _ = sender.send(LobbyEvent::Error(error.to_string())).await;
+                return;
+            }
+        };
+        let your_turn = matches!(event, LobbyEvent::YourTurn { .. });
+        if sender.send(event).await.is_err() || your_turn {
+            break;
+        }
+    }
+
+    let mut warned = false;
+    loop {
+        ticks.tick().await;
+        let remaining = {
+            let contributor = contributor_state.read().await;
+            match &contributor.active {
+                Some((id, session_info, _)) if id == &session_id => {
+                    deadline.saturating_sub(session_info.last_ping_time.elapsed())
+                }
+                _ => return,
+            }
+        };
+        if !warned && remaining <= options.lobby.compute_deadline_warning {
+            warned = true;
+            if sender
+                .send(LobbyEvent::DeadlineWarning { remaining })
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn start_contribution(
+    session_id: SessionId,
+    slot: ContributionSlot,
+    rate_limit: bool,
+    contributor_state: SharedContributorState,
+    lobby_state: SharedLobbyState,
+    admin_state: SharedAdminState,
+    storage: PersistentStorage,
+    transcript: SharedTranscript,
+    options: crate::Options,
+    format: Format,
 ) -> Result<TryContributeResponse<BatchContribution>, TryContributeError> {
     let uid: String;
+    let ahead: usize;
+    let (admissions_paused, lobby_options) = {
+        let admin = admin_state.read().await;
+        (admin.admissions_paused, admin.lobby_options(&options.lobby))
+    };
 
     // 1. Check if this is a valid session. If so, we log the ping time
     {
@@ -90,10 +388,10 @@ pub async fn try_contribute(
             .ok_or(TryContributeError::UnknownSessionId)?;
 
         let min_diff =
-            options.lobby.lobby_checkin_frequency - options.lobby.lobby_checkin_tolerance;
+            lobby_options.lobby_checkin_frequency - lobby_options.lobby_checkin_tolerance;
 
         let now = Instant::now();
-        if !info.is_first_ping_attempt && now < info.last_ping_time + min_diff {
+        if rate_limit && !info.is_first_ping_attempt && now < info.last_ping_time + min_diff {
             return Err(TryContributeError::RateLimited);
         }
 
@@ -101,74 +399,103 @@ pub async fn try_contribute(
         info.last_ping_time = now;
 
         uid = info.token.unique_identifier().to_owned();
+
+        // Participants who missed a check-in lose their turn until they check
+        // in again, so they don't hold up the queue
+        let max_diff =
+            lobby_options.lobby_checkin_frequency + lobby_options.lobby_checkin_tolerance;
+        ahead = lobby
+            .participants_ahead(&session_id, now, max_diff)
+            .expect("participant is in the lobby");
+    }
+
+    if slot == ContributionSlot::Offline && !lobby_options.offline_contributors.contains(&uid) {
+        return Err(TryContributeError::NotApprovedForOffline);
     }
 
     {
-        // Check if there is an existing contribution in progress
+        // Only the head of the queue can take a free slot, unless admissions
+        // are paused
         let contributor = contributor_state.read().await;
-        if contributor.is_some() {
-            return Err(TryContributeError::AnotherContributionInProgress);
+        if ahead > 0 || contributor.active.is_some() || admissions_paused {
+            return Err(TryContributeError::WaitingInQueue {
+                position:       ahead + 1,
+                estimated_wait: contributor.estimated_wait(ahead, &lobby_options),
+            });
         }
     }
 
     // If this insertion fails, worst case we allow multiple contributions from the
     // same participant
-    storage.insert_contributor(&uid).await?;
-    set_current_contributor(contributor_state.clone(), lobby_state, session_id.clone()).await;
+    storage.insert_contributor(&uid, slot).await?;
+    set_current_contributor(
+        contributor_state.clone(),
+        lobby_state,
+        &storage,
+        session_id.clone(),
+        slot,
+    )
+    .await?;
+    if slot == ContributionSlot::Offline {
+        info!(%uid, deadline = ?lobby_options.offline_compute_deadline, "Offline slot started");
+    }
 
-    // Start a timer to remove this user if they go over the `COMPUTE_DEADLINE`
+    // Start a timer to remove this user if they go over the deadline of their slot
     tokio::spawn(async move {
         remove_participant_on_deadline(
             contributor_state,
             storage.clone(),
             session_id,
-            uid,
-            options.lobby,
+            slot,
+            lobby_options,
         )
         .await
         .unwrap(); // TODO: Handle error
     });
 
-    let transcript = transcript.read().await;
+    let transcript = transcript.load();
 
     Ok(TryContributeResponse {
         contribution: transcript.contribution(),
+        format,
     })
 }
 
-// Clears the contribution spot on `COMPUTE_DEADLINE` interval
+// Clears the contribution spot when the deadline of the slot type passes
 // We use the session_id to avoid needing a channel to check if
 pub async fn remove_participant_on_deadline(
     contributor_state: SharedContributorState,
     storage: PersistentStorage,
     session_id: SessionId,
-    uid: String,
+    slot: ContributionSlot,
     options: lobby::Options,
 ) -> Result<(), StorageError> {
-    tokio::time::sleep(options.compute_deadline).await;
-
-    {
-        // Check if the contributor has already left the position
-        let contributor = contributor_state.read().await;
-        if let Some((participant_session_id, _)) = contributor.as_ref() {
-            //
-            if participant_session_id != &session_id {
-                // Abort, this means that the participant has already contributed and
-                // the /contribute endpoint has removed them from the contribution spot
-                return Ok(());
-            }
-        } else {
-            return Ok(());
+    // The deadline counts from the start of the slot, which may be before a
+    // restart of the sequencer
+    let started = match contributor_state.read().await.active.as_ref() {
+        Some((participant_session_id, session_info, _))
+            if participant_session_id == &session_id =>
+        {
+            session_info.last_ping_time
         }
+        _ => return Ok(()),
+    };
+    tokio::time::sleep_until(started + options.deadline(slot)).await;
+
+    // Nothing to do if the participant has already left the slot, or if their
+    // contribution arrived in time and is being verified
+    let expiry =
+        expire_current_contributor(contributor_state, &storage, |(id, ..)| id == &session_id)
+            .await?;
+    if matches!(expiry, Expiry::Expired(_)) {
+        println!(
+            "User with session id {} took too long to contribute",
+            &session_id.to_string()
+        );
+        DEADLINE_EXPIRATIONS
+            .with_label_values(&[slot.as_str()])
+            .inc();
     }
-
-    println!(
-        "User with session id {} took too long to contribute",
-        &session_id.to_string()
-    );
-
-    storage.expire_contribution(&uid).await?;
-    clear_current_contributor(contributor_state).await;
     Ok(())
 }
 
@@ -177,12 +504,12 @@ mod tests {
     use super::*;
     use crate::{
         api::v1::lobby::TryContributeError,
+        lobby::clear_current_contributor,
         storage::storage_client,
         test_util::{create_test_session_info, test_options},
         tests::test_transcript,
     };
-    use std::{sync::Arc, time::Duration};
-    use tokio::sync::RwLock;
+    use std::time::Duration;
 
     #[tokio::test]
     #[allow(clippy::too_many_lines)]
@@ -190,7 +517,7 @@ mod tests {
         let opts = test_options();
         let contributor_state = SharedContributorState::default();
         let lobby_state = SharedLobbyState::default();
-        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let transcript = SharedTranscript::new(test_transcript());
         let db = storage_client(&opts.storage).await.unwrap();
 
         let session_id = SessionId::new();
@@ -204,9 +531,11 @@ mod tests {
             session_id.clone(),
             Extension(contributor_state.clone()),
             Extension(lobby_state.clone()),
+            Extension(SharedAdminState::default()),
             Extension(db.clone()),
             Extension(transcript.clone()),
             Extension(opts),
+            Format::Json,
         )
         .await;
         assert!(matches!(
@@ -214,15 +543,15 @@ mod tests {
             Err(TryContributeError::UnknownSessionId)
         ));
 
-        // add two participants to lobby
+        // add two participants to lobby, "other participant" first
         {
             let mut state = lobby_state.write().await;
             state
                 .participants
-                .insert(session_id.clone(), create_test_session_info(100));
+                .insert(other_session_id.clone(), create_test_session_info(100));
             state
                 .participants
-                .insert(other_session_id.clone(), create_test_session_info(100));
+                .insert(session_id.clone(), create_test_session_info(100));
         }
 
         // "other participant" is contributing
@@ -230,9 +559,11 @@ mod tests {
             other_session_id.clone(),
             Extension(contributor_state.clone()),
             Extension(lobby_state.clone()),
+            Extension(SharedAdminState::default()),
             Extension(db.clone()),
             Extension(transcript.clone()),
             Extension(test_options()),
+            Format::Json,
         )
         .await
         .unwrap();
@@ -240,14 +571,16 @@ mod tests {
             session_id.clone(),
             Extension(contributor_state.clone()),
             Extension(lobby_state.clone()),
+            Extension(SharedAdminState::default()),
             Extension(db.clone()),
             Extension(transcript.clone()),
             Extension(test_options()),
+            Format::Json,
         )
         .await;
         assert!(matches!(
             contribution_in_progress_response,
-            Err(TryContributeError::AnotherContributionInProgress)
+            Err(TryContributeError::WaitingInQueue { position: 1, .. })
         ));
 
         // call the endpoint too soon - rate limited, other participant computing
@@ -256,9 +589,11 @@ mod tests {
             session_id.clone(),
             Extension(contributor_state.clone()),
             Extension(lobby_state.clone()),
+            Extension(SharedAdminState::default()),
             Extension(db.clone()),
             Extension(transcript.clone()),
             Extension(test_options()),
+            Format::Json,
         )
         .await;
 
@@ -271,7 +606,7 @@ mod tests {
         // "other participant" finished contributing
         {
             let mut state = contributor_state.write().await;
-            *state = None;
+            state.active = None;
         }
 
         // call the endpoint too soon - rate limited, no one computing
@@ -280,9 +615,11 @@ mod tests {
             session_id.clone(),
             Extension(contributor_state.clone()),
             Extension(lobby_state.clone()),
+            Extension(SharedAdminState::default()),
             Extension(db.clone()),
             Extension(transcript.clone()),
             Extension(test_options()),
+            Format::Json,
         )
         .await;
         assert!(matches!(
@@ -296,16 +633,246 @@ mod tests {
             session_id.clone(),
             Extension(contributor_state.clone()),
             Extension(lobby_state.clone()),
+            Extension(SharedAdminState::default()),
             Extension(db.clone()),
             Extension(transcript.clone()),
             Extension(test_options()),
+            Format::Json,
         )
         .await;
         assert!(matches!(
             success_response,
             Ok(TryContributeResponse {
                 contribution: BatchContribution { .. },
+                format:       Format::Json,
             })
         ));
     }
+
+    #[tokio::test]
+    async fn offline_slot_test() {
+        let mut opts = test_options();
+        let contributor_state = SharedContributorState::default();
+        let lobby_state = SharedLobbyState::default();
+        let transcript = SharedTranscript::new(test_transcript());
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        tokio::time::pause();
+
+        let session_id = SessionId::new();
+        let session_info = create_test_session_info(100);
+        let uid = session_info.token.unique_identifier().to_owned();
+        lobby_state
+            .write()
+            .await
+            .participants
+            .insert(session_id.clone(), session_info);
+
+        // not approved
+        let response = try_contribute_offline(
+            session_id.clone(),
+            Extension(contributor_state.clone()),
+            Extension(lobby_state.clone()),
+            Extension(SharedAdminState::default()),
+            Extension(db.clone()),
+            Extension(transcript.clone()),
+            Extension(opts.clone()),
+            Format::Json,
+        )
+        .await;
+        assert!(matches!(
+            response,
+            Err(TryContributeError::NotApprovedForOffline)
+        ));
+        assert!(contributor_state.read().await.active.is_none());
+
+        // approved
+        opts.lobby.offline_contributors = vec![uid.clone()];
+        tokio::time::advance(opts.lobby.lobby_checkin_frequency).await;
+        try_contribute_offline(
+            session_id.clone(),
+            Extension(contributor_state.clone()),
+            Extension(lobby_state.clone()),
+            Extension(SharedAdminState::default()),
+            Extension(db.clone()),
+            Extension(transcript.clone()),
+            Extension(opts.clone()),
+            Format::Json,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            contributor_state.read().await.active.as_ref(),
+            Some((_, _, ContributionSlot::Offline))
+        ));
+
+        // the slot is held past the online deadline
+        let remove = || {
+            remove_participant_on_deadline(
+                contributor_state.clone(),
+                db.clone(),
+                session_id.clone(),
+                ContributionSlot::Offline,
+                opts.lobby.clone(),
+            )
+        };
+        let online_deadline = opts.lobby.compute_deadline + Duration::from_secs(1);
+        assert!(tokio::time::timeout(online_deadline, remove())
+            .await
+            .is_err());
+        assert!(contributor_state.read().await.active.is_some());
+
+        // and released after the offline deadline
+        remove().await.unwrap();
+        assert!(contributor_state.read().await.active.is_none());
+    }
+
+    #[tokio::test]
+    async fn received_upload_outlives_deadline() {
+        let opts = test_options();
+        let contributor_state = SharedContributorState::default();
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        tokio::time::pause();
+
+        let session_id = SessionId::new();
+        let session_info = create_test_session_info(100);
+        {
+            let mut state = contributor_state.write().await;
+            state.active = Some((session_id.clone(), session_info, ContributionSlot::Online));
+            state.upload_received = true;
+        }
+        remove_participant_on_deadline(
+            contributor_state.clone(),
+            db,
+            session_id,
+            ContributionSlot::Online,
+            opts.lobby.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(contributor_state.read().await.active.is_some());
+    }
+
+    #[tokio::test]
+    async fn lobby_queue_test() {
+        let opts = test_options();
+        let contributor_state = SharedContributorState::default();
+        let lobby_state = SharedLobbyState::default();
+        let transcript = SharedTranscript::new(test_transcript());
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        tokio::time::pause();
+
+        let sessions = [SessionId::new(), SessionId::new(), SessionId::new()];
+        for session_id in &sessions {
+            lobby_state
+                .write()
+                .await
+                .participants
+                .insert(session_id.clone(), create_test_session_info(100));
+        }
+        let check_in = |session_id: &SessionId| {
+            try_contribute(
+                session_id.clone(),
+                Extension(contributor_state.clone()),
+                Extension(lobby_state.clone()),
+                Extension(SharedAdminState::default()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+                Format::Json,
+            )
+        };
+
+        // the slot is free, but the first participant is ahead
+        assert!(matches!(
+            check_in(&sessions[2]).await,
+            Err(TryContributeError::WaitingInQueue { position: 3, .. })
+        ));
+        assert!(matches!(
+            check_in(&sessions[1]).await,
+            Err(TryContributeError::WaitingInQueue { position: 2, estimated_wait })
+                if estimated_wait == opts.lobby.compute_deadline
+        ));
+
+        // the first participant misses their check-in and is skipped
+        tokio::time::advance(opts.lobby.lobby_checkin_frequency + Duration::from_secs(5)).await;
+        check_in(&sessions[1]).await.unwrap();
+
+        // the last one waits for the remainder of the current slot
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(matches!(
+            check_in(&sessions[2]).await,
+            Err(TryContributeError::WaitingInQueue { position: 1, estimated_wait })
+                if estimated_wait == Duration::from_secs(180 - 30)
+        ));
+    }
+
+    #[tokio::test]
+    async fn lobby_stream_test() {
+        let opts = test_options();
+        let contributor_state = SharedContributorState::default();
+        let lobby_state = SharedLobbyState::default();
+        let transcript = SharedTranscript::new(test_transcript());
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        tokio::time::pause();
+
+        let first = SessionId::new();
+        let streaming = SessionId::new();
+        let joined = Instant::now();
+        for session_id in [&first, &streaming] {
+            lobby_state
+                .write()
+                .await
+                .participants
+                .insert(session_id.clone(), create_test_session_info(100));
+        }
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let (sender, mut receiver) = mpsc::channel(4);
+        tokio::spawn(stream_lobby_events(
+            sender,
+            streaming.clone(),
+            contributor_state.clone(),
+            lobby_state.clone(),
+            SharedAdminState::default(),
+            db.clone(),
+            transcript.clone(),
+            opts.clone(),
+        ));
+
+        assert!(matches!(
+            receiver.recv().await,
+            Some(LobbyEvent::QueuePosition { position: 2, .. })
+        ));
+        // the open stream counts as a check-in
+        assert!(
+            lobby_state.read().await.participants[&streaming].last_ping_time
+                >= joined + Duration::from_secs(10)
+        );
+
+        // the first participant stops checking in, so the streaming one gets
+        // the slot
+        assert!(matches!(
+            receiver.recv().await,
+            Some(LobbyEvent::YourTurn { deadline, .. }) if deadline == opts.lobby.compute_deadline
+        ));
+        assert!(matches!(
+            contributor_state.read().await.active.as_ref(),
+            Some((session_id, ..)) if session_id == &streaming
+        ));
+        assert!(matches!(
+            receiver.recv().await,
+            Some(LobbyEvent::DeadlineWarning { remaining })
+                if remaining <= opts.lobby.compute_deadline_warning
+        ));
+
+        // the stream ends when the slot is released
+        clear_current_contributor(contributor_state.clone(), &db, &streaming)
+            .await
+            .unwrap();
+        assert!(receiver.recv().await.is_none());
+    }
 }
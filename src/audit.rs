@@ -0,0 +1,222 @@
+//! Offline verification of a transcript file, for the `kzg-verify` binary.
+//!
+//! Runs the same checks as the sequencer on startup, plus the full subgroup,
+//! pairing and witness chain verification of every ceremony.
+
+use crate::{io::CeremonySizes, Engine, DEFAULT_CEREMONY_SIZES};
+use clap::Parser;
+use eyre::{Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{BatchTranscript, G2};
+use serde::Serialize;
+use std::{fs::File, io::BufReader, path::PathBuf, time::Instant};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Transcript file to verify.
+    pub transcript: PathBuf,
+
+    /// Expected number of G1 and G2 powers of each ceremony, in the format of
+    /// the sequencer's `--ceremony-sizes`.
+    #[clap(long, env, value_parser=CeremonySizes::parse_from_cmd, default_value=DEFAULT_CEREMONY_SIZES, multiple(false))]
+    pub ceremony_sizes: CeremonySizes,
+
+    /// Print the results as JSON instead of a summary.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Results of verifying a transcript.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub valid:      bool,
+    /// Whether the transcript is finalized with a randomness beacon.
+    pub finalized:  bool,
+    /// Errors that are not specific to a single ceremony.
+    pub errors:     Vec<String>,
+    pub ceremonies: Vec<CeremonyReport>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CeremonyReport {
+    pub num_g1_powers:     usize,
+    pub num_g2_powers:     usize,
+    pub num_contributions: usize,
+    /// Pubkey of the last contribution.
+    pub final_pubkey:      Option<G2>,
+    pub valid:             bool,
+    pub error:             Option<String>,
+}
+
+/// Verifies a transcript, reporting progress as each step starts.
+pub fn audit(
+    transcript: &BatchTranscript,
+    sizes: &CeremonySizes,
+    mut progress: impl FnMut(&str),
+) -> Report {
+    let mut errors = Vec::new();
+    progress("Checking transcript shape");
+    if let Err(e) = sizes.validate_batch_transcript(transcript) {
+        errors.push(e.to_string());
+    }
+    progress("Checking consistency between ceremonies");
+    if let Err(e) = transcript.sanity_check() {
+        errors.push(e.to_string());
+    }
+    if transcript.beacon.is_some() {
+        progress("Recomputing the randomness beacon");
+        if let Err(e) = transcript.verify_beacon::<Engine>() {
+            errors.push(e.to_string());
+        }
+    }
+
+    let num_ceremonies = transcript.transcripts.len();
+    let ceremonies = transcript
+        .transcripts
+        .iter()
+        .enumerate()
+        .map(|(i, ceremony)| {
+            let num_contributions = ceremony.witness.pubkeys.len().saturating_sub(1);
+            progress(&format!(
+                "Verifying ceremony {}/{num_ceremonies} ({} G1 powers, {} G2 powers, {} \
+                 contributions)",
+                i + 1,
+                ceremony.powers.g1.len(),
+                ceremony.powers.g2.len(),
+                num_contributions
+            ));
+            let error = ceremony
+                .verify_full::<Engine>()
+                .err()
+                .map(|e| e.to_string());
+            CeremonyReport {
+                num_g1_powers: ceremony.powers.g1.len(),
+                num_g2_powers: ceremony.powers.g2.len(),
+                num_contributions,
+                final_pubkey: ceremony.witness.pubkeys.last().copied(),
+                valid: error.is_none(),
+                error,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Report {
+        valid: errors.is_empty() && ceremonies.iter().all(|c| c.valid),
+        finalized: transcript.beacon.is_some(),
+        errors,
+        ceremonies,
+    }
+}
+
+/// Loads and verifies the transcript file, printing progress to stderr and the
+/// results to stdout. Returns whether the transcript is valid.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read or is not a transcript.
+pub fn run(options: &Options) -> EyreResult<bool> {
+    let start = Instant::now();
+    let progress = |message: &str| {
+        eprintln!("[{:>7.1}s] {message}", start.elapsed().as_secs_f64());
+    };
+
+    progress(&format!("Reading {}", options.transcript.display()));
+    let file = File::open(&options.transcript).wrap_err("Can not open transcript file")?;
+    let transcript: BatchTranscript =
+        serde_json::from_reader(BufReader::new(file)).wrap_err("Can not parse transcript file")?;
+
+    let report = audit(&transcript, &options.ceremony_sizes, progress);
+    progress("Done");
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_summary(&report);
+    }
+    Ok(report.valid)
+}
+
+fn print_summary(report: &Report) {
+    for error in &report.errors {
+        println!("Error: {error}");
+    }
+    for (i, ceremony) in report.ceremonies.iter().enumerate() {
+        println!(
+            "Ceremony {i}: {} G1 powers, {} G2 powers, {} contributions: {}",
+            ceremony.num_g1_powers,
+            ceremony.num_g2_powers,
+            ceremony.num_contributions,
+            ceremony.error.as_deref().unwrap_or("valid")
+        );
+        if let Some(pubkey) = ceremony.final_pubkey {
+            println!("  final pubkey: 0x{}", hex::encode(pubkey.0));
+        }
+    }
+    println!(
+        "Transcript is {}{}",
+        if report.valid { "valid" } else { "INVALID" },
+        if report.finalized {
+            ", finalized with beacon"
+        } else {
+            ""
+        }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+    use kzg_ceremony_crypto::Beacon;
+
+    #[test]
+    fn audits_transcript() {
+        let sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+        let mut transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        transcript.verify_add::<Engine>(contribution).unwrap();
+
+        let mut steps = 0;
+        let report = audit(&transcript, &sizes, |_| steps += 1);
+        assert_eq!(steps, 3);
+        assert!(report.valid);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.ceremonies.len(), 1);
+        assert_eq!(report.ceremonies[0].num_contributions, 1);
+        assert_eq!(
+            report.ceremonies[0].final_pubkey,
+            Some(transcript.transcripts[0].witness.pubkeys[1])
+        );
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["ceremonies"][0]["numContributions"], 1);
+
+        let wrong_sizes = CeremonySizes::parse_from_cmd("4,2:8,2").unwrap();
+        let report = audit(&transcript, &wrong_sizes, |_| ());
+        assert!(!report.valid);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.ceremonies[0].valid);
+
+        transcript.transcripts[0].powers.g1.swap(2, 3);
+        let report = audit(&transcript, &sizes, |_| ());
+        assert!(!report.valid);
+        assert!(report.ceremonies[0].error.is_some());
+    }
+
+    #[test]
+    fn audits_beacon() {
+        let sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+        let mut transcript = test_transcript();
+        transcript
+            .finalize::<Engine>(Beacon::new(vec![1], 2))
+            .unwrap();
+        let report = audit(&transcript, &sizes, |_| ());
+        assert!(report.valid);
+        assert!(report.finalized);
+
+        transcript.beacon = Some(Beacon::new(vec![2], 2));
+        let report = audit(&transcript, &sizes, |_| ());
+        assert!(!report.valid);
+        assert_eq!(report.errors.len(), 1);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{
+    lobby::{expire_current_contributor, Expiry, SharedContributorState, SharedLobbyState},
+    storage::PersistentStorage,
+    SharedTranscript,
+};
+use async_session::async_trait;
+use axum::{
+    extract::{FromRequest, RequestParts},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, FixedOffset, Utc};
+use clap::Parser;
+use http::StatusCode;
+use serde::Serialize;
+use serde_json::json;
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// When sign-ins open, in RFC 3339 format. Opens at startup when not set.
+    #[clap(long, env)]
+    pub ceremony_start: Option<DateTime<FixedOffset>>,
+
+    /// When sign-ins close. Participants already in the lobby can still
+    /// contribute until it is drained. Never closes when not set.
+    #[clap(long, env)]
+    pub ceremony_end: Option<DateTime<FixedOffset>>,
+
+    /// When the transcript is finalized even if the lobby is not drained yet.
+    #[clap(long, env)]
+    pub finalize_deadline: Option<DateTime<FixedOffset>>,
+}
+
+/// Lifecycle of the ceremony. Phases only move forward.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CeremonyPhase {
+    /// Before `ceremony_start`, nobody can sign in yet.
+    Pending,
+    /// Participants can sign in and contribute.
+    Open,
+    /// After `ceremony_end`, the participants in the lobby can still
+    /// contribute, but nobody new can sign in.
+    Closing,
+    /// The lobby is drained and the transcript is final.
+    Finalized,
+}
+
+impl fmt::Display for CeremonyPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pending => "pending",
+            Self::Open => "open",
+            Self::Closing => "closing",
+            Self::Finalized => "finalized",
+        })
+    }
+}
+
+impl CeremonyPhase {
+    /// The phase at `now`, moving forward from `self`. `drained` tells whether
+    /// the lobby is empty and nobody holds the contribution slot.
+    #[must_use]
+    pub fn advance(self, now: DateTime<Utc>, options: &Options, drained: bool) -> Self {
+        let reached =
+            |time: Option<DateTime<FixedOffset>>| matches!(time, Some(time) if now >= time);
+        let mut phase = self;
+        if phase == Self::Pending
+            && (options.ceremony_start.is_none() || reached(options.ceremony_start))
+        {
+            phase = Self::Open;
+        }
+        if phase == Self::Open && reached(options.ceremony_end) {
+            phase = Self::Closing;
+        }
+        if phase == Self::Closing && (drained || reached(options.finalize_deadline)) {
+            phase = Self::Finalized;
+        }
+        phase
+    }
+}
+
+pub type SharedCeremonyPhase = Arc<RwLock<CeremonyPhase>>;
+
+/// Whether the lobby is empty and nobody holds the contribution slot.
+pub async fn is_drained(
+    lobby_state: &SharedLobbyState,
+    contributor_state: &SharedContributorState,
+) -> bool {
+    lobby_state.read().await.participants.is_empty()
+        && contributor_state.read().await.active.is_none()
+}
+
+/// Moves the ceremony through its phases as the configured times pass and the
+/// lobby drains. When the transcript is finalized before the lobby is drained,
+/// the remaining participants are removed and the current slot is expired.
+/// The transcript is frozen once a contribution being verified is done.
+pub async fn advance_phase_on_interval(
+    phase: SharedCeremonyPhase,
+    lobby_state: SharedLobbyState,
+    contributor_state: SharedContributorState,
+    transcript: SharedTranscript,
+    storage: PersistentStorage,
+    options: Options,
+    period: Duration,
+) {
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        let drained = is_drained(&lobby_state, &contributor_state).await;
+        let (previous, current) = {
+            let mut phase = phase.write().await;
+            let previous = *phase;
+            *phase = previous.advance(Utc::now(), &options, drained);
+            (previous, *phase)
+        };
+        if previous != current {
+            info!(%previous, %current, "Ceremony phase changed");
+        }
+
+        if current == CeremonyPhase::Finalized {
+            if !drained {
+                lobby_state.write().await.participants.clear();
+                match expire_current_contributor(contributor_state, &storage, |_| true).await {
+                    Ok(Expiry::UploadReceived) => {
+                        info!("Slot kept at finalization, its upload is being verified");
+                    }
+                    Ok(Expiry::Expired(_) | Expiry::NotHeld) => {}
+                    Err(error) => error!(%error, "Failed to expire the slot at finalization"),
+                }
+            }
+            transcript.freeze().await;
+            info!("Ceremony finalized, the transcript is final");
+            return;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PhaseError(pub CeremonyPhase);
+
+impl IntoResponse for PhaseError {
+    fn into_response(self) -> Response {
+        let message = match self.0 {
+            CeremonyPhase::Pending => "ceremony has not started yet",
+            CeremonyPhase::Open => "ceremony is open",
+            CeremonyPhase::Closing => "ceremony is closed for new participants",
+            CeremonyPhase::Finalized => "ceremony is finalized",
+        };
+        let body = Json(json!({ "error": message, "phase": self.0 }));
+        (StatusCode::FORBIDDEN, body).into_response()
+    }
+}
+
+async fn current_phase<B: Send>(req: &mut RequestParts<B>) -> CeremonyPhase {
+    let Extension(phase) = Extension::<SharedCeremonyPhase>::from_request(req)
+        .await
+        .expect("ceremony phase extension is set");
+    let phase = *phase.read().await;
+    phase
+}
+
+/// Extractor that only accepts requests while participants can sign in.
+pub struct AcceptingSignIns;
+
+#[async_trait]
+impl<B> FromRequest<B> for AcceptingSignIns
+where
+    B: Send,
+{
+    type Rejection = PhaseError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        match current_phase(req).await {
+            CeremonyPhase::Open => Ok(Self),
+            phase => Err(PhaseError(phase)),
+        }
+    }
+}
+
+/// Extractor that only accepts requests while participants can contribute.
+pub struct AcceptingContributions;
+
+#[async_trait]
+impl<B> FromRequest<B> for AcceptingContributions
+where
+    B: Send,
+{
+    type Rejection = PhaseError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        match current_phase(req).await {
+            CeremonyPhase::Open | CeremonyPhase::Closing => Ok(Self),
+            phase => Err(PhaseError(phase)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    #[test]
+    fn advance_through_phases() {
+        let options = Options {
+            ceremony_start:    Some(time("2022-11-01T00:00:00Z")),
+            ceremony_end:      Some(time("2022-12-01T00:00:00Z")),
+            finalize_deadline: Some(time("2022-12-08T00:00:00Z")),
+        };
+        let at = |value: &str| time(value).with_timezone(&Utc);
+
+        let phase = CeremonyPhase::Pending;
+        assert_eq!(
+            phase.advance(at("2022-10-31T00:00:00Z"), &options, true),
+            CeremonyPhase::Pending
+        );
+        let phase = phase.advance(at("2022-11-02T00:00:00Z"), &options, true);
+        assert_eq!(phase, CeremonyPhase::Open);
+        // waits for the lobby to drain
+        let phase = phase.advance(at("2022-12-02T00:00:00Z"), &options, false);
+        assert_eq!(phase, CeremonyPhase::Closing);
+        assert_eq!(
+            phase.advance(at("2022-12-03T00:00:00Z"), &options, true),
+            CeremonyPhase::Finalized
+        );
+        // or until the finalization time
+        assert_eq!(
+            phase.advance(at("2022-12-09T00:00:00Z"), &options, false),
+            CeremonyPhase::Finalized
+        );
+        // a restart after the end skips ahead
+        assert_eq!(
+            CeremonyPhase::Pending.advance(at("2022-12-02T00:00:00Z"), &options, true),
+            CeremonyPhase::Finalized
+        );
+
+        let always_open = Options {
+            ceremony_start:    None,
+            ceremony_end:      None,
+            finalize_deadline: None,
+        };
+        assert_eq!(
+            CeremonyPhase::Pending.advance(Utc::now(), &always_open, true),
+            CeremonyPhase::Open
+        );
+    }
+}
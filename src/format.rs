@@ -0,0 +1,190 @@
+//! Negotiation between the JSON and binary encodings of transcripts and
+//! contributions.
+
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{
+        rejection::{BytesRejection, JsonRejection},
+        FromRequest, RequestParts,
+    },
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use kzg_ceremony_crypto::{BinaryError, BinaryFormat};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+use std::convert::Infallible;
+use thiserror::Error;
+
+/// Media type of the binary encoding.
+pub const BINARY_MEDIA_TYPE: &str = "application/octet-stream";
+
+/// Encoding of a request or response body.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    Binary,
+}
+
+impl Format {
+    /// The format requested by the `Accept` header. This is the first of the
+    /// listed media types that is supported, ignoring quality values. JSON is
+    /// the default.
+    pub fn from_accept(headers: &HeaderMap) -> Self {
+        headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(Self::from_media_type)
+            .unwrap_or_default()
+    }
+
+    /// The format given by the `Content-Type` header. JSON is the default.
+    pub fn from_content_type(headers: &HeaderMap) -> Self {
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::from_media_type)
+            .unwrap_or_default()
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next()?.trim();
+        if essence.eq_ignore_ascii_case(BINARY_MEDIA_TYPE) {
+            Some(Self::Binary)
+        } else if essence.eq_ignore_ascii_case("application/json") || essence == "*/*" {
+            Some(Self::Json)
+        } else {
+            None
+        }
+    }
+
+    /// Creates a successful response with `value` in this format.
+    pub fn response<T: Serialize + BinaryFormat>(self, value: &T) -> Response {
+        match self {
+            Self::Json => (StatusCode::OK, Json(value)).into_response(),
+            Self::Binary => (
+                StatusCode::OK,
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(BINARY_MEDIA_TYPE),
+                )],
+                value.to_binary(),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// Extracts the response format from the `Accept` header.
+#[async_trait]
+impl<B> FromRequest<B> for Format
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_accept(req.headers()))
+    }
+}
+
+/// Request body in the format given by the `Content-Type` header.
+#[derive(Debug)]
+pub struct Payload<T>(pub T);
+
+#[derive(Debug, Error)]
+pub enum PayloadError {
+    #[error(transparent)]
+    Json(#[from] JsonRejection),
+    #[error(transparent)]
+    Body(#[from] BytesRejection),
+    #[error("invalid binary encoding: {0}")]
+    Binary(#[from] BinaryError),
+}
+
+impl IntoResponse for PayloadError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Json(rejection) => rejection.into_response(),
+            Self::Body(rejection) => rejection.into_response(),
+            Self::Binary(err) => {
+                let body = Json(json!({ "error": format!("invalid binary encoding: {}", err) }));
+                (StatusCode::BAD_REQUEST, body).into_response()
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<B, T> FromRequest<B> for Payload<T>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    T: DeserializeOwned + BinaryFormat,
+{
+    type Rejection = PayloadError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        match Format::from_content_type(req.headers()) {
+            Format::Json => {
+                let Json(value) = Json::<T>::from_request(req).await?;
+                Ok(Self(value))
+            }
+            Format::Binary => {
+                let bytes = Bytes::from_request(req).await?;
+                Ok(Self(T::from_binary(&bytes)?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn negotiates_format() {
+        assert_eq!(Format::from_accept(&HeaderMap::new()), Format::Json);
+        assert_eq!(
+            Format::from_accept(&headers(header::ACCEPT, "application/octet-stream")),
+            Format::Binary
+        );
+        assert_eq!(
+            Format::from_accept(&headers(
+                header::ACCEPT,
+                "text/html, application/octet-stream;q=0.9, */*;q=0.1"
+            )),
+            Format::Binary
+        );
+        assert_eq!(
+            Format::from_accept(&headers(
+                header::ACCEPT,
+                "application/json, application/octet-stream"
+            )),
+            Format::Json
+        );
+        assert_eq!(
+            Format::from_content_type(&headers(header::CONTENT_TYPE, "application/octet-stream")),
+            Format::Binary
+        );
+        assert_eq!(
+            Format::from_content_type(&headers(
+                header::CONTENT_TYPE,
+                "application/json; charset=utf-8"
+            )),
+            Format::Json
+        );
+    }
+}
@@ -1,11 +1,12 @@
 // TODO: Error handling
 
-use crate::SharedTranscript;
+use crate::{Engine, SharedTranscript};
 use eyre::eyre;
-use kzg_ceremony_crypto::BatchTranscript;
+use kzg_ceremony_crypto::{
+    BatchTranscript, Beacon, Phase1Accumulator, Ptau, TrustedSetup, ZcashChallenge, ZcashResponse,
+};
 use serde::{de::DeserializeOwned, Serialize};
 use std::{path::PathBuf, sync::Arc};
-use tokio::sync::RwLock;
 use tracing::{info, warn};
 
 /// Represents a size constraint on a batch transcript
@@ -47,9 +48,10 @@ impl CeremonySizes {
 
     /// Validates a batch transcript against this shape description
     ///
-    /// # Errors:
+    /// # Errors
+    ///
     /// - when the transcript does not conform to the required shape
-    fn validate_batch_transcript(&self, transcript: &BatchTranscript) -> eyre::Result<()> {
+    pub fn validate_batch_transcript(&self, transcript: &BatchTranscript) -> eyre::Result<()> {
         let defined_ceremonies = transcript.transcripts.len();
         let expected_ceremonies = self.sizes.len();
         if defined_ceremonies != expected_ceremonies {
@@ -83,30 +85,179 @@ impl CeremonySizes {
     }
 }
 
+/// A Phase-1 ceremony file to seed a new transcript with.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Phase1Seed {
+    Ptau(PathBuf),
+    ZcashChallenge(u32, PathBuf),
+    ZcashResponse(u32, PathBuf),
+}
+
+impl Phase1Seed {
+    /// Parses a Phase-1 file from command line format: `ptau:<path>` for a
+    /// snarkjs `.ptau` file, or `zcash-challenge:<power>:<path>` and
+    /// `zcash-response:<power>:<path>` for Zcash files with `2^power` powers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format is unknown or the power is not a number.
+    pub fn parse_from_cmd(cmd: &str) -> eyre::Result<Self> {
+        let (format, rest) = cmd
+            .split_once(':')
+            .ok_or_else(|| eyre!("Invalid Phase-1 file {cmd}"))?;
+        let with_power = |rest: &str| -> eyre::Result<(u32, PathBuf)> {
+            let (power, path) = rest
+                .split_once(':')
+                .ok_or_else(|| eyre!("Missing power in Phase-1 file {cmd}"))?;
+            Ok((power.parse()?, path.into()))
+        };
+        match format {
+            "ptau" => Ok(Self::Ptau(rest.into())),
+            "zcash-challenge" => {
+                with_power(rest).map(|(power, path)| Self::ZcashChallenge(power, path))
+            }
+            "zcash-response" => {
+                with_power(rest).map(|(power, path)| Self::ZcashResponse(power, path))
+            }
+            _ => Err(eyre!("Unknown Phase-1 file format {format}")),
+        }
+    }
+
+    /// Reads the file and validates all points.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is invalid.
+    pub async fn read(&self) -> eyre::Result<Phase1Accumulator> {
+        let seed = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let accumulator = match seed {
+                Self::Ptau(path) => Ptau::read::<Engine>(&std::fs::read(path)?)?.accumulator,
+                Self::ZcashChallenge(power, path) => {
+                    ZcashChallenge::read::<Engine>(&std::fs::read(path)?, power)?.accumulator
+                }
+                Self::ZcashResponse(power, path) => {
+                    ZcashResponse::read::<Engine>(&std::fs::read(path)?, power)?.accumulator
+                }
+            };
+            Ok(accumulator)
+        })
+        .await?
+    }
+}
+
 /// Reads a transcript file from disk, or creates it, if it doesn't exist.
+/// A new transcript starts from the powers of `phase1` if given, and from
+/// the generators otherwise.
 ///
 /// # Errors
 ///
 /// - when the transcript exists, but does not conform to the required shape.
+/// - when the transcript exists, but fails the batch sanity checks.
+/// - when the Phase-1 file is invalid or has too few powers.
 pub async fn read_or_create_transcript(
     path: PathBuf,
     work_path: PathBuf,
     ceremony_sizes: &CeremonySizes,
+    phase1: Option<&Phase1Seed>,
 ) -> eyre::Result<SharedTranscript> {
     if path.exists() {
         info!(?path, "Opening transcript file");
+        if phase1.is_some() {
+            warn!("Transcript file exists, ignoring the Phase-1 file");
+        }
         let transcript = read_json_file::<BatchTranscript>(path).await;
         ceremony_sizes.validate_batch_transcript(&transcript)?;
-        Ok(Arc::new(RwLock::new(transcript)))
+        transcript.sanity_check()?;
+        Ok(SharedTranscript::new(transcript))
     } else {
         warn!(?path, "No transcript found, creating new transcript file");
-        let transcript = BatchTranscript::new(&ceremony_sizes.sizes);
-        let shared_transcript = Arc::new(RwLock::new(transcript));
-        write_json_file(path, work_path, shared_transcript.clone()).await;
-        Ok(shared_transcript)
+        let transcript = if let Some(phase1) = phase1 {
+            info!(?phase1, "Seeding the transcript from a Phase-1 ceremony");
+            let accumulator = phase1.read().await?;
+            let powers = ceremony_sizes
+                .sizes
+                .iter()
+                .map(|&(num_g1, num_g2)| accumulator.powers(num_g1, num_g2))
+                .collect::<Result<_, _>>()?;
+            let transcript = BatchTranscript::from_powers(powers);
+            transcript.sanity_check()?;
+            transcript
+        } else {
+            BatchTranscript::new(&ceremony_sizes.sizes)
+        };
+        let transcript = SharedTranscript::new(transcript);
+        write_json_file(path, work_path, transcript.load()).await;
+        Ok(transcript)
     }
 }
 
+/// Exports one ceremony of a transcript file in the c-kzg `trusted_setup.txt`
+/// format.
+///
+/// # Errors
+///
+/// - when the ceremony does not exist or the transcript fails the sanity
+///   checks.
+/// - when the number of G1 powers is not a power of two.
+/// - when the output file can not be written.
+pub async fn export_trusted_setup(
+    path: PathBuf,
+    ceremony: usize,
+    output: PathBuf,
+) -> eyre::Result<()> {
+    info!(?path, ceremony, "Exporting trusted setup");
+    let transcript = read_json_file::<BatchTranscript>(path).await;
+    let transcript = transcript
+        .transcripts
+        .get(ceremony)
+        .ok_or_else(|| eyre!("Transcript has no ceremony #{ceremony}"))?;
+    transcript.sanity_check()?;
+    let setup = TrustedSetup::from_transcript(transcript)?;
+    tokio::fs::write(&output, setup.to_text()).await?;
+    info!(?output, "Wrote trusted setup");
+    Ok(())
+}
+
+/// Finalizes the transcript file with a contribution derived from the
+/// randomness beacon. The sequencer should be stopped while this runs.
+///
+/// # Errors
+///
+/// - when the transcript fails the sanity checks.
+/// - when the transcript is already finalized.
+pub async fn finalize_transcript(
+    path: PathBuf,
+    work_path: PathBuf,
+    beacon: Beacon,
+) -> eyre::Result<()> {
+    info!(
+        ?path,
+        log2_iterations = beacon.log2_iterations,
+        "Finalizing transcript"
+    );
+    let mut transcript = read_json_file::<BatchTranscript>(path.clone()).await;
+    transcript.sanity_check()?;
+    let transcript = tokio::task::spawn_blocking(move || {
+        transcript.finalize::<Engine>(beacon)?;
+        transcript.verify_beacon::<Engine>()?;
+        Ok::<_, eyre::Report>(transcript)
+    })
+    .await??;
+    write_json_file(path, work_path, Arc::new(transcript)).await;
+    info!("Transcript finalized");
+    Ok(())
+}
+
+/// Parses a hex value with optional `0x` prefix from the command line.
+///
+/// # Errors
+///
+/// Returns an error if the value is not valid hex.
+pub fn parse_hex(value: &str) -> eyre::Result<Vec<u8>> {
+    Ok(hex::decode(value.strip_prefix("0x").unwrap_or(value))?)
+}
+
 /// Asynchronously reads a JSON file from disk.
 pub async fn read_json_file<T: DeserializeOwned + Send + 'static>(path: PathBuf) -> T {
     let handle = tokio::task::spawn_blocking::<_, T>(|| {
@@ -126,7 +277,7 @@ pub async fn read_json_file<T: DeserializeOwned + Send + 'static>(path: PathBuf)
 pub async fn write_json_file<T: Serialize + Send + Sync + 'static>(
     target_path: PathBuf,
     work_path: PathBuf,
-    data: Arc<RwLock<T>>,
+    data: Arc<T>,
 ) {
     let handle = tokio::task::spawn_blocking(move || {
         let f = std::fs::OpenOptions::new()
@@ -134,9 +285,145 @@ pub async fn write_json_file<T: Serialize + Send + Sync + 'static>(
             .create(true)
             .open(&work_path)
             .expect("Can't access work file.");
-        let guard = data.blocking_read();
-        serde_json::to_writer_pretty(&f, &*guard).expect("Cannot write transcript");
+        serde_json::to_writer_pretty(&f, &*data).expect("Cannot write transcript");
         std::fs::rename(&work_path, &target_path).unwrap();
     });
     handle.await.expect("Cannot write transcript");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn exports_trusted_setup() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transcript.json");
+        let output = dir.path().join("trusted_setup.txt");
+        let sizes = CeremonySizes::parse_from_cmd("4,2:8,2").unwrap();
+        let transcript = read_or_create_transcript(
+            path.clone(),
+            dir.path().join("transcript.next"),
+            &sizes,
+            None,
+        )
+        .await
+        .unwrap();
+
+        export_trusted_setup(path.clone(), 1, output.clone())
+            .await
+            .unwrap();
+        let text = std::fs::read_to_string(&output).unwrap();
+        let setup = TrustedSetup::from_text(&text).unwrap();
+        assert_eq!(
+            setup.to_powers().unwrap(),
+            transcript.load().transcripts[1].powers
+        );
+
+        assert!(export_trusted_setup(path, 2, output).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn seeds_transcript_from_ptau() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transcript.json");
+        let work_path = dir.path().join("transcript.next");
+        let ptau_path = dir.path().join("phase1.ptau");
+
+        // A power 3 Phase-1 accumulator has 15 G1 and 8 G2 powers of tau
+        let mut phase1 = BatchTranscript::new(&[(15, 8)]);
+        let mut contribution = phase1.contribution();
+        contribution.add_entropy::<Engine>(&[1; 32].into()).unwrap();
+        phase1.verify_add::<Engine>(contribution).unwrap();
+        let powers = phase1.transcripts[0].powers.clone();
+        let ptau = Ptau {
+            power:          3,
+            ceremony_power: 3,
+            accumulator:    Phase1Accumulator {
+                tau_g1:       powers.g1.clone(),
+                tau_g2:       powers.g2.clone(),
+                alpha_tau_g1: powers.g1[..8].to_vec(),
+                beta_tau_g1:  powers.g1[..8].to_vec(),
+                beta_g2:      powers.g2[1],
+            },
+        };
+        std::fs::write(&ptau_path, ptau.to_bytes().unwrap()).unwrap();
+
+        let seed = Phase1Seed::parse_from_cmd(&format!("ptau:{}", ptau_path.display())).unwrap();
+        let sizes = CeremonySizes::parse_from_cmd("4,2:8,3:15,8").unwrap();
+        let seeded =
+            read_or_create_transcript(path.clone(), work_path.clone(), &sizes, Some(&seed))
+                .await
+                .unwrap()
+                .load();
+        assert!(seeded.imported);
+        for (transcript, (num_g1, num_g2)) in
+            seeded.transcripts.iter().zip([(4, 2), (8, 3), (15, 8)])
+        {
+            assert_eq!(transcript.num_contributions(), 1);
+            assert_eq!(transcript.powers.g1, powers.g1[..num_g1]);
+            assert_eq!(transcript.powers.g2, powers.g2[..num_g2]);
+        }
+        assert!(seeded.verify_full::<Engine>().is_ok());
+
+        // The seeded transcript passes the checks when loaded again
+        let loaded = read_or_create_transcript(path, work_path, &sizes, None)
+            .await
+            .unwrap();
+        assert_eq!(loaded.load(), seeded);
+
+        // A Phase-1 ceremony that is too small is rejected
+        let sizes = CeremonySizes::parse_from_cmd("16,2").unwrap();
+        let path = dir.path().join("too_small.json");
+        assert!(read_or_create_transcript(
+            path,
+            dir.path().join("too_small.next"),
+            &sizes,
+            Some(&seed)
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    fn parses_phase1_seed() {
+        assert_eq!(
+            Phase1Seed::parse_from_cmd("ptau:a:b.ptau").unwrap(),
+            Phase1Seed::Ptau("a:b.ptau".into())
+        );
+        assert_eq!(
+            Phase1Seed::parse_from_cmd("zcash-response:12:response").unwrap(),
+            Phase1Seed::ZcashResponse(12, "response".into())
+        );
+        assert_eq!(
+            Phase1Seed::parse_from_cmd("zcash-challenge:12:challenge").unwrap(),
+            Phase1Seed::ZcashChallenge(12, "challenge".into())
+        );
+        assert!(Phase1Seed::parse_from_cmd("zcash-response:response").is_err());
+        assert!(Phase1Seed::parse_from_cmd("ptau").is_err());
+        assert!(Phase1Seed::parse_from_cmd("other:file").is_err());
+    }
+
+    #[tokio::test]
+    async fn finalizes_transcript() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transcript.json");
+        let work_path = dir.path().join("transcript.next");
+        let sizes = CeremonySizes::parse_from_cmd("4,2:8,2").unwrap();
+        read_or_create_transcript(path.clone(), work_path.clone(), &sizes, None)
+            .await
+            .unwrap();
+
+        let beacon = Beacon::new(parse_hex("0x0123").unwrap(), 2);
+        finalize_transcript(path.clone(), work_path.clone(), beacon.clone())
+            .await
+            .unwrap();
+        let transcript = read_json_file::<BatchTranscript>(path.clone()).await;
+        assert_eq!(transcript.beacon, Some(beacon.clone()));
+        assert_eq!(transcript.transcripts[1].num_contributions(), 1);
+        assert!(transcript.verify_beacon::<Engine>().is_ok());
+
+        assert!(finalize_transcript(path, work_path, beacon).await.is_err());
+    }
+}
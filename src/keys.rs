@@ -23,7 +23,7 @@ pub struct Options {
     pub signing_key: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Signature(String);
 
 #[derive(Debug, Error)]
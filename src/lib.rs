@@ -7,33 +7,45 @@
 #![allow(clippy::module_name_repetitions)]
 
 use crate::{
+    admin::SharedAdminState,
     api::v1::{
         auth::{auth_client_link, eth_callback, github_callback},
-        contribute::contribute,
+        contribute::{contribute, contribute_offline, contribute_status, SharedContributionJobs},
         info::{current_state, status},
-        lobby::try_contribute,
+        lobby::{
+            lobby_stream, remove_participant_on_deadline, try_contribute, try_contribute_offline,
+        },
     },
-    io::{read_or_create_transcript, CeremonySizes},
-    keys::Keys,
-    lobby::{clear_lobby_on_interval, SharedContributorState, SharedLobbyState},
-    oauth::{
-        eth_oauth_client, github_oauth_client, EthAuthOptions, GithubAuthOptions, SharedAuthState,
+    ceremony::{
+        advance_phase_on_interval, is_drained, AcceptingContributions, AcceptingSignIns,
+        CeremonyPhase,
+    },
+    io::{
+        export_trusted_setup, finalize_transcript, parse_hex, read_or_create_transcript,
+        CeremonySizes, Phase1Seed,
     },
+    keys::Keys,
+    lobby::{clear_lobby_on_interval, persist_lobby_on_interval, SharedContributorState},
+    oauth::{eth_oauth_client, github_oauth_client, EthAuthOptions, GithubAuthOptions},
     sessions::{SessionId, SessionInfo},
     storage::storage_client,
     util::parse_url,
 };
 use axum::{
     extract::Extension,
+    middleware::from_extractor,
     response::Html,
     routing::{get, post, IntoMakeService},
     Router, Server,
 };
-use clap::Parser;
+use chrono::Utc;
+use clap::{Parser, Subcommand, ValueEnum};
 use cli_batteries::await_shutdown;
 use eyre::Result as EyreResult;
 use hyper::server::conn::AddrIncoming;
-use kzg_ceremony_crypto::BatchTranscript;
+use kzg_ceremony_crypto::{
+    BatchContribution, BatchTranscript, Beacon, Randomness, Transcript, MAX_BEACON_LOG2_ITERATIONS,
+};
 use std::{
     path::PathBuf,
     sync::{atomic::AtomicUsize, Arc},
@@ -43,20 +55,29 @@ use tower_http::trace::TraceLayer;
 use tracing::info;
 use url::Url;
 
+mod admin;
 mod api;
+pub mod audit;
+mod ceremony;
+pub mod format;
 pub mod io;
 mod keys;
 mod lobby;
+mod metrics;
 mod oauth;
 mod receipt;
 mod sessions;
 mod storage;
 #[cfg(test)]
 pub mod test_util;
+mod transcript;
 mod util;
 
+#[cfg(not(feature = "blst"))]
 pub type Engine = kzg_ceremony_crypto::Arkworks;
-pub type SharedTranscript = Arc<RwLock<BatchTranscript>>;
+#[cfg(feature = "blst")]
+pub type Engine = kzg_ceremony_crypto::Blst;
+pub use crate::transcript::SharedTranscript;
 pub type SharedCeremonyStatus = Arc<AtomicUsize>;
 
 pub const DEFAULT_CEREMONY_SIZES: &str = "4096,65:8192,65:16384,65:32768,65";
@@ -85,15 +106,113 @@ pub struct Options {
     #[clap(long, env, value_parser=CeremonySizes::parse_from_cmd, default_value=DEFAULT_CEREMONY_SIZES, multiple(false))]
     pub ceremony_sizes: CeremonySizes,
 
+    /// Start a new transcript from the powers of a Phase-1 ceremony instead
+    /// of the generators: `ptau:<path>`, `zcash-challenge:<power>:<path>` or
+    /// `zcash-response:<power>:<path>`. Ignored if the transcript file exists.
+    #[clap(long, env, value_parser=Phase1Seed::parse_from_cmd)]
+    pub phase1_seed: Option<Phase1Seed>,
+
+    /// Randomness for the pairing checks. With `fiat-shamir` it is derived
+    /// from the transcript and contribution, so third parties can replay the
+    /// verification.
+    #[clap(long, env, value_enum, default_value = "fresh")]
+    pub verification_randomness: VerificationRandomness,
+
+    /// After each accepted contribution, commit to and open a random
+    /// polynomial with the powers of every ceremony, as an end-to-end check of
+    /// the transcript. Runs in the background and only logs the outcome.
+    #[clap(long, env)]
+    pub kzg_smoke_test: bool,
+
     #[clap(flatten)]
     pub lobby: lobby::Options,
 
     #[clap(flatten)]
     pub storage: storage::Options,
+
+    #[clap(flatten)]
+    pub admin: admin::Options,
+
+    #[clap(flatten)]
+    pub ceremony: ceremony::Options,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Export a ceremony from the transcript file in the c-kzg
+    /// `trusted_setup.txt` format and exit.
+    ExportTrustedSetup {
+        /// Index of the ceremony in the transcript.
+        #[clap(long, default_value = "0")]
+        ceremony: usize,
+
+        /// File to write the trusted setup to.
+        #[clap(long, default_value = "./trusted_setup.txt")]
+        output: PathBuf,
+    },
+
+    /// Finalize the transcript file with a contribution derived from a public
+    /// randomness beacon, such as a future block hash, and exit. Run this
+    /// with the sequencer stopped once the ceremony is finalized.
+    Finalize {
+        /// Beacon value in hex.
+        // Fully qualified, so clap parses one value instead of a list of bytes.
+        #[clap(long, value_parser = parse_hex)]
+        beacon_value: ::std::vec::Vec<u8>,
+
+        /// The beacon value is iterated through `2^n` rounds of SHA-256.
+        #[clap(
+            long,
+            default_value = "42",
+            value_parser = clap::value_parser!(u32).range(..=i64::from(MAX_BEACON_LOG2_ITERATIONS))
+        )]
+        beacon_log2_iterations: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum VerificationRandomness {
+    Fresh,
+    FiatShamir,
+}
+
+impl VerificationRandomness {
+    #[must_use]
+    pub fn randomness(
+        self,
+        transcript: &BatchTranscript,
+        contribution: &BatchContribution,
+    ) -> Randomness {
+        match self {
+            Self::Fresh => Randomness::Fresh,
+            Self::FiatShamir => Randomness::fiat_shamir_batch(transcript, contribution),
+        }
+    }
 }
 
 #[allow(clippy::missing_errors_doc)]
 pub async fn async_main(options: Options) -> EyreResult<()> {
+    match options.command {
+        Some(Command::ExportTrustedSetup { ceremony, output }) => {
+            return export_trusted_setup(options.transcript_file, ceremony, output).await;
+        }
+        Some(Command::Finalize {
+            beacon_value,
+            beacon_log2_iterations,
+        }) => {
+            let beacon = Beacon::new(beacon_value, beacon_log2_iterations);
+            return finalize_transcript(
+                options.transcript_file,
+                options.transcript_in_progress_file,
+                beacon,
+            )
+            .await;
+        }
+        None => {}
+    }
     let addr = options.server.clone();
     let server = start_server(options).await?;
     info!("Listening on http://{}{}", server.local_addr(), addr.path());
@@ -101,11 +220,12 @@ pub async fn async_main(options: Options) -> EyreResult<()> {
     Ok(())
 }
 
-#[allow(clippy::missing_errors_doc)]
+#[allow(clippy::missing_errors_doc, clippy::too_many_lines)]
 pub async fn start_server(
     options: Options,
 ) -> EyreResult<Server<AddrIncoming, IntoMakeService<Router>>> {
     info!(size=?options.ceremony_sizes, "Starting sequencer for KZG ceremony.");
+    metrics::register();
 
     let keys = Arc::new(Keys::new(&options.keys)?);
 
@@ -113,15 +233,37 @@ pub async fn start_server(
         options.transcript_file.clone(),
         options.transcript_in_progress_file.clone(),
         &options.ceremony_sizes,
+        options.phase1_seed.as_ref(),
     )
     .await?;
 
-    let active_contributor_state = SharedContributorState::default();
+    let ceremony_status = Arc::new(AtomicUsize::new(
+        transcript
+            .load()
+            .transcripts
+            .first()
+            .map_or(0, Transcript::num_contributions),
+    ));
 
-    // TODO: figure it out from the transcript
-    let ceremony_status = Arc::new(AtomicUsize::new(0));
-    let lobby_state = SharedLobbyState::default();
-    let auth_state = SharedAuthState::default();
+    // Restore the state from before a restart. Ping times and deadlines are
+    // stored as wall-clock times, so they count the time the sequencer was down.
+    let storage = storage_client(&options.storage).await?;
+    let lobby_state = Arc::new(RwLock::new(storage.load_lobby().await?));
+    let auth_state = Arc::new(RwLock::new(storage.load_auth_state().await?));
+    let active_contributor_state = SharedContributorState::default();
+    let admin_state = SharedAdminState::default();
+    if let Some((session_id, session_info, slot)) = storage.load_active_contributor().await? {
+        info!(%session_id, ?slot, "Restoring contribution in progress");
+        active_contributor_state.write().await.active =
+            Some((session_id.clone(), session_info, slot));
+        tokio::spawn(remove_participant_on_deadline(
+            active_contributor_state.clone(),
+            storage.clone(),
+            session_id,
+            slot,
+            options.lobby.clone(),
+        ));
+    }
 
     // Spawn automatic queue flusher -- flushes those in the lobby whom have not
     // pinged in a considerable amount of time
@@ -129,26 +271,80 @@ pub async fn start_server(
         lobby_state.clone(),
         options.lobby.clone(),
     ));
+    tokio::spawn(persist_lobby_on_interval(
+        lobby_state.clone(),
+        auth_state.clone(),
+        storage.clone(),
+        options.lobby.clone(),
+    ));
 
+    let phase = CeremonyPhase::Pending.advance(
+        Utc::now(),
+        &options.ceremony,
+        is_drained(&lobby_state, &active_contributor_state).await,
+    );
+    info!(%phase, "Ceremony phase");
+    let phase = Arc::new(RwLock::new(phase));
+    tokio::spawn(advance_phase_on_interval(
+        phase.clone(),
+        lobby_state.clone(),
+        active_contributor_state.clone(),
+        transcript.clone(),
+        storage.clone(),
+        options.ceremony.clone(),
+        options.lobby.lobby_flush_interval,
+    ));
+
+    let sign_ins = || from_extractor::<AcceptingSignIns>();
+    let contributions = || from_extractor::<AcceptingContributions>();
     let app = Router::new()
         .layer(TraceLayer::new_for_http())
         .route("/hello_world", get(hello_world))
-        .route("/auth/request_link", get(auth_client_link))
-        .route("/auth/callback/github", get(github_callback))
-        .route("/auth/callback/eth", get(eth_callback))
-        .route("/lobby/try_contribute", post(try_contribute))
-        .route("/contribute", post(contribute))
+        .route(
+            "/auth/request_link",
+            get(auth_client_link).route_layer(sign_ins()),
+        )
+        .route(
+            "/auth/callback/github",
+            get(github_callback).route_layer(sign_ins()),
+        )
+        .route(
+            "/auth/callback/eth",
+            get(eth_callback).route_layer(sign_ins()),
+        )
+        .route(
+            "/lobby/try_contribute",
+            post(try_contribute).route_layer(contributions()),
+        )
+        .route(
+            "/lobby/stream",
+            get(lobby_stream).route_layer(contributions()),
+        )
+        .route(
+            "/lobby/try_contribute_offline",
+            post(try_contribute_offline).route_layer(contributions()),
+        )
+        .route("/contribute", post(contribute).route_layer(contributions()))
+        .route(
+            "/contribute/offline",
+            post(contribute_offline).route_layer(contributions()),
+        )
+        .route("/contribute/status/:job_id", get(contribute_status))
         .route("/info/status", get(status))
         .route("/info/current_state", get(current_state))
+        .nest("/admin", api::v1::admin::router())
         .layer(Extension(active_contributor_state))
+        .layer(Extension(SharedContributionJobs::default()))
         .layer(Extension(lobby_state))
         .layer(Extension(auth_state))
+        .layer(Extension(admin_state))
+        .layer(Extension(phase))
         .layer(Extension(ceremony_status))
         .layer(Extension(keys))
         .layer(Extension(eth_oauth_client(&options.ethereum)))
         .layer(Extension(github_oauth_client(&options.github)))
         .layer(Extension(reqwest::Client::new()))
-        .layer(Extension(storage_client(&options.storage).await?))
+        .layer(Extension(storage))
         .layer(Extension(transcript))
         .layer(Extension(options.clone()));
 
@@ -175,7 +371,9 @@ mod tests {
 
     pub fn valid_contribution(transcript: &BatchTranscript, no: u8) -> BatchContribution {
         let mut contribution = transcript.contribution();
-        contribution.add_entropy::<Engine>([no; 32]).unwrap();
+        contribution
+            .add_entropy::<Engine>(&[no; 32].into())
+            .unwrap();
         contribution
     }
 
@@ -184,4 +382,33 @@ mod tests {
         contribution.contributions[0].pubkey = G2::zero();
         contribution
     }
+
+    #[test]
+    fn beacon_iterations_are_bounded() {
+        let parse = |log2_iterations: &str| {
+            Options::try_parse_from([
+                "kzg-ceremony-sequencer",
+                "--gh-client-id=id",
+                "--gh-client-secret=secret",
+                "--eth-client-id=id",
+                "--eth-client-secret=secret",
+                "finalize",
+                "--beacon-value",
+                "0x00",
+                "--beacon-log2-iterations",
+                log2_iterations,
+            ])
+        };
+        match parse("48").unwrap().command {
+            Some(Command::Finalize {
+                beacon_value,
+                beacon_log2_iterations,
+            }) => {
+                assert_eq!(beacon_value, vec![0]);
+                assert_eq!(beacon_log2_iterations, 48);
+            }
+            _ => panic!("expected the finalize command"),
+        }
+        assert!(parse("49").is_err());
+    }
 }
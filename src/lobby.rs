@@ -1,7 +1,17 @@
-use crate::sessions::{SessionId, SessionInfo};
+use crate::{
+    metrics::set_lobby_size,
+    oauth::{AuthState, SharedAuthState},
+    sessions::{SessionId, SessionInfo},
+    storage::{PersistentStorage, StorageError},
+};
 use clap::Parser;
-use std::{collections::BTreeMap, num::ParseIntError, str::FromStr, sync::Arc, time::Duration};
+use indexmap::IndexMap;
+use std::{collections::VecDeque, num::ParseIntError, str::FromStr, sync::Arc, time::Duration};
 use tokio::{sync::RwLock, time::Instant};
+use tracing::error;
+
+/// Number of recent slot durations used to estimate waiting times.
+const RECENT_DURATIONS: usize = 10;
 
 fn duration_from_str(value: &str) -> Result<Duration, ParseIntError> {
     Ok(Duration::from_secs(u64::from_str(value)?))
@@ -23,17 +33,155 @@ pub struct Options {
 
     #[clap(long, env, default_value = "1000")]
     pub max_lobby_size: usize,
+
+    /// Interval between check-ins and updates for participants streaming
+    /// `/lobby/stream`. Must be shorter than `lobby_checkin_frequency`.
+    #[clap(long, env, value_parser=duration_from_str, default_value="5")]
+    pub lobby_stream_interval: Duration,
+
+    /// How long before the compute deadline streaming contributors are warned.
+    #[clap(long, env, value_parser=duration_from_str, default_value="30")]
+    pub compute_deadline_warning: Duration,
+
+    /// Deadline for contributors in an offline slot, who compute on an
+    /// air-gapped machine.
+    #[clap(long, env, value_parser=duration_from_str, default_value="86400")]
+    pub offline_compute_deadline: Duration,
+
+    /// Comma separated unique identifiers of the participants approved for an
+    /// offline slot.
+    #[clap(long, env, use_value_delimiter = true)]
+    pub offline_contributors: Vec<String>,
+}
+
+impl Options {
+    /// How long a contributor may hold a slot of this type.
+    #[must_use]
+    pub const fn deadline(&self, slot: ContributionSlot) -> Duration {
+        match slot {
+            ContributionSlot::Online => self.compute_deadline,
+            ContributionSlot::Offline => self.offline_compute_deadline,
+        }
+    }
 }
 
+/// How the current contributor computes their contribution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContributionSlot {
+    /// Computed by the client and submitted to `/contribute` within
+    /// `compute_deadline`.
+    Online,
+    /// Computed on an air-gapped machine and submitted to
+    /// `/contribute/offline` within `offline_compute_deadline`.
+    Offline,
+}
+
+impl ContributionSlot {
+    /// Name of the slot type in storage.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Online => "online",
+            Self::Offline => "offline",
+        }
+    }
+
+    /// Parses the name of a slot type in storage.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "online" => Some(Self::Online),
+            "offline" => Some(Self::Offline),
+            _ => None,
+        }
+    }
+}
+
+/// Participants waiting for a contribution slot, in the order they joined.
 #[derive(Default)]
 pub struct LobbyState {
-    pub participants: BTreeMap<SessionId, SessionInfo>,
+    pub participants: IndexMap<SessionId, SessionInfo>,
 }
 
-pub type ActiveContributor = Option<(SessionId, SessionInfo)>;
+impl LobbyState {
+    /// The number of participants ahead of `session_id` in the queue, not
+    /// counting those whose last check-in is more than `max_diff` before
+    /// `now`. Returns `None` if the participant is not in the lobby.
+    #[must_use]
+    pub fn participants_ahead(
+        &self,
+        session_id: &SessionId,
+        now: Instant,
+        max_diff: Duration,
+    ) -> Option<usize> {
+        let mut ahead = 0;
+        for (id, session_info) in &self.participants {
+            if id == session_id {
+                return Some(ahead);
+            }
+            if now - session_info.last_ping_time <= max_diff {
+                ahead += 1;
+            }
+        }
+        None
+    }
+}
+
+/// The participant holding the contribution slot. The slot starts at the
+/// check-in that took it, which is the `last_ping_time` of the session.
+pub type ActiveContributor = (SessionId, SessionInfo, ContributionSlot);
+
+#[derive(Default)]
+pub struct ContributorState {
+    pub active:          Option<ActiveContributor>,
+    /// Set once the active contributor's upload is received. The deadline of
+    /// the slot no longer applies while it is verified.
+    pub upload_received: bool,
+    /// How long recent online slots were held, oldest first.
+    recent_durations:    VecDeque<Duration>,
+}
+
+impl ContributorState {
+    /// Average duration of recent online slots, or `None` if no slot has been
+    /// released yet.
+    #[must_use]
+    pub fn average_duration(&self) -> Option<Duration> {
+        let count = u32::try_from(self.recent_durations.len()).ok()?;
+        if count == 0 {
+            return None;
+        }
+        Some(self.recent_durations.iter().sum::<Duration>() / count)
+    }
+
+    /// Estimates how long a participant with `ahead` participants in front of
+    /// them has to wait for the slot: the expected remainder of the current
+    /// slot, plus the average slot duration for each participant ahead.
+    #[must_use]
+    pub fn estimated_wait(&self, ahead: usize, options: &Options) -> Duration {
+        let average = self.average_duration().unwrap_or(options.compute_deadline);
+        let current = self
+            .active
+            .as_ref()
+            .map_or(Duration::ZERO, |(_, session_info, slot)| {
+                let expected = match slot {
+                    ContributionSlot::Online => average,
+                    ContributionSlot::Offline => options.offline_compute_deadline,
+                };
+                expected.saturating_sub(session_info.last_ping_time.elapsed())
+            });
+        current + average.saturating_mul(u32::try_from(ahead).unwrap_or(u32::MAX))
+    }
+
+    fn record_duration(&mut self, duration: Duration) {
+        if self.recent_durations.len() == RECENT_DURATIONS {
+            self.recent_durations.pop_front();
+        }
+        self.recent_durations.push_back(duration);
+    }
+}
 
 pub type SharedLobbyState = Arc<RwLock<LobbyState>>;
-pub type SharedContributorState = Arc<RwLock<ActiveContributor>>;
+pub type SharedContributorState = Arc<RwLock<ContributorState>>;
 
 pub async fn clear_lobby_on_interval(state: SharedLobbyState, options: Options) {
     let max_diff = options.lobby_checkin_frequency + options.lobby_checkin_tolerance;
@@ -58,31 +206,105 @@ pub async fn clear_lobby_on_interval(state: SharedLobbyState, options: Options)
 async fn clear_lobby(state: SharedLobbyState, predicate: impl Fn(&SessionInfo) -> bool + Send) {
     let mut lobby_state = state.write().await;
 
-    // Iterate top `MAX_LOBBY_SIZE` participants and check if they have
-    let participants = lobby_state.participants.keys().cloned();
-    let mut sessions_to_kick = Vec::new();
-
-    for participant in participants {
-        // Check if they are over their ping deadline
-        lobby_state.participants.get(&participant).map_or_else(
-            ||
-                // This should not be possible
-                tracing::debug!("session id in queue but not a valid session"),
-            |session_info| {
-                if predicate(session_info) {
-                    sessions_to_kick.push(participant);
-                }
-            },
-        );
+    // Kick everyone over their ping deadline, keeping the order of the rest
+    lobby_state
+        .participants
+        .retain(|_, session_info| !predicate(session_info));
+    set_lobby_size(lobby_state.participants.len());
+}
+
+/// Periodically stores the lobby and the sign-in state, so participants keep
+/// their place and session across restarts.
+pub async fn persist_lobby_on_interval(
+    lobby_state: SharedLobbyState,
+    auth_state: SharedAuthState,
+    storage: PersistentStorage,
+    options: Options,
+) {
+    let mut interval = tokio::time::interval(options.lobby_flush_interval);
+
+    loop {
+        interval.tick().await;
+
+        // Copy the state so the locks are not held while writing
+        let lobby = LobbyState {
+            participants: lobby_state.read().await.participants.clone(),
+        };
+        if let Err(error) = storage.save_lobby(&lobby).await {
+            error!(%error, "Failed to store the lobby");
+        }
+
+        let auth = {
+            let auth = auth_state.read().await;
+            AuthState {
+                csrf_tokens:       auth.csrf_tokens.clone(),
+                unique_id_session: auth.unique_id_session.clone(),
+            }
+        };
+        if let Err(error) = storage.save_auth_state(&auth).await {
+            error!(%error, "Failed to store the sign-in state");
+        }
     }
-    for session_id in sessions_to_kick {
-        lobby_state.participants.remove(&session_id);
+}
+
+/// Frees the contribution slot if `session_id` holds it. Checking the holder
+/// under the same lock keeps a late release from freeing the slot of the next
+/// participant.
+pub async fn clear_current_contributor(
+    contributor: SharedContributorState,
+    storage: &PersistentStorage,
+    session_id: &SessionId,
+) -> Result<(), StorageError> {
+    let mut state = contributor.write().await;
+    if !matches!(&state.active, Some((id, ..)) if id == session_id) {
+        return Ok(());
     }
+    release_slot(&mut state);
+    storage.save_active_contributor(None).await
 }
 
-pub async fn clear_current_contributor(contributor: SharedContributorState) {
-    let mut active_contributor = contributor.write().await;
-    *active_contributor = None;
+/// Outcome of [`expire_current_contributor`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expiry {
+    /// The slot was taken from the participant with this unique identifier.
+    Expired(String),
+    /// The upload of the participant is being verified, so they keep the
+    /// slot until it is done.
+    UploadReceived,
+    /// Nobody holds the slot, or not the participant that was asked for.
+    NotHeld,
+}
+
+/// Takes the contribution slot away from the participant holding it, if
+/// `holds` accepts them and their upload is not being verified yet. Their
+/// contribution is recorded as expired.
+pub async fn expire_current_contributor(
+    contributor: SharedContributorState,
+    storage: &PersistentStorage,
+    holds: impl FnOnce(&ActiveContributor) -> bool + Send,
+) -> Result<Expiry, StorageError> {
+    let mut state = contributor.write().await;
+    let uid = match &state.active {
+        Some(active) if holds(active) => active.1.token.unique_identifier().to_owned(),
+        _ => return Ok(Expiry::NotHeld),
+    };
+    if state.upload_received {
+        return Ok(Expiry::UploadReceived);
+    }
+    storage.expire_contribution(&uid).await?;
+    release_slot(&mut state);
+    storage.save_active_contributor(None).await?;
+    Ok(Expiry::Expired(uid))
+}
+
+fn release_slot(state: &mut ContributorState) {
+    state.upload_received = false;
+    if let Some((_, session_info, slot)) = state.active.take() {
+        // Offline slots are not representative of how fast the queue moves
+        if slot == ContributionSlot::Online {
+            state.record_duration(session_info.last_ping_time.elapsed());
+        }
+    }
 }
 
 /// # Panics
@@ -91,15 +313,19 @@ pub async fn clear_current_contributor(contributor: SharedContributorState) {
 pub async fn set_current_contributor(
     contributor: SharedContributorState,
     lobby_state: SharedLobbyState,
+    storage: &PersistentStorage,
     session_id: SessionId,
-) {
+    slot: ContributionSlot,
+) -> Result<(), StorageError> {
     let session_info = {
         let mut lobby = lobby_state.write().await;
-        lobby.participants.remove(&session_id).unwrap()
+        lobby.participants.shift_remove(&session_id).unwrap()
     };
 
-    let mut active_contributor = contributor.write().await;
-    *active_contributor = Some((session_id, session_info));
+    let mut state = contributor.write().await;
+    state.active = Some((session_id, session_info, slot));
+    state.upload_received = false;
+    storage.save_active_contributor(state.active.as_ref()).await
 }
 
 #[tokio::test]
@@ -148,3 +374,32 @@ async fn flush_on_predicate() {
         assert_eq!(info.token.exp % 2, 1);
     }
 }
+
+#[test]
+fn estimates_wait_from_recent_durations() {
+    use crate::{sessions::SessionId, test_util::create_test_session_info};
+    use clap::Parser;
+
+    let options = Options::parse_from(Vec::<&str>::new());
+    let mut state = ContributorState::default();
+    assert_eq!(state.average_duration(), None);
+    assert_eq!(
+        state.estimated_wait(2, &options),
+        2 * options.compute_deadline
+    );
+
+    for secs in 0..=RECENT_DURATIONS as u64 {
+        state.record_duration(Duration::from_secs(secs * 10));
+    }
+    // The oldest duration is dropped
+    assert_eq!(state.average_duration(), Some(Duration::from_secs(55)));
+
+    state.active = Some((
+        SessionId::new(),
+        create_test_session_info(100),
+        ContributionSlot::Offline,
+    ));
+    let wait = state.estimated_wait(1, &options);
+    assert!(wait > options.offline_compute_deadline);
+    assert!(wait <= options.offline_compute_deadline + Duration::from_secs(55));
+}
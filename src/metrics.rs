@@ -0,0 +1,134 @@
+//! Prometheus metrics of the ceremony, served on the `--prometheus` endpoint
+//! of cli-batteries.
+
+use kzg_ceremony_crypto::CeremoniesError;
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge,
+};
+use std::fmt::Debug;
+
+pub static LOBBY_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("lobby_size", "Number of participants waiting in the lobby.").unwrap()
+});
+
+pub static AUTH_ATTEMPTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "auth_attempts_total",
+        "Sign-in callbacks by provider and outcome.",
+        &["provider", "outcome"]
+    )
+    .unwrap()
+});
+
+pub static TRY_CONTRIBUTE: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "try_contribute_total",
+        "Calls to try_contribute by slot and outcome.",
+        &["slot", "outcome"]
+    )
+    .unwrap()
+});
+
+pub static VERIFICATION_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "contribution_verification_seconds",
+        "Time to verify a contribution and publish the extended transcript, by outcome.",
+        &["outcome"],
+        exponential_buckets(0.01, 2.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
+pub static CONTRIBUTIONS_ACCEPTED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!("contributions_accepted_total", "Accepted contributions.").unwrap()
+});
+
+pub static CONTRIBUTIONS_REJECTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "contributions_rejected_total",
+        "Rejected contributions by the check they failed.",
+        &["error"]
+    )
+    .unwrap()
+});
+
+pub static DEADLINE_EXPIRATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "deadline_expirations_total",
+        "Slots that expired before a contribution was received.",
+        &["slot"]
+    )
+    .unwrap()
+});
+
+pub static TRANSCRIPT_WRITE_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "transcript_write_seconds",
+        "Time to write the transcript file.",
+        exponential_buckets(0.01, 2.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
+/// Registers all metrics, so they are exported before their first update.
+pub fn register() {
+    Lazy::force(&LOBBY_SIZE);
+    Lazy::force(&AUTH_ATTEMPTS);
+    Lazy::force(&TRY_CONTRIBUTE);
+    Lazy::force(&VERIFICATION_SECONDS);
+    Lazy::force(&CONTRIBUTIONS_ACCEPTED);
+    Lazy::force(&CONTRIBUTIONS_REJECTED);
+    Lazy::force(&DEADLINE_EXPIRATIONS);
+    Lazy::force(&TRANSCRIPT_WRITE_SECONDS);
+}
+
+pub fn set_lobby_size(size: usize) {
+    LOBBY_SIZE.set(i64::try_from(size).unwrap_or(i64::MAX));
+}
+
+/// The name of an enum variant, without its fields, as a label value.
+pub fn variant_name(value: &impl Debug) -> String {
+    let debug = format!("{value:?}");
+    debug
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// The label of a rejected contribution. Errors in a sub-ceremony are labeled
+/// with the [`CeremonyError`](kzg_ceremony_crypto::CeremonyError) variant.
+pub fn rejection_label(error: &CeremoniesError) -> String {
+    match error {
+        CeremoniesError::InvalidCeremony(_, error) => variant_name(error),
+        error => variant_name(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kzg_ceremony_crypto::CeremonyError;
+
+    #[test]
+    fn labels() {
+        assert_eq!(
+            variant_name(&CeremonyError::G1PairingFailed),
+            "G1PairingFailed"
+        );
+        assert_eq!(
+            rejection_label(&CeremoniesError::InvalidCeremony(
+                2,
+                CeremonyError::DuplicateG1(1, 3)
+            )),
+            "DuplicateG1"
+        );
+        assert_eq!(
+            rejection_label(&CeremoniesError::DuplicatePubKey(0, 1)),
+            "DuplicatePubKey"
+        );
+    }
+}
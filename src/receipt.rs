@@ -6,7 +6,7 @@ use serde::Serialize;
 
 // Receipt for contributor that sequencer has
 // included their contribution
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Receipt<T> {
     pub(crate) id_token: IdToken,
     pub witness:         T,
@@ -1,3 +1,8 @@
+use crate::{
+    lobby::{ActiveContributor, ContributionSlot, LobbyState},
+    oauth::AuthState,
+    sessions::{IdToken, SessionId, SessionInfo},
+};
 use axum::{
     response::{IntoResponse, Response},
     Json,
@@ -10,11 +15,11 @@ use serde_json::json;
 use sqlx::{
     any::{AnyConnectOptions, AnyKind},
     migrate::{Migrate, MigrateDatabase, Migrator},
-    Any, AnyConnection, ConnectOptions, Executor, Row,
+    Any, AnyConnection, ConnectOptions, Connection, Executor, Row,
 };
-use std::{str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc, time::Duration};
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::{sync::Mutex, time::Instant};
 use tracing::{error, info, warn};
 
 // Statically link in migration files
@@ -47,6 +52,8 @@ pub struct PersistentStorage(Arc<Mutex<AnyConnection>>);
 pub enum StorageError {
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::error::Error),
+    #[error("Invalid stored state: {0}")]
+    InvalidState(String),
 }
 
 pub async fn storage_client(options: &Options) -> eyre::Result<PersistentStorage> {
@@ -139,6 +146,7 @@ impl IntoResponse for StorageError {
     fn into_response(self) -> Response {
         let message = match self {
             Self::DatabaseError(error) => error.to_string(),
+            Self::InvalidState(message) => message,
         };
         let body = Json(json!({ "error": message }));
         (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
@@ -158,12 +166,21 @@ impl PersistentStorage {
         Ok(result)
     }
 
-    pub async fn insert_contributor(&self, uid: &str) -> Result<(), StorageError> {
-        let sql = "INSERT INTO contributors (uid, started_at) VALUES (?1, ?2)";
+    pub async fn insert_contributor(
+        &self,
+        uid: &str,
+        slot: ContributionSlot,
+    ) -> Result<(), StorageError> {
+        let sql = "INSERT INTO contributors (uid, started_at, slot) VALUES (?1, ?2, ?3)";
         self.0
             .lock()
             .await
-            .execute(sqlx::query(sql).bind(uid).bind(Utc::now()))
+            .execute(
+                sqlx::query(sql)
+                    .bind(uid)
+                    .bind(Utc::now())
+                    .bind(slot.as_str()),
+            )
             .await?;
         Ok(())
     }
@@ -187,4 +204,300 @@ impl PersistentStorage {
             .await?;
         Ok(())
     }
+
+    pub async fn is_banned(&self, uid: &str) -> Result<bool, StorageError> {
+        let sql = "SELECT EXISTS(SELECT 1 FROM banned_users WHERE uid = ?1)";
+        let result = self
+            .0
+            .lock()
+            .await
+            .fetch_one(sqlx::query(sql).bind(uid))
+            .await
+            .map(|row| row.get(0))?;
+        Ok(result)
+    }
+
+    pub async fn ban(&self, uid: &str) -> Result<(), StorageError> {
+        let sql =
+            "INSERT INTO banned_users (uid, banned_at) VALUES (?1, ?2) ON CONFLICT DO NOTHING";
+        self.0
+            .lock()
+            .await
+            .execute(sqlx::query(sql).bind(uid).bind(Utc::now()))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unban(&self, uid: &str) -> Result<(), StorageError> {
+        let sql = "DELETE FROM banned_users WHERE uid = ?1";
+        self.0
+            .lock()
+            .await
+            .execute(sqlx::query(sql).bind(uid))
+            .await?;
+        Ok(())
+    }
+
+    /// Records an action taken through the admin API in the audit log.
+    pub async fn log_admin_action(
+        &self,
+        action: &str,
+        details: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let sql = "INSERT INTO admin_actions (performed_at, action, details) VALUES (?1, ?2, ?3)";
+        self.0
+            .lock()
+            .await
+            .execute(sqlx::query(sql).bind(Utc::now()).bind(action).bind(details))
+            .await?;
+        Ok(())
+    }
+
+    /// The audit log of admin actions, oldest first.
+    pub async fn admin_actions(&self) -> Result<Vec<(String, Option<String>)>, StorageError> {
+        let sql = "SELECT action, details FROM admin_actions ORDER BY performed_at";
+        let rows = self.0.lock().await.fetch_all(sql).await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    /// Replaces the stored lobby with `lobby`, keeping the queue order.
+    pub async fn save_lobby(&self, lobby: &LobbyState) -> Result<(), StorageError> {
+        let sql = "INSERT INTO lobby (session_id, position, token, last_ping_at, \
+                   is_first_ping_attempt) VALUES (?1, ?2, ?3, ?4, ?5)";
+        let mut connection = self.0.lock().await;
+        let mut transaction = connection.begin().await?;
+        transaction.execute("DELETE FROM lobby").await?;
+        for (position, (session_id, session_info)) in lobby.participants.iter().enumerate() {
+            transaction
+                .execute(
+                    sqlx::query(sql)
+                        .bind(session_id.to_string())
+                        .bind(i64::try_from(position).unwrap_or(i64::MAX))
+                        .bind(encode_token(session_info)?)
+                        .bind(to_unix_millis(session_info.last_ping_time))
+                        .bind(session_info.is_first_ping_attempt),
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn load_lobby(&self) -> Result<LobbyState, StorageError> {
+        let sql = "SELECT session_id, token, last_ping_at, is_first_ping_attempt FROM lobby ORDER \
+                   BY position";
+        let rows = self.0.lock().await.fetch_all(sql).await?;
+        let mut lobby = LobbyState::default();
+        for row in rows {
+            let session_info = SessionInfo {
+                token:                 decode_token(row.get(1))?,
+                last_ping_time:        from_unix_millis(row.get(2)),
+                is_first_ping_attempt: row.get(3),
+            };
+            lobby
+                .participants
+                .insert(SessionId::from(row.get::<String, _>(0)), session_info);
+        }
+        Ok(lobby)
+    }
+
+    /// Replaces the stored CSRF tokens and sessions with those in `auth`.
+    pub async fn save_auth_state(&self, auth: &AuthState) -> Result<(), StorageError> {
+        let mut connection = self.0.lock().await;
+        let mut transaction = connection.begin().await?;
+        transaction.execute("DELETE FROM csrf_tokens").await?;
+        for token in &auth.csrf_tokens {
+            let sql = "INSERT INTO csrf_tokens (token) VALUES (?1)";
+            transaction.execute(sqlx::query(sql).bind(token)).await?;
+        }
+        transaction.execute("DELETE FROM sessions").await?;
+        for (uid, session_id) in &auth.unique_id_session {
+            let sql = "INSERT INTO sessions (uid, session_id) VALUES (?1, ?2)";
+            transaction
+                .execute(sqlx::query(sql).bind(uid).bind(session_id.to_string()))
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn load_auth_state(&self) -> Result<AuthState, StorageError> {
+        let mut connection = self.0.lock().await;
+        let csrf_tokens = connection
+            .fetch_all("SELECT token FROM csrf_tokens")
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+        let unique_id_session = connection
+            .fetch_all("SELECT uid, session_id FROM sessions")
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), SessionId::from(row.get::<String, _>(1))))
+            .collect();
+        Ok(AuthState {
+            csrf_tokens,
+            unique_id_session,
+        })
+    }
+
+    /// Stores the participant holding the contribution slot, or clears it.
+    pub async fn save_active_contributor(
+        &self,
+        active: Option<&ActiveContributor>,
+    ) -> Result<(), StorageError> {
+        let sql = "INSERT INTO active_contributor (id, session_id, token, slot, started_at) \
+                   VALUES (0, ?1, ?2, ?3, ?4)";
+        let mut connection = self.0.lock().await;
+        let mut transaction = connection.begin().await?;
+        transaction
+            .execute("DELETE FROM active_contributor")
+            .await?;
+        if let Some((session_id, session_info, slot)) = active {
+            transaction
+                .execute(
+                    sqlx::query(sql)
+                        .bind(session_id.to_string())
+                        .bind(encode_token(session_info)?)
+                        .bind(slot.as_str())
+                        .bind(to_unix_millis(session_info.last_ping_time)),
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn load_active_contributor(&self) -> Result<Option<ActiveContributor>, StorageError> {
+        let sql = "SELECT session_id, token, slot, started_at FROM active_contributor";
+        let row = self.0.lock().await.fetch_optional(sql).await?;
+        row.map(|row| {
+            let slot = row.get::<String, _>(2);
+            let slot = ContributionSlot::from_name(&slot)
+                .ok_or_else(|| StorageError::InvalidState(format!("unknown slot {slot}")))?;
+            let session_info = SessionInfo {
+                token:                 decode_token(row.get(1))?,
+                last_ping_time:        from_unix_millis(row.get(3)),
+                is_first_ping_attempt: false,
+            };
+            Ok((SessionId::from(row.get::<String, _>(0)), session_info, slot))
+        })
+        .transpose()
+    }
+}
+
+fn encode_token(session_info: &SessionInfo) -> Result<String, StorageError> {
+    serde_json::to_string(&session_info.token)
+        .map_err(|error| StorageError::InvalidState(error.to_string()))
+}
+
+fn decode_token(token: &str) -> Result<IdToken, StorageError> {
+    serde_json::from_str(token).map_err(|error| StorageError::InvalidState(error.to_string()))
+}
+
+/// The wall-clock time of `instant` in unix milliseconds, so it survives a
+/// restart.
+fn to_unix_millis(instant: Instant) -> i64 {
+    let elapsed = i64::try_from(instant.elapsed().as_millis()).unwrap_or(i64::MAX);
+    Utc::now().timestamp_millis().saturating_sub(elapsed)
+}
+
+/// The instant of a wall-clock time in unix milliseconds, as long ago as the
+/// timestamp. Times in the future, or before the monotonic clock started, map
+/// to now.
+fn from_unix_millis(millis: i64) -> Instant {
+    let elapsed = Utc::now().timestamp_millis().saturating_sub(millis);
+    let now = Instant::now();
+    u64::try_from(elapsed)
+        .ok()
+        .and_then(|elapsed| now.checked_sub(Duration::from_millis(elapsed)))
+        .unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{create_test_session_info, test_options};
+
+    #[tokio::test]
+    async fn lobby_roundtrip() {
+        let storage = storage_client(&test_options().storage).await.unwrap();
+        let mut lobby = LobbyState::default();
+        for exp in [3, 1, 2] {
+            let mut session_info = create_test_session_info(exp);
+            session_info.last_ping_time -= Duration::from_secs(exp * 10);
+            session_info.is_first_ping_attempt = exp == 1;
+            lobby.participants.insert(SessionId::new(), session_info);
+        }
+        storage.save_lobby(&lobby).await.unwrap();
+        // saving again replaces the previous snapshot
+        lobby.participants.shift_remove_index(1);
+        storage.save_lobby(&lobby).await.unwrap();
+
+        let loaded = storage.load_lobby().await.unwrap();
+        assert_eq!(
+            loaded.participants.keys().collect::<Vec<_>>(),
+            lobby.participants.keys().collect::<Vec<_>>()
+        );
+        for (session_id, session_info) in &lobby.participants {
+            let restored = &loaded.participants[session_id];
+            assert_eq!(restored.token.exp, session_info.token.exp);
+            assert_eq!(restored.token.sub, session_info.token.sub);
+            assert_eq!(
+                restored.is_first_ping_attempt,
+                session_info.is_first_ping_attempt
+            );
+            let drift = restored
+                .last_ping_time
+                .saturating_duration_since(session_info.last_ping_time)
+                .max(
+                    session_info
+                        .last_ping_time
+                        .saturating_duration_since(restored.last_ping_time),
+                );
+            assert!(drift < Duration::from_secs(1));
+        }
+    }
+
+    #[tokio::test]
+    async fn auth_state_roundtrip() {
+        let storage = storage_client(&test_options().storage).await.unwrap();
+        let mut auth = AuthState::default();
+        auth.csrf_tokens.insert("csrf".to_owned());
+        auth.unique_id_session
+            .insert("uid".to_owned(), SessionId::new());
+        storage.save_auth_state(&auth).await.unwrap();
+
+        let loaded = storage.load_auth_state().await.unwrap();
+        assert_eq!(loaded.csrf_tokens, auth.csrf_tokens);
+        assert_eq!(loaded.unique_id_session, auth.unique_id_session);
+    }
+
+    #[tokio::test]
+    async fn active_contributor_roundtrip() {
+        let storage = storage_client(&test_options().storage).await.unwrap();
+        assert!(storage.load_active_contributor().await.unwrap().is_none());
+
+        let session_id = SessionId::new();
+        let mut session_info = create_test_session_info(100);
+        session_info.last_ping_time -= Duration::from_secs(90);
+        let active = (session_id.clone(), session_info, ContributionSlot::Offline);
+        storage
+            .save_active_contributor(Some(&active))
+            .await
+            .unwrap();
+
+        let (id, restored, slot) = storage.load_active_contributor().await.unwrap().unwrap();
+        assert_eq!(id, session_id);
+        assert_eq!(slot, ContributionSlot::Offline);
+        assert_eq!(restored.token.exp, 100);
+        let elapsed = restored.last_ping_time.elapsed();
+        assert!(elapsed >= Duration::from_secs(89) && elapsed < Duration::from_secs(91));
+
+        storage.save_active_contributor(None).await.unwrap();
+        assert!(storage.load_active_contributor().await.unwrap().is_none());
+    }
 }
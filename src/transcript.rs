@@ -0,0 +1,174 @@
+use kzg_ceremony_crypto::BatchTranscript;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+/// The transcript no longer accepts updates, see [`SharedTranscript::freeze`].
+#[derive(Debug, Error)]
+#[error("transcript is frozen")]
+pub struct Frozen;
+
+/// The current transcript, shared between requests.
+///
+/// Readers get a snapshot that is never blocked by a contribution being
+/// verified. Updates run on a copy on the blocking thread pool and the result
+/// is published in one step, so readers see either the old or the new
+/// transcript.
+#[derive(Clone)]
+pub struct SharedTranscript {
+    current: Arc<RwLock<Arc<BatchTranscript>>>,
+    /// Serializes updates, so none is lost. Holds whether the transcript is
+    /// frozen.
+    update:  Arc<Mutex<bool>>,
+}
+
+impl SharedTranscript {
+    #[must_use]
+    pub fn new(transcript: BatchTranscript) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(transcript))),
+            update:  Arc::new(Mutex::new(false)),
+        }
+    }
+
+    /// The latest published transcript.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an update panicked while publishing.
+    #[must_use]
+    pub fn load(&self) -> Arc<BatchTranscript> {
+        self.current
+            .read()
+            .expect("transcript lock poisoned")
+            .clone()
+    }
+
+    /// Applies `update` to a copy of the transcript on the blocking thread
+    /// pool and publishes the copy if it returns `Ok`. Returns the published
+    /// transcript.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `update`, in which case nothing is published, or
+    /// [`Frozen`] if the transcript is frozen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `update` panics.
+    pub async fn update<E, F>(&self, update: F) -> Result<Arc<BatchTranscript>, E>
+    where
+        E: From<Frozen> + Send + 'static,
+        F: FnOnce(&mut BatchTranscript) -> Result<(), E> + Send + 'static,
+    {
+        let frozen = self.update.lock().await;
+        if *frozen {
+            return Err(Frozen.into());
+        }
+        let mut transcript = BatchTranscript::clone(&self.load());
+        let transcript = tokio::task::spawn_blocking(move || {
+            update(&mut transcript)?;
+            Ok::<_, E>(Arc::new(transcript))
+        })
+        .await
+        .expect("transcript update panicked")?;
+        *self.current.write().expect("transcript lock poisoned") = transcript.clone();
+        Ok(transcript)
+    }
+
+    /// Rejects all further updates. Waits for an update in progress, so the
+    /// transcript does not change once this returns.
+    pub async fn freeze(&self) {
+        *self.update.lock().await = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{invalid_contribution, test_transcript, valid_contribution},
+        Engine,
+    };
+    use std::time::Duration;
+
+    #[derive(Debug)]
+    enum UpdateError {
+        Invalid,
+        Frozen,
+    }
+
+    impl From<Frozen> for UpdateError {
+        fn from(_: Frozen) -> Self {
+            Self::Frozen
+        }
+    }
+
+    #[tokio::test]
+    async fn publishes_successful_updates() {
+        let shared = SharedTranscript::new(test_transcript());
+        let before = shared.load();
+
+        let contribution = invalid_contribution(&before, 1);
+        let result = shared
+            .update(move |transcript| {
+                transcript
+                    .verify_add::<Engine>(contribution)
+                    .map_err(|_| UpdateError::Invalid)
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(Arc::ptr_eq(&shared.load(), &before));
+
+        let contribution = valid_contribution(&before, 1);
+        let published = shared
+            .update(move |transcript| {
+                transcript
+                    .verify_add::<Engine>(contribution)
+                    .map_err(|_| UpdateError::Invalid)
+            })
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&shared.load(), &published));
+        assert_eq!(published.transcripts[0].num_contributions(), 1);
+        // Earlier snapshots are unchanged
+        assert_eq!(before.transcripts[0].num_contributions(), 0);
+    }
+
+    #[tokio::test]
+    async fn freeze_waits_for_update() {
+        let shared = SharedTranscript::new(test_transcript());
+        let contribution = valid_contribution(&shared.load(), 1);
+
+        let (started, running) = tokio::sync::oneshot::channel();
+        let update = tokio::spawn({
+            let shared = shared.clone();
+            async move {
+                shared
+                    .update(move |transcript| {
+                        started.send(()).unwrap();
+                        std::thread::sleep(Duration::from_millis(50));
+                        transcript
+                            .verify_add::<Engine>(contribution)
+                            .map_err(|_| UpdateError::Invalid)
+                    })
+                    .await
+            }
+        });
+        running.await.unwrap();
+        shared.freeze().await;
+        assert_eq!(shared.load().transcripts[0].num_contributions(), 1);
+        update.await.unwrap().unwrap();
+
+        let contribution = valid_contribution(&shared.load(), 2);
+        let result = shared
+            .update(move |transcript| {
+                transcript
+                    .verify_add::<Engine>(contribution)
+                    .map_err(|_| UpdateError::Invalid)
+            })
+            .await;
+        assert!(matches!(result, Err(UpdateError::Frozen)));
+        assert_eq!(shared.load().transcripts[0].num_contributions(), 1);
+    }
+}
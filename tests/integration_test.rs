@@ -3,8 +3,11 @@
 use crate::mock_auth_service::{AuthState, GhUser};
 use clap::Parser;
 use http::StatusCode;
-use kzg_ceremony_crypto::{Arkworks, BatchContribution, BatchTranscript};
-use kzg_ceremony_sequencer::{io::read_json_file, start_server, Options};
+use kzg_ceremony_client::{CheckIn, Client, ClientError};
+use kzg_ceremony_crypto::{Arkworks, BatchContribution, BatchTranscript, BinaryFormat};
+use kzg_ceremony_sequencer::{
+    format::BINARY_MEDIA_TYPE, io::read_json_file, start_server, Options,
+};
 use serde_json::Value;
 use std::collections::HashMap;
 use tempfile::{tempdir, TempDir};
@@ -36,6 +39,8 @@ fn test_options() -> Options {
         "INVALID",
         "--database-url",
         "sqlite::memory:",
+        "--admin-key",
+        "test-admin-key",
     ];
     Options::parse_from(args)
 }
@@ -65,11 +70,14 @@ async fn server_lock() -> &'static Mutex<()> {
 }
 
 async fn run_test_harness() -> Harness {
+    run_test_harness_with(test_options()).await
+}
+
+async fn run_test_harness_with(mut options: Options) -> Harness {
     let lock = server_lock().await.lock().await;
     let temp_dir = tempdir().unwrap();
     let transcript = temp_dir.path().join("transcript.json");
     let transcript_wip = temp_dir.path().join("transcript.json.next");
-    let mut options = test_options();
     options.transcript_file = transcript;
     options.transcript_in_progress_file = transcript_wip;
     let server_options = options.clone();
@@ -262,13 +270,13 @@ async fn test_contribution_happy_path() {
 
     // not only is it unguessable, it is also 32 characters long and we depend on
     // this.
-    let entropy = "such an unguessable string, wow!"
+    let entropy: [u8; 32] = "such an unguessable string, wow!"
         .bytes()
         .collect::<Vec<u8>>()
         .try_into()
         .unwrap();
     contribution
-        .add_entropy::<Arkworks>(entropy)
+        .add_entropy::<Arkworks>(&entropy.into())
         .expect("Adding entropy must be possible");
 
     contribute(&harness, &http_client, &session_id, &contribution).await;
@@ -307,3 +315,251 @@ async fn test_contribution_happy_path() {
         "the pubkeys recorded in transcript must be the ones submitted"
     )
 }
+
+#[tokio::test]
+async fn test_binary_contribution_happy_path() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+
+    let session_id = login_gh_user(&harness, &http_client, "kustosz".to_string()).await;
+
+    let response = http_client
+        .post(harness.options.server.join("lobby/try_contribute").unwrap())
+        .header("Authorization", format!("Bearer {session_id}"))
+        .header("Accept", BINARY_MEDIA_TYPE)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Content-Type"], BINARY_MEDIA_TYPE);
+    let mut contribution = BatchContribution::from_binary(&response.bytes().await.unwrap())
+        .expect("Response must be a binary contribution");
+
+    contribution
+        .add_entropy::<Arkworks>(&[1; 32].into())
+        .expect("Adding entropy must be possible");
+
+    let response = http_client
+        .post(harness.options.server.join("contribute").unwrap())
+        .header("Authorization", format!("Bearer {session_id}"))
+        .header("Content-Type", BINARY_MEDIA_TYPE)
+        .body(contribution.to_binary())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = http_client
+        .get(harness.options.server.join("info/current_state").unwrap())
+        .header("Accept", BINARY_MEDIA_TYPE)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let transcript = BatchTranscript::from_binary(&response.bytes().await.unwrap())
+        .expect("Response must be a binary transcript");
+    assert_eq!(
+        transcript,
+        read_json_file::<BatchTranscript>(harness.options.transcript_file.clone()).await
+    );
+    assert_eq!(transcript.transcripts[0].num_contributions(), 1);
+}
+
+#[tokio::test]
+async fn test_client_contribution_happy_path() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let client = Client::new(
+        harness.options.server.clone(),
+        harness.options.lobby.lobby_checkin_frequency,
+    );
+
+    let links = client.auth_links().await.expect("Must get auth links");
+    assert!(links.github_auth_url.contains("state="));
+
+    let session_id = login_gh_user(&harness, &http_client, "kustosz".to_string()).await;
+    let other_session_id = login_gh_user(&harness, &http_client, "other".to_string()).await;
+    assert!(matches!(
+        client.try_contribute(&other_session_id).await,
+        Ok(CheckIn::Waiting { position: 2, .. })
+    ));
+
+    let receipt = client
+        .contribute(&session_id, [2; 32].into())
+        .await
+        .expect("Contribution must be accepted");
+    assert_eq!(receipt.receipt["witness"].as_array().unwrap().len(), 3);
+
+    let transcript =
+        read_json_file::<BatchTranscript>(harness.options.transcript_file.clone()).await;
+    let transcript_pubkeys = transcript
+        .transcripts
+        .iter()
+        .map(|t| serde_json::to_value(t.witness.pubkeys.last().unwrap()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        receipt.receipt["witness"].as_array().unwrap(),
+        &transcript_pubkeys
+    );
+
+    let error = client
+        .try_contribute("unknown")
+        .await
+        .expect_err("Unknown sessions must be rejected");
+    assert!(matches!(
+        error,
+        ClientError::Sequencer(StatusCode::BAD_REQUEST, message) if message == "unknown session id"
+    ));
+}
+
+#[tokio::test]
+async fn test_lobby_stream() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+
+    let response = http_client
+        .get(harness.options.server.join("lobby/stream").unwrap())
+        .header("Authorization", "Bearer unknown")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let session_id = login_gh_user(&harness, &http_client, "kustosz".to_string()).await;
+    let mut response = http_client
+        .get(harness.options.server.join("lobby/stream").unwrap())
+        .header("Authorization", format!("Bearer {session_id}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Content-Type"], "text/event-stream");
+
+    // Alone in the lobby, the first event is our turn.
+    let mut body = Vec::new();
+    while !body.ends_with(b"\n\n") {
+        body.extend_from_slice(&response.chunk().await.unwrap().unwrap());
+    }
+    let body = String::from_utf8(body).unwrap();
+    assert!(body.starts_with("event:your_turn\n"), "{body}");
+    let data: Value = serde_json::from_str(
+        body.lines()
+            .find_map(|line| line.strip_prefix("data:"))
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(data["deadline_seconds"], 180);
+    let contribution: BatchContribution =
+        serde_json::from_value(data["contribution"].clone()).unwrap();
+    assert_eq!(contribution.contributions.len(), 3);
+}
+
+#[tokio::test]
+async fn test_admin_api() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let admin_url = |path: &str| harness.options.server.join(path).unwrap();
+
+    let response = http_client
+        .get(admin_url("admin/lobby"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = http_client
+        .get(admin_url("admin/lobby"))
+        .bearer_auth("wrong-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let session_id = login_gh_user(&harness, &http_client, "kicked user".to_string()).await;
+    let lobby = http_client
+        .get(admin_url("admin/lobby"))
+        .bearer_auth("test-admin-key")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(lobby["participants"][0]["session_id"], session_id.as_str());
+    assert_eq!(lobby["admissions_paused"], false);
+
+    let response = http_client
+        .delete(admin_url(&format!("admin/lobby/{session_id}")))
+        .bearer_auth("test-admin-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = http_client
+        .post(harness.options.server.join("lobby/try_contribute").unwrap())
+        .bearer_auth(&session_id)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = http_client
+        .post(admin_url("admin/pause"))
+        .bearer_auth("test-admin-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = http_client
+        .get(admin_url("auth/request_link"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let actions = http_client
+        .get(admin_url("admin/actions"))
+        .bearer_auth("test-admin-key")
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(actions["actions"][0]["action"], "kick");
+    assert_eq!(actions["actions"][1]["action"], "pause");
+}
+
+#[tokio::test]
+async fn test_ceremony_not_started() {
+    let mut options = test_options();
+    options.ceremony.ceremony_start = Some("2100-01-01T00:00:00Z".parse().unwrap());
+    let harness = run_test_harness_with(options).await;
+    let http_client = reqwest::Client::new();
+
+    let status = http_client
+        .get(harness.options.server.join("info/status").unwrap())
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(status["phase"], "pending");
+
+    let response = http_client
+        .get(harness.options.server.join("auth/request_link").unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(body["phase"], "pending");
+
+    let response = http_client
+        .post(harness.options.server.join("lobby/try_contribute").unwrap())
+        .bearer_auth("unknown")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}